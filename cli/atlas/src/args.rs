@@ -1,6 +1,7 @@
 use clap:: {
     Parser,
     Subcommand,
+    ValueEnum,
 };
 
 #[derive(Parser, Debug)]
@@ -34,6 +35,145 @@ pub enum Command {
         #[arg(short = 'o', long = "output", value_name = "OUTPUT")]
         output: String,
     },
+    Run {
+        /// input assembly sources (.asm) or object files (.o)
+        #[arg(value_name = "INPUTS", required = true)]
+        inputs: Vec<String>,
+
+        /// stop after this many instructions if the program has not halted
+        #[arg(long = "max-steps", value_name = "N", default_value_t = 1_000_000)]
+        max_steps: u64,
+
+        /// report stores to an address or range (e.g. 0x80..0x84, frame..frame+4)
+        #[arg(long = "watch", value_name = "RANGE")]
+        watch: Vec<String>,
+
+        /// report loads from an address or range
+        #[arg(long = "rwatch", value_name = "RANGE")]
+        rwatch: Vec<String>,
+
+        /// report loads from and stores to an address or range
+        #[arg(long = "awatch", value_name = "RANGE")]
+        awatch: Vec<String>,
+    },
+    Sim {
+        /// input assembly sources (.asm) or object files (.o)
+        #[arg(value_name = "INPUTS", required = true)]
+        inputs: Vec<String>,
+
+        /// stop after this many instructions if the program has not halted
+        #[arg(long = "max-steps", value_name = "N", default_value_t = 1_000_000)]
+        max_steps: u64,
+
+        /// simulate the L1/L2 caches
+        #[arg(long = "cache")]
+        cache: bool,
+
+        /// attach the MMU (registers at 0xff00) and 16 MB of physical memory
+        #[arg(long = "mmu")]
+        mmu: bool,
+
+        /// wait for a GDB remote protocol client on ADDR (e.g. 127.0.0.1:1234)
+        #[arg(long = "gdb", value_name = "ADDR")]
+        gdb: Option<String>,
+
+        /// write a per-instruction execution trace to FILE
+        #[arg(long = "trace", value_name = "FILE", conflicts_with = "gdb")]
+        trace: Option<String>,
+
+        /// format of the execution trace
+        #[arg(long = "trace-format", value_enum, default_value_t = TraceFormat::Text)]
+        trace_format: TraceFormat,
+
+        /// print instructions and cycles per function and per label
+        #[arg(long = "profile", conflicts_with_all = ["gdb", "trace"])]
+        profile: bool,
+
+        /// with --profile, also write the call stacks in folded format to FILE
+        #[arg(long = "profile-folded", value_name = "FILE", requires = "profile")]
+        profile_folded: Option<String>,
+
+        /// record history, then rewind to the last instruction started at or before CYCLE;
+        /// only about the last 100000 instructions are kept, older cycles rewind to the oldest one
+        #[arg(long = "rewind", value_name = "CYCLE", conflicts_with = "gdb")]
+        rewind: Option<u64>,
+
+        /// restore the machine from a snapshot written by --save before running
+        #[arg(long = "resume", value_name = "FILE")]
+        resume: Option<String>,
+
+        /// write a snapshot of the machine to FILE when the run stops
+        #[arg(long = "save", value_name = "FILE")]
+        save: Option<String>,
+    },
+    Emu {
+        /// input assembly sources (.asm) or object files (.o)
+        #[arg(value_name = "INPUTS", required = true)]
+        inputs: Vec<String>,
+
+        /// stop after this many steps if the program has not halted
+        #[arg(long = "max-steps", value_name = "N", default_value_t = 1_000_000)]
+        max_steps: u64,
+
+        /// feed the UART from this file instead of stdin
+        #[arg(long = "input", value_name = "FILE")]
+        input: Option<String>,
+
+        /// write UART output to this file instead of stdout
+        #[arg(long = "output", value_name = "FILE")]
+        output: Option<String>,
+
+        /// never read stdin and print nothing but UART output
+        #[arg(long = "headless")]
+        headless: bool,
+
+        /// restore the machine from a snapshot written by --save before running
+        #[arg(long = "resume", value_name = "FILE")]
+        resume: Option<String>,
+
+        /// write a snapshot of the machine to FILE when the run stops
+        #[arg(long = "save", value_name = "FILE")]
+        save: Option<String>,
+    },
+    Dbg {
+        /// input assembly sources (.asm) or object files (.o)
+        #[arg(value_name = "INPUTS", required = true)]
+        inputs: Vec<String>,
+
+        /// stop `continue` and `next` after this many instructions
+        #[arg(long = "max-steps", value_name = "N", default_value_t = 1_000_000)]
+        max_steps: u64,
+    },
+    Test {
+        /// test programs or directories to search for them (default: test);
+        /// with --golden, the inputs of the traced program
+        #[arg(value_name = "INPUTS")]
+        inputs: Vec<String>,
+
+        /// golden trace to compare against (.jsonl for JSON lines, text otherwise)
+        #[arg(long = "golden", value_name = "FILE")]
+        golden: Option<String>,
+
+        /// overwrite the golden trace with this run's trace
+        #[arg(long = "bless", requires = "golden")]
+        bless: bool,
+
+        /// step budget for programs without an `expect steps <= N` comment
+        #[arg(long = "max-steps", value_name = "N", default_value_t = 1_000_000)]
+        max_steps: u64,
+
+        /// write an lcov file and annotated listings of instruction and
+        /// branch coverage to DIR (default: coverage)
+        #[arg(
+            long = "coverage",
+            value_name = "DIR",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "coverage",
+            conflicts_with = "golden"
+        )]
+        coverage: Option<String>,
+    },
     Inspect {
         /// input file to inspect
         #[arg(value_name = "INPUT")]
@@ -43,4 +183,23 @@ pub enum Command {
         #[arg(short = 'f', long = "format")]
         format: Option<String>,
     },
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    /// one aligned line per instruction
+    Text,
+    /// one JSON object per line
+    Json,
+}
+
+impl TraceFormat {
+    /// JSON lines for `.jsonl` / `.json` files, text otherwise.
+    pub fn for_path(path: &str) -> Self {
+        if path.ends_with(".jsonl") || path.ends_with(".json") {
+            TraceFormat::Json
+        } else {
+            TraceFormat::Text
+        }
+    }
+}
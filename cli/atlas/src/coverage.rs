@@ -0,0 +1,238 @@
+//! Source-level coverage reports for `atlas test --coverage`.
+//!
+//! Every test program is linked on its own, so the simulator's per-address
+//! [`Coverage`] is first mapped back to source lines through the image's
+//! line table. Results are merged per source file: a routine linked into
+//! several test programs accumulates the hits of all of them. Labels at
+//! instructions become function records.
+//!
+//! The report is written as an lcov tracefile (`lcov.info`) and, for every
+//! source file, a gcov-style annotated listing (`<file>.cov`).
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use atlas_isa::{BranchCond, ParsedInstruction};
+use atlas_linker::LinkedImage;
+use atlas_sim::{BranchCoverage, Coverage};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileCoverage {
+    /// Executions of every line with code.
+    pub lines: BTreeMap<u32, u64>,
+    /// Outcomes of the conditional branch on a line; `None` while the branch
+    /// has never executed.
+    pub branches: BTreeMap<u32, Option<BranchCoverage>>,
+    /// Label → line of its instruction and executions of it.
+    pub functions: BTreeMap<String, (u32, u64)>,
+}
+
+impl FileCoverage {
+    /// Lines executed and lines with code.
+    pub fn line_summary(&self) -> (usize, usize) {
+        (self.lines.values().filter(|&&count| count > 0).count(), self.lines.len())
+    }
+
+    /// Branch outcomes seen and possible outcomes (two per branch).
+    pub fn branch_summary(&self) -> (usize, usize) {
+        let hit = self
+            .branches
+            .values()
+            .flatten()
+            .map(|b| (b.taken > 0) as usize + (b.not_taken > 0) as usize)
+            .sum();
+        (hit, 2 * self.branches.len())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceCoverage {
+    /// Coverage by source file, as named in the line tables.
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+impl SourceCoverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge the coverage of one run of `image`. Instructions without line
+    /// information are left out. A line of several instructions, such as a
+    /// relaxed branch, counts as often as its most executed instruction.
+    pub fn add(&mut self, image: &LinkedImage, coverage: &Coverage) {
+        let mut line_counts: BTreeMap<(&str, u32), u64> = BTreeMap::new();
+        for (&address, source) in &image.lines {
+            let file = self.files.entry(source.file.clone()).or_default();
+            let count = coverage.executed.get(&address).copied().unwrap_or(0);
+            let line_count = line_counts.entry((source.file.as_str(), source.line)).or_default();
+            *line_count = (*line_count).max(count);
+
+            if is_conditional_branch(image, address) {
+                let branch = file.branches.entry(source.line).or_default();
+                if let Some(outcome) = coverage.branches.get(&address) {
+                    let merged = branch.get_or_insert_with(BranchCoverage::default);
+                    merged.taken += outcome.taken;
+                    merged.not_taken += outcome.not_taken;
+                }
+            }
+            if let Some(name) = image.symbols.get(&address) {
+                let function = file.functions.entry(name.clone()).or_insert((source.line, 0));
+                function.1 += count;
+            }
+        }
+        for ((path, line), count) in line_counts {
+            *self.files.entry(path.to_string()).or_default().lines.entry(line).or_default() += count;
+        }
+    }
+
+    /// Render the report as an lcov tracefile.
+    pub fn to_lcov(&self) -> String {
+        let mut out = String::new();
+        for (path, file) in &self.files {
+            out.push_str("TN:\n");
+            out.push_str(&format!("SF:{}\n", path));
+            for (name, (line, _)) in &file.functions {
+                out.push_str(&format!("FN:{},{}\n", line, name));
+            }
+            for (name, (_, count)) in &file.functions {
+                out.push_str(&format!("FNDA:{},{}\n", count, name));
+            }
+            let functions_hit = file.functions.values().filter(|(_, count)| *count > 0).count();
+            out.push_str(&format!("FNF:{}\nFNH:{}\n", file.functions.len(), functions_hit));
+            for (line, branch) in &file.branches {
+                let (taken, not_taken) = match branch {
+                    Some(b) => (b.taken.to_string(), b.not_taken.to_string()),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                out.push_str(&format!("BRDA:{},0,0,{}\n", line, taken));
+                out.push_str(&format!("BRDA:{},0,1,{}\n", line, not_taken));
+            }
+            let (branches_hit, branches) = file.branch_summary();
+            out.push_str(&format!("BRF:{}\nBRH:{}\n", branches, branches_hit));
+            for (line, count) in &file.lines {
+                out.push_str(&format!("DA:{},{}\n", line, count));
+            }
+            let (lines_hit, lines) = file.line_summary();
+            out.push_str(&format!("LF:{}\nLH:{}\n", lines, lines_hit));
+            out.push_str("end_of_record\n");
+        }
+        out
+    }
+
+    /// Annotate `source`, the text of `path`, with execution counts: `-` for
+    /// lines without code, `#####` for lines never executed, and the
+    /// outcomes below every conditional branch.
+    pub fn annotate(&self, path: &str, source: &str) -> String {
+        let file = self.files.get(path);
+        let mut out = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let line = index as u32 + 1;
+            let count = match file.and_then(|f| f.lines.get(&line)) {
+                Some(0) => "#####".to_string(),
+                Some(count) => count.to_string(),
+                None => "-".to_string(),
+            };
+            out.push_str(&format!("{:>9}:{:>5}:{}\n", count, line, text));
+            match file.and_then(|f| f.branches.get(&line)) {
+                Some(Some(b)) => {
+                    out.push_str(&format!("{:>16}branch taken {}, not taken {}\n", "", b.taken, b.not_taken));
+                }
+                Some(None) => out.push_str(&format!("{:>16}branch never executed\n", "")),
+                None => {}
+            }
+        }
+        out
+    }
+
+    /// Write `lcov.info` and an annotated listing of every readable source
+    /// file into `dir`. Returns the paths written.
+    pub fn write(&self, dir: &Path) -> Result<Vec<PathBuf>, String> {
+        let create = |path: &Path| {
+            std::fs::create_dir_all(path.parent().unwrap_or(dir))
+                .map_err(|e| format!("Failed to create directory for '{}': {}", path.display(), e))
+        };
+        let write = |path: &Path, contents: String| {
+            create(path)?;
+            std::fs::write(path, contents).map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+        };
+
+        let lcov = dir.join("lcov.info");
+        write(&lcov, self.to_lcov())?;
+        let mut written = vec![lcov];
+        for path in self.files.keys() {
+            let Ok(source) = std::fs::read_to_string(path) else {
+                continue;
+            };
+            let listing = dir.join(listing_name(path));
+            write(&listing, self.annotate(path, &source))?;
+            written.push(listing);
+        }
+        Ok(written)
+    }
+}
+
+/// Whether the instruction at `address` in `image` is a conditional branch.
+fn is_conditional_branch(image: &LinkedImage, address: u16) -> bool {
+    let Some(bytes) = image.data.get(address as usize..address as usize + 2) else {
+        return false;
+    };
+    match ParsedInstruction::decode(u16::from_be_bytes([bytes[0], bytes[1]])) {
+        Ok(ParsedInstruction::BI { cond, .. } | ParsedInstruction::BR { cond, .. }) => {
+            cond != BranchCond::Unconditional
+        }
+        _ => false,
+    }
+}
+
+/// Listing path for a source file, relative to the report directory:
+/// `test/math.asm` becomes `test/math.asm.cov`. Root and parent components
+/// are dropped so listings stay inside the directory.
+fn listing_name(path: &str) -> PathBuf {
+    let mut name: PathBuf = Path::new(path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect();
+    let file_name = format!("{}.cov", name.file_name().unwrap_or_default().to_string_lossy());
+    name.set_file_name(file_name);
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_coverage_to_lines_and_writes_lcov() {
+        let source = "start:\n    ldi r1, 0x02\nloop:\n    subi r1, 1\n    bne loop\n    halt\nunused:\n    nop\n";
+        let obj = atlas_assembler::assemble_source(source, "loop.asm").unwrap();
+        let image = atlas_linker::link_objects(&[("loop.asm".to_string(), obj)]).unwrap();
+        let mut sim = atlas_sim::Simulator::new();
+        sim.load(&image.data, 0).unwrap();
+        sim.record_coverage();
+        sim.run(100).unwrap();
+
+        let mut report = SourceCoverage::new();
+        report.add(&image, sim.coverage().unwrap());
+        report.add(&image, sim.coverage().unwrap());
+        let file = &report.files["loop.asm"];
+        assert_eq!(file.lines.get(&4), Some(&4));
+        assert_eq!(file.lines.get(&8), Some(&0));
+        assert_eq!(file.line_summary(), (4, 5));
+        assert_eq!(file.branch_summary(), (2, 2));
+        assert_eq!(file.functions["unused"], (8, 0));
+
+        let lcov = report.to_lcov();
+        assert!(lcov.contains("SF:loop.asm\nFN:4,loop\nFN:2,start\nFN:8,unused\n"));
+        assert!(lcov.contains("BRDA:5,0,0,2\nBRDA:5,0,1,2\n"));
+        assert!(lcov.contains("DA:8,0\nLF:5\nLH:4\nend_of_record\n"));
+
+        let listing = report.annotate("loop.asm", source);
+        assert!(listing.contains("        4:    4:    subi r1, 1\n"));
+        assert!(listing.contains("branch taken 2, not taken 2\n"));
+        assert!(listing.contains("    #####:    8:    nop\n"));
+        assert_eq!(listing_name("../test/math.asm"), PathBuf::from("test/math.asm.cov"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Expectations declared in assembly comments, checked by `atlas test`.
+//!
+//! Any source with at least one `expect` comment is a test program:
+//!
+//! ```text
+//! ; expect r9 == 6 at halt
+//! ; expect mem[0x80] == 0xaa
+//! ; expect sp != 0
+//! ; expect steps <= 500
+//! ; link math.asm io.asm
+//! ```
+//!
+//! Registers (`r0`–`r15`, `sr`, and the `tr`, `sp` and `pc` pairs) and
+//! memory bytes are compared once the program halts. `steps <= N` replaces
+//! the default step budget, and `link` names further inputs, relative to the
+//! test program, to link after it.
+
+use std::path::{Path, PathBuf};
+
+use atlas_sim::Simulator;
+use atlas_sim::cpu::{PC_HIGH, PC_LOW, SP_HIGH, SP_LOW, TR_HIGH, TR_LOW};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Register(u8),
+    Pair(&'static str, u8, u8),
+    Status,
+    Memory(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectation {
+    /// 1-based source line of the comment.
+    pub line: usize,
+    pub target: Target,
+    /// `==` when true, `!=` otherwise.
+    pub equal: bool,
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestProgram {
+    pub path: PathBuf,
+    /// Further inputs linked after the test program.
+    pub links: Vec<PathBuf>,
+    pub expectations: Vec<Expectation>,
+    /// Step budget from `expect steps <= N`.
+    pub max_steps: Option<u64>,
+}
+
+impl TestProgram {
+    /// Collect the expectations in `source`. Returns `Ok(None)` if it has
+    /// none, so it is not a test program.
+    pub fn parse(path: &Path, source: &str) -> Result<Option<TestProgram>, String> {
+        let mut program = TestProgram {
+            path: path.to_path_buf(),
+            links: Vec::new(),
+            expectations: Vec::new(),
+            max_steps: None,
+        };
+        let mut is_test = false;
+        for (index, text) in source.lines().enumerate() {
+            let Some((_, comment)) = text.split_once(';') else {
+                continue;
+            };
+            let comment = comment.trim_start_matches(';').trim();
+            let error = |what: &str| format!("{}:{}: {} '{}'", path.display(), index + 1, what, comment);
+            if let Some(expectation) = comment.strip_prefix("expect ") {
+                is_test = true;
+                let expectation = expectation.trim();
+                if let Some(limit) = expectation.strip_prefix("steps") {
+                    let limit = limit.trim_start().strip_prefix("<=").ok_or_else(|| error("invalid step budget"))?;
+                    program.max_steps = Some(parse_number(limit.trim()).ok_or_else(|| error("invalid step budget"))? as u64);
+                } else {
+                    let parsed = parse_expectation(index + 1, expectation).ok_or_else(|| error("invalid expectation"))?;
+                    program.expectations.push(parsed);
+                }
+            } else if let Some(links) = comment.strip_prefix("link ") {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                program.links.extend(links.split_whitespace().map(|link| dir.join(link)));
+            }
+        }
+        Ok(is_test.then_some(program))
+    }
+}
+
+/// `<target> (==|!=) <value> [at halt]`
+fn parse_expectation(line: usize, text: &str) -> Option<Expectation> {
+    let text = text.strip_suffix("at halt").unwrap_or(text).trim();
+    let (target, equal, value) = if let Some((target, value)) = text.split_once("==") {
+        (target, true, value)
+    } else {
+        let (target, value) = text.split_once("!=")?;
+        (target, false, value)
+    };
+    Some(Expectation {
+        line,
+        target: parse_target(target.trim())?,
+        equal,
+        value: u16::try_from(parse_number(value.trim())?).ok()?,
+    })
+}
+
+fn parse_target(text: &str) -> Option<Target> {
+    match text {
+        "sr" => return Some(Target::Status),
+        "tr" => return Some(Target::Pair("tr", TR_HIGH, TR_LOW)),
+        "sp" => return Some(Target::Pair("sp", SP_HIGH, SP_LOW)),
+        "pc" => return Some(Target::Pair("pc", PC_HIGH, PC_LOW)),
+        _ => {}
+    }
+    if let Some(address) = text.strip_prefix("mem[").and_then(|rest| rest.strip_suffix(']')) {
+        return Some(Target::Memory(u16::try_from(parse_number(address.trim())?).ok()?));
+    }
+    let register: u8 = text.strip_prefix('r')?.parse().ok()?;
+    (register < 16).then_some(Target::Register(register))
+}
+
+/// Parse a `0x`/`0b`-prefixed or decimal number.
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+impl Expectation {
+    /// Compare against the halted simulator, describing any failure.
+    pub fn check(&self, sim: &Simulator) -> Result<(), String> {
+        let (name, actual) = match self.target {
+            Target::Register(r) => (format!("r{}", r), Some(sim.cpu.reg(r) as u16)),
+            Target::Pair(name, high, low) => (name.to_string(), Some(sim.cpu.pair(high, low))),
+            Target::Status => ("sr".to_string(), Some(sim.cpu.sr.0 as u16)),
+            Target::Memory(address) => (format!("mem[0x{:04x}]", address), sim.peek(address).map(u16::from)),
+        };
+        let operator = if self.equal { "==" } else { "!=" };
+        match actual {
+            Some(actual) if (actual == self.value) == self.equal => Ok(()),
+            Some(actual) => Err(format!(
+                "line {}: expected {} {} 0x{:02x}, found 0x{:02x}",
+                self.line, name, operator, self.value, actual
+            )),
+            None => Err(format!("line {}: {} is not mapped", self.line, name)),
+        }
+    }
+}
+
+/// Every `.asm` file in `paths`, searching directories recursively, in
+/// path order.
+pub fn discover(paths: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut found = Vec::new();
+    for path in paths {
+        collect(Path::new(path), &mut found)?;
+    }
+    found.sort();
+    found.dedup();
+    Ok(found)
+}
+
+fn collect(path: &Path, found: &mut Vec<PathBuf>) -> Result<(), String> {
+    if path.is_dir() {
+        let entries = std::fs::read_dir(path)
+            .map_err(|e| format!("Failed to read directory '{}': {}", path.display(), e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read directory '{}': {}", path.display(), e))?;
+            collect(&entry.path(), found)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == "asm") {
+        found.push(path.to_path_buf());
+    } else if !path.exists() {
+        return Err(format!("No such file or directory '{}'", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_expectations_budget_and_links() {
+        let source = "
+            ; expect r9 == 6 at halt
+            ; expect mem[0x80] != 0x35
+            ; expect steps <= 500
+            ; link math.asm io.asm
+            halt        ; expect sp == 0xf000
+            ; expected to be ignored
+        ";
+        let program = TestProgram::parse(Path::new("test/main.asm"), source).unwrap().unwrap();
+        let targets: Vec<(Target, bool, u16)> =
+            program.expectations.iter().map(|e| (e.target, e.equal, e.value)).collect();
+        assert_eq!(
+            targets,
+            [
+                (Target::Register(9), true, 6),
+                (Target::Memory(0x80), false, 0x35),
+                (Target::Pair("sp", SP_HIGH, SP_LOW), true, 0xf000),
+            ]
+        );
+        assert_eq!(program.expectations[2].line, 6);
+        assert_eq!(program.max_steps, Some(500));
+        assert_eq!(program.links, [PathBuf::from("test/math.asm"), PathBuf::from("test/io.asm")]);
+
+        assert_eq!(TestProgram::parse(Path::new("lib.asm"), "; a library\nnop\n"), Ok(None));
+        assert!(TestProgram::parse(Path::new("bad.asm"), "; expect r16 == 1").is_err());
+    }
+}
@@ -1,12 +1,29 @@
 pub mod args;
+pub mod coverage;
+pub mod expect;
+pub mod profile;
+pub mod report;
 
 use args::Arguments;
 use clap::Parser;
 
-use crate::args::Command;
-use atlas_files::{ObjectFile, FileFormat};
-use atlas_inspect::{inspect_obj, disassemble, build_label_map, print_asm_summary, print_link_summary};
+use crate::args::{Command, TraceFormat};
+use crate::coverage::SourceCoverage;
+use crate::expect::TestProgram;
+use crate::profile::Profiler;
+use crate::report::{format_trace_json, format_trace_text, print_cache_stats, print_run_stats};
+use atlas_dbg::Debugger;
+use atlas_dbg::debugger::{format_watch_hit, resolve_range};
+use atlas_files::{ObjectFile, FileFormat, Snapshot, SourceLine};
+use atlas_inspect::{inspect_obj, disassemble, build_label_map, build_line_map, print_asm_summary, print_link_summary, print_registers, print_run_summary, print_test_outcome, print_test_start, print_test_summary, print_trace_check, print_coverage_summary, print_profile, CoverageSummary, ProfileRow, TraceMismatch};
+use atlas_sim::history::DEFAULT_SNAPSHOT_INTERVAL;
+use atlas_sim::timer::{TIMER_BASE, TIMER_IRQ_LINE, TIMER_SIZE};
+use atlas_sim::uart::{UART_BASE, UART_IRQ_LINE, UART_SIZE};
+use atlas_sim::{CacheHierarchy, ExitReason, InterruptController, Memory, Mmu, SimError, Simulator, SystemBus, SystemControl, Timer, TraceEntry, Uart, WatchKind, Watchpoint};
 use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
 
 fn main() {
     let args = Arguments::parse();
@@ -22,10 +39,11 @@ fn main() {
                             println!();
                             inspect_obj(&obj);
                             let labels = build_label_map(&obj);
+                            let lines = build_line_map(&obj);
                             for sec in &obj.sections {
                                 if sec.name == ".text" {
                                     println!();
-                                    disassemble(&sec.data, &labels);
+                                    disassemble(&sec.data, &labels, &lines);
                                 }
                             }
                             println!();
@@ -44,6 +62,7 @@ fn main() {
             if res.is_ok() {
                 // Build a combined label map from all input object files
                 let mut labels = BTreeMap::new();
+                let mut lines = BTreeMap::new();
                 let mut text_base: u32 = 0;
                 for inp in &inputs {
                     if let Ok(obj) = ObjectFile::from_file(inp) {
@@ -56,6 +75,11 @@ fn main() {
                                 }
                             }
                         }
+                        for entry in obj.line_table().map(|t| t.entries).unwrap_or_default() {
+                            if entry.section == ".text" {
+                                lines.insert((text_base + entry.offset) as u16, entry.source);
+                            }
+                        }
                         for sec in &obj.sections {
                             if sec.name == ".text" {
                                 text_base += sec.data.len() as u32;
@@ -73,13 +97,43 @@ fn main() {
 
                 if args.verbose {
                     println!();
-                    disassemble(&data, &labels);
+                    disassemble(&data, &labels, &lines);
                     println!();
                 }
                 print_link_summary(&inputs, &output, data.len());
             }
             res
         },
+        Command::Run { inputs, max_steps, watch, rwatch, awatch } => {
+            let watches = watch.into_iter().map(|range| (WatchKind::Write, range))
+                .chain(rwatch.into_iter().map(|range| (WatchKind::Read, range)))
+                .chain(awatch.into_iter().map(|range| (WatchKind::Access, range)))
+                .collect();
+            run(&inputs, max_steps, &RunOptions { watches, ..Default::default() })
+        },
+        Command::Sim { inputs, max_steps, cache, mmu, gdb, trace, trace_format, profile, profile_folded, rewind, resume, save } => {
+            let trace = trace.map(|path| (path, trace_format));
+            let profile = profile.then_some(profile_folded);
+            let options = RunOptions { stats: true, caches: cache, mmu, gdb, trace, profile, rewind, resume, save, ..Default::default() };
+            run(&inputs, max_steps, &options)
+        },
+        Command::Emu { inputs, max_steps, input, output, headless, resume, save } => {
+            let console = Console { input, output, headless };
+            run(&inputs, max_steps, &RunOptions { console: Some(console), resume, save, ..Default::default() })
+        },
+        Command::Dbg { inputs, max_steps } => {
+            debug(&inputs, max_steps)
+        },
+        Command::Test { inputs, golden: Some(golden), bless, max_steps, .. } => {
+            if inputs.is_empty() {
+                Err("--golden needs the inputs of the program to trace".to_string())
+            } else {
+                test(&inputs, &golden, bless, max_steps)
+            }
+        },
+        Command::Test { inputs, golden: None, max_steps, coverage, .. } => {
+            run_tests(&inputs, max_steps, coverage.as_deref())
+        },
         Command::Inspect { .. } => {
             eprintln!("Inspect command is not implemented yet.");
             std::process::exit(1);
@@ -90,4 +144,359 @@ fn main() {
         eprintln!("\n  error: {}", e);
         std::process::exit(1);
     }
-}
\ No newline at end of file
+}
+/// Assemble (or load) every input, link them in memory and return the image.
+fn build_image(inputs: &[String]) -> Result<atlas_linker::LinkedImage, String> {
+    let mut objects = Vec::new();
+    for input in inputs {
+        let obj = if input.ends_with(".o") {
+            ObjectFile::from_file(input)
+                .map_err(|e| format!("Failed to read object file '{}': {}", input, e))?
+        } else {
+            let source = std::fs::read_to_string(input)
+                .map_err(|e| format!("Failed to read input file '{}': {}", input, e))?;
+            atlas_assembler::assemble_source(&source, input).map_err(|e| format!("{}", e))?
+        };
+        objects.push((input.clone(), obj));
+    }
+    atlas_linker::link_objects(&objects).map_err(|e| format!("{}", e))
+}
+
+/// How `atlas run` / `atlas sim` set up and report a simulation.
+#[derive(Default)]
+struct RunOptions {
+    /// print cycle counts and instruction statistics
+    stats: bool,
+    /// attach the L1/L2 cache model
+    caches: bool,
+    /// attach the MMU and the full 24-bit physical address space
+    mmu: bool,
+    /// run on a system bus with a UART console and a timer
+    console: Option<Console>,
+    /// hand control to a GDB client connecting to this address
+    gdb: Option<String>,
+    /// write an execution trace to this file
+    trace: Option<(String, TraceFormat)>,
+    /// profile the run, writing folded stacks to the file if one is given
+    profile: Option<Option<String>>,
+    /// report accesses to these address ranges as they happen
+    watches: Vec<(WatchKind, String)>,
+    /// after the run, rewind to this cycle
+    rewind: Option<u64>,
+    /// restore the machine from this snapshot before running
+    resume: Option<String>,
+    /// save a snapshot to this file after the run
+    save: Option<String>,
+}
+
+/// Host side of the `atlas emu` UART.
+struct Console {
+    /// file to read UART input from instead of stdin
+    input: Option<String>,
+    /// file to write UART output to instead of stdout
+    output: Option<String>,
+    /// do not read stdin and suppress the register dump and summary
+    headless: bool,
+}
+
+/// The `atlas emu` machine: RAM around a UART at `UART_BASE` and a timer at
+/// `TIMER_BASE`, both wired to their IRQ lines.
+fn emu_bus(console: &Console, interrupts: &InterruptController) -> Result<SystemBus, String> {
+    let mut uart = match (&console.input, console.headless) {
+        (Some(path), _) => Uart::new(
+            std::fs::read(path).map_err(|e| format!("Failed to read UART input '{}': {}", path, e))?,
+            Box::new(std::io::stdout()),
+        ),
+        (None, true) => Uart::new(Vec::new(), Box::new(std::io::stdout())),
+        (None, false) => Uart::stdio(),
+    };
+    if let Some(path) = &console.output {
+        let file = std::fs::File::create(path)
+            .map_err(|e| format!("Failed to create UART output '{}': {}", path, e))?;
+        uart = uart.with_output(Box::new(file));
+    }
+    let uart = uart.with_irq(interrupts.line(UART_IRQ_LINE));
+
+    let timer = Timer::new().with_irq(interrupts.line(TIMER_IRQ_LINE));
+
+    let mut bus = SystemBus::new();
+    let end = TIMER_BASE + TIMER_SIZE;
+    bus.map_ram(0, UART_BASE).map_err(|e| format!("{}", e))?;
+    bus.map_device(UART_BASE, UART_SIZE, Box::new(uart)).map_err(|e| format!("{}", e))?;
+    bus.map_device(TIMER_BASE, TIMER_SIZE, Box::new(timer)).map_err(|e| format!("{}", e))?;
+    bus.map_ram(end, 0x10000 - end).map_err(|e| format!("{}", e))?;
+    Ok(bus)
+}
+
+fn run(inputs: &[String], max_steps: u64, options: &RunOptions) -> Result<(), String> {
+    let image = build_image(inputs)?;
+    let snapshot = match &options.resume {
+        Some(path) => Some(
+            Snapshot::from_file(path).map_err(|e| format!("Failed to read snapshot '{}': {}", path, e))?,
+        ),
+        None => None,
+    };
+
+    let mut sim = if let Some(console) = &options.console {
+        let interrupts = InterruptController::new();
+        let mut sim = Simulator::with_bus(Box::new(emu_bus(console, &interrupts)?));
+        sim.system = Some(SystemControl::new());
+        sim.interrupts = Some(interrupts);
+        sim
+    } else if options.mmu || snapshot.as_ref().is_some_and(|s| s.mmu.is_some()) {
+        let mut sim = Simulator::with_bus(Box::new(Memory::with_size(1 << 24)));
+        sim.mmu = Some(Mmu::new());
+        sim.system = Some(SystemControl::new());
+        sim
+    } else {
+        Simulator::new()
+    };
+    if options.caches {
+        sim.caches = Some(CacheHierarchy::default());
+    }
+    sim.load(&image.data, 0x0000).map_err(|e| format!("{}", e))?;
+    if let Some(snapshot) = &snapshot {
+        sim.restore_snapshot(snapshot).map_err(|e| format!("{}", e))?;
+    }
+    for (kind, range) in &options.watches {
+        let (start, end) = resolve_range(&image.symbols, range).map_err(|e| format!("{}", e))?;
+        sim.watchpoints.push(Watchpoint::new(start, end, *kind));
+    }
+    if options.rewind.is_some() && !sim.record_history(DEFAULT_SNAPSHOT_INTERVAL) {
+        return Err("--rewind is not supported with memory-mapped devices".to_string());
+    }
+    let mut profiler = options.profile.as_ref().map(|_| Profiler::new(&image.symbols, &image.lines));
+    let outcome = match &options.gdb {
+        Some(address) => {
+            let mut dbg = Debugger::new(sim, image.symbols.clone());
+            serve_gdb(&mut dbg, address)?;
+            sim = dbg.sim;
+            Ok(if sim.is_halted() { "halted" } else { "detached" }.to_string())
+        }
+        None => match &options.trace {
+            Some((path, format)) => {
+                let file = std::fs::File::create(path)
+                    .map_err(|e| format!("Failed to create trace file '{}': {}", path, e))?;
+                let mut writer = std::io::BufWriter::new(file);
+                let mut written = Ok(());
+                let outcome = sim.run_traced(max_steps, |entry| {
+                    if written.is_ok() {
+                        written = writeln!(writer, "{}", format_trace(entry, *format, &image.lines));
+                    }
+                });
+                written
+                    .and_then(|_| writer.flush())
+                    .map_err(|e| format!("Failed to write trace file '{}': {}", path, e))?;
+                outcome.map(|reason| reason.to_string())
+            }
+            None => match profiler.as_mut() {
+                Some(profiler) => sim
+                    .run_profiled(max_steps, |sample| profiler.sample(sample))
+                    .map(|reason| reason.to_string()),
+                None if !sim.watchpoints.is_empty() => sim
+                    .run_watched(max_steps, |sim, hit| {
+                        println!("  Watchpoint: {}", format_watch_hit(sim, hit, &image.symbols, &image.lines));
+                    })
+                    .map(|reason| reason.to_string()),
+                None => sim.run(max_steps).map(|reason| reason.to_string()),
+            },
+        },
+    };
+
+    let outcome = match (outcome, options.rewind) {
+        (Ok(reason), Some(cycle)) => sim
+            .rewind_to_cycle(cycle)
+            .map(|()| format!("{}, rewound to cycle {}", reason, sim.cycles())),
+        (outcome, _) => outcome,
+    };
+    if let (Some(profiler), Some(Some(path))) = (&profiler, &options.profile) {
+        std::fs::write(path, profiler.folded())
+            .map_err(|e| format!("Failed to write folded stacks '{}': {}", path, e))?;
+    }
+    if let (Ok(_), Some(path)) = (&outcome, &options.save) {
+        sim.save_snapshot()
+            .to_file(path)
+            .map_err(|e| format!("Failed to write snapshot '{}': {}", path, e))?;
+    }
+
+    if options.console.as_ref().is_some_and(|c| c.headless) {
+        return outcome.map(|_| ()).map_err(|e| format!("{}", e));
+    }
+    println!();
+    print_registers(&sim.cpu.registers(), &sim.cpu.sr.to_string());
+    println!();
+    if options.stats {
+        print_run_stats(sim.stats());
+        println!();
+    }
+    if let Some(caches) = &sim.caches {
+        print_cache_stats(caches);
+        println!();
+    }
+    if let Some(profiler) = &profiler {
+        for (title, counts) in [("Functions:", &profiler.functions), ("Labels:", &profiler.labels)] {
+            let rows: Vec<ProfileRow> = Profiler::ranked(counts)
+                .into_iter()
+                .map(|(name, c)| ProfileRow { name, instructions: c.instructions, cycles: c.cycles })
+                .collect();
+            print_profile(title, &rows, profiler.total.cycles);
+            println!();
+        }
+    }
+
+    let reason = outcome.map_err(|e| format!("{}", e))?;
+    print_run_summary(inputs, &reason, sim.steps(), sim.cycles());
+    Ok(())
+}
+
+fn format_trace(entry: &TraceEntry, format: TraceFormat, lines: &BTreeMap<u16, SourceLine>) -> String {
+    match format {
+        TraceFormat::Text => format_trace_text(entry, lines),
+        TraceFormat::Json => format_trace_json(entry, lines),
+    }
+}
+
+/// Run a program and trace it in the format `golden`'s extension selects.
+/// Golden traces leave out source lines, so that edits which only move
+/// code between lines keep them valid.
+fn trace_program(inputs: &[String], golden: &str, max_steps: u64) -> Result<(Vec<String>, ExitReason), String> {
+    let image = build_image(inputs)?;
+    let mut sim = Simulator::new();
+    sim.load(&image.data, 0x0000).map_err(|e| format!("{}", e))?;
+
+    let format = TraceFormat::for_path(golden);
+    let mut lines = Vec::new();
+    let reason = sim
+        .run_traced(max_steps, |entry| lines.push(format_trace(entry, format, &BTreeMap::new())))
+        .map_err(|e: SimError| format!("{}", e))?;
+    Ok((lines, reason))
+}
+
+/// Compare a run's trace with the golden trace in `golden`, or replace the
+/// golden trace when blessing.
+fn test(inputs: &[String], golden: &str, bless: bool, max_steps: u64) -> Result<(), String> {
+    let (lines, reason) = trace_program(inputs, golden, max_steps)?;
+    if bless {
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        std::fs::write(golden, contents)
+            .map_err(|e| format!("Failed to write golden trace '{}': {}", golden, e))?;
+        println!("  Wrote {} trace lines to {} ({})", lines.len(), golden, reason);
+        return Ok(());
+    }
+
+    let expected = std::fs::read_to_string(golden)
+        .map_err(|e| format!("Failed to read golden trace '{}': {}", golden, e))?;
+    let expected: Vec<&str> = expected.lines().collect();
+    let differing: Vec<usize> = (0..expected.len().max(lines.len()))
+        .filter(|&i| expected.get(i).copied() != lines.get(i).map(String::as_str))
+        .collect();
+    let mismatch = differing.first().map(|&i| TraceMismatch {
+        line: i + 1,
+        expected: expected.get(i).copied(),
+        actual: lines.get(i).map(String::as_str),
+    });
+    print_trace_check(golden, lines.len(), mismatch.as_ref());
+    if differing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("trace differs from '{}' on {} of {} lines", golden, differing.len(), expected.len()))
+    }
+}
+
+/// Discover the test programs under `paths`, run each one and check its
+/// expectations. With `coverage_dir`, also write a coverage report there.
+fn run_tests(paths: &[String], max_steps: u64, coverage_dir: Option<&str>) -> Result<(), String> {
+    let paths = if paths.is_empty() { vec!["test".to_string()] } else { paths.to_vec() };
+    let mut programs = Vec::new();
+    for path in expect::discover(&paths)? {
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read input file '{}': {}", path.display(), e))?;
+        if let Some(program) = TestProgram::parse(&path, &source)? {
+            programs.push(program);
+        }
+    }
+
+    print_test_start(programs.len());
+    let mut failures = Vec::new();
+    let mut coverage = coverage_dir.map(|_| SourceCoverage::new());
+    for program in &programs {
+        let name = program.path.display().to_string();
+        let reasons = run_test(program, max_steps, coverage.as_mut());
+        print_test_outcome(&name, reasons.is_empty());
+        if !reasons.is_empty() {
+            failures.push((name, reasons));
+        }
+    }
+    print_test_summary(&failures, programs.len() - failures.len());
+    if let (Some(coverage), Some(dir)) = (&coverage, coverage_dir) {
+        let written = coverage.write(Path::new(dir))?;
+        let files: Vec<CoverageSummary> = coverage
+            .files
+            .iter()
+            .map(|(file, c)| CoverageSummary { file, lines: c.line_summary(), branches: c.branch_summary() })
+            .collect();
+        let report = format!("{} and {} annotated listings in {}", written[0].display(), written.len() - 1, dir);
+        print_coverage_summary(&files, &report);
+        println!();
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} of {} tests failed", failures.len(), programs.len()))
+    }
+}
+
+/// Run one test program, returning why it failed (empty if it passed), and
+/// merge its coverage into `coverage` if given.
+fn run_test(program: &TestProgram, max_steps: u64, coverage: Option<&mut SourceCoverage>) -> Vec<String> {
+    let inputs: Vec<String> = std::iter::once(&program.path)
+        .chain(&program.links)
+        .map(|path| path.display().to_string())
+        .collect();
+    let image = match build_image(&inputs) {
+        Ok(image) => image,
+        Err(e) => return vec![e],
+    };
+    let mut sim = Simulator::new();
+    if let Err(e) = sim.load(&image.data, 0x0000) {
+        return vec![format!("{}", e)];
+    }
+    if coverage.is_some() {
+        sim.record_coverage();
+    }
+    let budget = program.max_steps.unwrap_or(max_steps);
+    let outcome = sim.run(budget);
+    if let (Some(coverage), Some(executed)) = (coverage, sim.coverage()) {
+        coverage.add(&image, executed);
+    }
+    match outcome {
+        Ok(ExitReason::Halted) => {}
+        Ok(ExitReason::StepLimit) => return vec![format!("did not halt within {} steps", budget)],
+        Ok(reason) => return vec![format!("stopped before halting: {}", reason)],
+        Err(e) => return vec![format!("{}", e)],
+    }
+    program.expectations.iter().filter_map(|e| e.check(&sim).err()).collect()
+}
+
+/// Wait for one GDB connection on `address` and serve it.
+fn serve_gdb(dbg: &mut Debugger, address: &str) -> Result<(), String> {
+    let listener = TcpListener::bind(address)
+        .map_err(|e| format!("Failed to listen on '{}': {}", address, e))?;
+    println!("  Waiting for GDB on {}", address);
+    let (stream, peer) = listener.accept().map_err(|e| format!("{}", e))?;
+    println!("  GDB connected from {}", peer);
+    atlas_dbg::gdb::serve(dbg, stream).map_err(|e| format!("GDB connection failed: {}", e))
+}
+
+fn debug(inputs: &[String], max_steps: u64) -> Result<(), String> {
+    let image = build_image(inputs)?;
+    let mut sim = Simulator::new();
+    sim.load(&image.data, 0x0000).map_err(|e| format!("{}", e))?;
+
+    let mut dbg = Debugger::new(sim, image.symbols).with_lines(image.lines);
+    dbg.max_steps = max_steps;
+    println!("  Loaded {} bytes. Type 'help' for commands.", image.data.len());
+    atlas_dbg::repl::run(&mut dbg, std::io::stdin().lock());
+    Ok(())
+}
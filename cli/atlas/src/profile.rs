@@ -0,0 +1,172 @@
+//! Label and function profiles for `atlas sim --profile`.
+//!
+//! Every step is charged to the nearest label at or before its PC (the
+//! flat profile) and to each function on an inferred call stack. Atlas-8
+//! has no call instruction — routines are entered and left with plain
+//! branches — so the stack follows source files: a transfer of control
+//! into a file that already has a frame returns to that frame, and a
+//! transfer into any other file calls the label it lands on. Routines that
+//! call each other within one file are therefore charged to the outermost
+//! one.
+//!
+//! Stacks are also written in the folded format flamegraph tools read:
+//! one `main;multiply;mul_loop 42` line per distinct stack, with the label
+//! executing as the leaf and cycles as the count.
+
+use std::collections::BTreeMap;
+
+use atlas_files::SourceLine;
+use atlas_sim::StepSample;
+
+/// Instructions and cycles charged to a label or function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl Counts {
+    fn add(&mut self, sample: &StepSample) {
+        self.instructions += sample.instructions;
+        self.cycles += sample.cycles;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    label: String,
+    /// Source file of the code the frame was entered at.
+    file: Option<String>,
+}
+
+pub struct Profiler<'a> {
+    symbols: &'a BTreeMap<u16, String>,
+    lines: &'a BTreeMap<u16, SourceLine>,
+    stack: Vec<Frame>,
+    /// Self counts of every label that executed.
+    pub labels: BTreeMap<String, Counts>,
+    /// Inclusive counts of every function that was on the stack.
+    pub functions: BTreeMap<String, Counts>,
+    /// Cycles per folded stack.
+    pub stacks: BTreeMap<String, u64>,
+    pub total: Counts,
+}
+
+impl<'a> Profiler<'a> {
+    pub fn new(symbols: &'a BTreeMap<u16, String>, lines: &'a BTreeMap<u16, SourceLine>) -> Self {
+        Self {
+            symbols,
+            lines,
+            stack: Vec::new(),
+            labels: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            stacks: BTreeMap::new(),
+            total: Counts::default(),
+        }
+    }
+
+    pub fn sample(&mut self, sample: &StepSample) {
+        let label = self.label(sample.pc);
+        if self.stack.is_empty() {
+            self.stack.push(Frame { label: label.clone(), file: self.file(sample.pc) });
+        }
+
+        self.total.add(sample);
+        self.labels.entry(label.clone()).or_default().add(sample);
+        let mut names: Vec<&str> = self.stack.iter().map(|frame| frame.label.as_str()).collect();
+        let mut counted: Vec<&str> = Vec::new();
+        for name in &names {
+            if !counted.contains(name) {
+                self.functions.entry(name.to_string()).or_default().add(sample);
+                counted.push(name);
+            }
+        }
+        if names.last() != Some(&label.as_str()) {
+            names.push(&label);
+        }
+        *self.stacks.entry(names.join(";")).or_default() += sample.cycles;
+
+        if sample.next_pc != sample.pc.wrapping_add(2) && sample.next_pc != sample.pc {
+            self.transfer(sample.next_pc);
+        }
+    }
+
+    /// Follow a transfer of control to `target`.
+    fn transfer(&mut self, target: u16) {
+        let file = self.file(target);
+        match self.stack.iter().rposition(|frame| frame.file == file) {
+            Some(index) => self.stack.truncate(index + 1),
+            None => {
+                let label = self.label(target);
+                self.stack.push(Frame { label, file });
+            }
+        }
+    }
+
+    fn label(&self, address: u16) -> String {
+        match self.symbols.range(..=address).next_back() {
+            Some((_, name)) => name.clone(),
+            None => format!("0x{:04x}", address),
+        }
+    }
+
+    fn file(&self, address: u16) -> Option<String> {
+        self.lines.get(&address).map(|source| source.file.clone())
+    }
+
+    /// Counts sorted by cycles, highest first.
+    pub fn ranked(counts: &BTreeMap<String, Counts>) -> Vec<(&str, Counts)> {
+        let mut ranked: Vec<(&str, Counts)> = counts.iter().map(|(name, c)| (name.as_str(), *c)).collect();
+        ranked.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(b.0)));
+        ranked
+    }
+
+    /// The stacks in folded format, one per line.
+    pub fn folded(&self) -> String {
+        self.stacks.iter().map(|(stack, cycles)| format!("{} {}\n", stack, cycles)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_calls_across_files() {
+        let main = "\
+.import double
+.export back
+main:
+    ldi  r1, 0x03
+    br   double
+back:
+    halt
+";
+        let lib = "\
+.import back
+.export double
+double:
+    add  r1, r1
+    br   back
+";
+        let objects = [
+            ("main.asm".to_string(), atlas_assembler::assemble_source(main, "main.asm").unwrap()),
+            ("lib.asm".to_string(), atlas_assembler::assemble_source(lib, "lib.asm").unwrap()),
+        ];
+        let image = atlas_linker::link_objects(&objects).unwrap();
+        let mut sim = atlas_sim::Simulator::new();
+        sim.load(&image.data, 0).unwrap();
+        let mut profiler = Profiler::new(&image.symbols, &image.lines);
+        sim.run_profiled(100, |sample| profiler.sample(sample)).unwrap();
+
+        assert_eq!(profiler.labels["main"].instructions, 2);
+        assert_eq!(profiler.labels["back"].instructions, 1);
+        assert_eq!(profiler.functions["main"].instructions, 5);
+        assert_eq!(profiler.functions["double"].instructions, 2);
+        assert_eq!(profiler.total.instructions, 5);
+        let folded = profiler.folded();
+        let stacks: Vec<&str> = folded.lines().map(|l| l.rsplit_once(' ').unwrap().0).collect();
+        assert_eq!(stacks, ["main", "main;back", "main;double"]);
+        assert_eq!(Profiler::ranked(&profiler.functions)[0].0, "main");
+    }
+}
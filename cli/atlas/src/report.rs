@@ -0,0 +1,116 @@
+//! Simulator reports for `atlas sim`: run statistics, cache statistics and
+//! execution trace lines.
+
+use std::collections::BTreeMap;
+
+use atlas_files::SourceLine;
+use atlas_inspect::{bold, cyan, dim, format_word};
+use atlas_sim::{CacheHierarchy, RunStats, TraceEntry};
+
+/// Print cycle counts and instruction statistics collected by the simulator.
+pub fn print_run_stats(stats: &RunStats) {
+    println!("  {}", bold("Statistics:"));
+    println!("    {:<16} {}", "instructions", stats.instructions);
+    println!("    {:<16} {} {}", "cycles", stats.cycles, dim(&format!("({:.2} CPI)", stats.cpi())));
+    println!(
+        "    {:<16} {} {}",
+        "branches",
+        stats.branches,
+        dim(&format!("({} taken)", stats.branches_taken)),
+    );
+    println!(
+        "    {:<16} {} {}",
+        "memory accesses",
+        stats.memory_reads + stats.memory_writes,
+        dim(&format!("({} reads, {} writes)", stats.memory_reads, stats.memory_writes)),
+    );
+    println!("    {:<16} {}", "stack ops", stats.stack_ops);
+    println!();
+    println!("  {}", bold("By format:"));
+    for (format, counts) in &stats.by_format {
+        println!(
+            "    {} {:>8} instr {:>10} cycles",
+            cyan(&format!("{:<16}", format!("{:?}-type", format))),
+            counts.instructions,
+            counts.cycles,
+        );
+    }
+}
+
+/// Print per-level cache statistics.
+pub fn print_cache_stats(caches: &CacheHierarchy) {
+    println!("  {}", bold("Caches:"));
+    for (name, cache) in [("L1i", &caches.l1i), ("L1d", &caches.l1d), ("L2", &caches.l2)] {
+        let stats = &cache.stats;
+        let mut detail = format!("({:.1}% hit rate", stats.hit_rate() * 100.0);
+        if stats.writebacks > 0 {
+            detail.push_str(&format!(", {} writebacks", stats.writebacks));
+        }
+        detail.push(')');
+        println!(
+            "    {} {:>8} hits {:>8} misses {}",
+            cyan(&format!("{:<16}", name)),
+            stats.hits,
+            stats.misses,
+            dim(&detail),
+        );
+    }
+}
+
+/// Render a trace entry as one line of plain text: step, PC, word,
+/// disassembly and status register, followed by the changed registers
+/// (`r1=0x10`), stores (`[0x0080]=0x35`), any exception taken (`!vec2`)
+/// and the source line of the instruction (`; main.asm:12`) if known.
+pub fn format_trace_text(entry: &TraceEntry, lines: &BTreeMap<u16, SourceLine>) -> String {
+    let mut line = format!(
+        "{:>6}  {:04x}  {:04x}  {:<24} {}",
+        entry.step,
+        entry.pc,
+        entry.word,
+        format_word(entry.word, entry.pc, &BTreeMap::new()),
+        entry.sr,
+    );
+    for (reg, value) in &entry.registers {
+        line.push_str(&format!("  r{}=0x{:02x}", reg, value));
+    }
+    for (address, value) in &entry.memory {
+        line.push_str(&format!("  [0x{:04x}]=0x{:02x}", address, value));
+    }
+    if let Some(vector) = entry.exception {
+        line.push_str(&format!("  !vec{}", vector));
+    }
+    if let Some(source) = lines.get(&entry.pc) {
+        line.push_str(&format!("  ; {}", source));
+    }
+    line
+}
+
+/// Render a trace entry as a single-line JSON object, with a `source` field
+/// when the instruction's source line is known.
+pub fn format_trace_json(entry: &TraceEntry, lines: &BTreeMap<u16, SourceLine>) -> String {
+    let registers: serde_json::Map<String, serde_json::Value> = entry
+        .registers
+        .iter()
+        .map(|(reg, value)| (format!("r{}", reg), (*value).into()))
+        .collect();
+    let memory: Vec<serde_json::Value> = entry
+        .memory
+        .iter()
+        .map(|(address, value)| serde_json::json!({ "address": address, "value": value }))
+        .collect();
+    let mut json = serde_json::json!({
+        "step": entry.step,
+        "pc": entry.pc,
+        "word": entry.word,
+        "disasm": format_word(entry.word, entry.pc, &BTreeMap::new()).split_whitespace().collect::<Vec<_>>().join(" "),
+        "sr": entry.sr.0,
+        "flags": entry.sr.to_string(),
+        "registers": registers,
+        "memory": memory,
+        "exception": entry.exception,
+    });
+    if let Some(source) = lines.get(&entry.pc) {
+        json["source"] = source.to_string().into();
+    }
+    json.to_string()
+}
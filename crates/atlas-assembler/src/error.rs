@@ -2,6 +2,7 @@ use std::fmt::Display;
 use std::io;
 
 use crate::lexer::LexError;
+use crate::macros::MacroCall;
 use crate::parser::ParseError;
 use atlas_isa::EncodingError;
 
@@ -18,6 +19,12 @@ pub enum AssemblerError {
     LexError(LexError),
     // Encoding errors (unresolved labels, invalid instructions, etc.)
     EncodingError(EncodingError),
+    // An error in a line expanded from a macro, reported at the line of the
+    // macro definition, with the calls that led there (outermost first)
+    InMacro {
+        error: Box<AssemblerError>,
+        calls: Vec<MacroCall>,
+    },
 }
 
 impl Display for AssemblerError {
@@ -35,6 +42,13 @@ impl Display for AssemblerError {
             AssemblerError::EncodingError(err) => {
                 write!(f, "{}", err)
             }
+            AssemblerError::InMacro { error, calls } => {
+                write!(f, "{}", error)?;
+                for call in calls.iter().rev() {
+                    write!(f, ", in macro '{}' called at line {}", call.name, call.line)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -1,6 +1,6 @@
 use atlas_isa::{Mnemonic};
 
-use crate::lexer::{LexError, Token, token::{Directive, Span, SpannedToken}};
+use crate::lexer::{LexError, Token, token::{Directive, Operator, Span, SpannedToken}};
 
 #[derive(Debug)]
 pub struct Lexer<'a> {
@@ -9,12 +9,34 @@ pub struct Lexer<'a> {
     line: usize,
     eof_reached: bool,
     last_was_newline: bool,
+    /// Whether the last token ends an operand, so that a following `+` or
+    /// `-` is a binary operator rather than the sign of a number.
+    last_was_operand: bool,
 }
 
 impl<'a> Iterator for Lexer<'a> {
     type Item = Result<SpannedToken, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let result = self.lex();
+        if let Some(Ok(spanned)) = &result {
+            self.last_was_operand = matches!(
+                spanned.token,
+                Token::Immediate(_) | Token::LabelRef(_) | Token::Register(_) | Token::CloseParen
+            );
+        }
+        result
+    }
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Self {
+            src, pos: 0, line: 1, eof_reached: false, last_was_newline: false, last_was_operand: false
+        }
+    }
+
+    fn lex(&mut self) -> Option<Result<SpannedToken, LexError>> {
         // check if we already emitted EOF
         if self.eof_reached {
             return None;
@@ -46,7 +68,7 @@ impl<'a> Iterator for Lexer<'a> {
                 }
                 // continue to next token
                 self.skip();
-                return self.next();
+                return self.lex();
             } else {
                 let start = self.pos;
                 let line = self.line;
@@ -65,19 +87,55 @@ impl<'a> Iterator for Lexer<'a> {
         let start = self.pos;
 
         // check for single-char tokens first (before get_word to avoid empty strings)
-        if let Some(c) = self.peek() {
-            if let Some(token) = Self::process_single_char_token(c) {
-                self.advance(c.len_utf8());
-                self.last_was_newline = false;
-                return Some(Ok(SpannedToken {
-                    token,
-                    span: Span {
-                        start,
-                        end: self.pos,
-                        line: self.line,
-                    },
-                }));
-            }
+        if let Some(c) = self.peek()
+            && let Some(token) = Self::process_single_char_token(c)
+        {
+            self.advance(c.len_utf8());
+            self.last_was_newline = false;
+            return Some(Ok(SpannedToken {
+                token,
+                span: Span {
+                    start,
+                    end: self.pos,
+                    line: self.line,
+                },
+            }));
+        }
+
+        // a sign directly before a digit starts a signed number, unless it
+        // follows an operand (`x-1` is a subtraction)
+        if let Some(c @ ('+' | '-')) = self.peek()
+            && !self.last_was_operand
+            && self.src[start + 1..].starts_with(|d: char| d.is_ascii_digit())
+        {
+            self.advance(1);
+            let word = self.get_word();
+            self.last_was_newline = false;
+            return Some(match Self::check_for_number(word) {
+                Some(Ok(value)) => Ok(SpannedToken {
+                    token: Token::Immediate(crate::lexer::token::Immediate {
+                        value: if c == '-' { -value } else { value },
+                        signed: true,
+                    }),
+                    span: Span { start, end: self.pos, line: self.line },
+                }),
+                _ => Err(LexError::InvalidNumber(self.src[start..self.pos].to_string(), self.line, start)),
+            });
+        }
+
+        // check for operators
+        if let Some(c) = self.peek()
+            && let Some((operator, length)) = self.operator(c)
+        {
+            self.advance(length);
+            self.last_was_newline = false;
+            return Some(Ok(SpannedToken {
+                token: Token::Operator(operator),
+                span: Span { start, end: self.pos, line: self.line },
+            }));
+        }
+        if let Some(c @ ('<' | '>')) = self.peek() {
+            return Some(Err(LexError::InvalidCharacter(c, self.line, start)));
         }
 
         // get next word
@@ -97,21 +155,20 @@ impl<'a> Iterator for Lexer<'a> {
                 }));
             } else {
                 // invalid directive
-                return Some(Err(LexError::InvalidDirective(word.to_string(), start, self.line)));
+                return Some(Err(LexError::InvalidDirective(word.to_string(), self.line, start)));
             }
         }
 
         // check for registers
-        if let Some(reg_num) = word.strip_prefix('r') {
-            if let Ok(n) = reg_num.parse::<u8>() {
-                if n <= 15 {
-                    self.last_was_newline = false;
-                    return Some(Ok(SpannedToken {
-                        token: Token::Register(n),
-                        span: Span { start, end: self.pos, line: self.line },
-                    }));
-                }
-            }
+        if let Some(reg_num) = word.strip_prefix('r')
+            && let Ok(n) = reg_num.parse::<u8>()
+            && n <= 15
+        {
+            self.last_was_newline = false;
+            return Some(Ok(SpannedToken {
+                token: Token::Register(n),
+                span: Span { start, end: self.pos, line: self.line },
+            }));
         }
 
         if let Some(reg) = match word {
@@ -130,12 +187,11 @@ impl<'a> Iterator for Lexer<'a> {
         // check for numbers
         if let Some(result) = Self::check_for_number(word) {
             self.last_was_newline = false;
-            // Check if the number has an explicit +/- prefix
-            let is_signed = word.starts_with('+') || word.starts_with('-');
+            // signed numbers were handled above
             return Some(result.map(|value| SpannedToken {
                 token: Token::Immediate(crate::lexer::token::Immediate {
                     value,
-                    signed: is_signed,
+                    signed: false,
                 }),
                 span: Span {
                     start,
@@ -143,7 +199,7 @@ impl<'a> Iterator for Lexer<'a> {
                     line: self.line
                 }
             }).map_err(|(error_msg, _)| {
-                LexError::InvalidNumber(error_msg, start, self.line)
+                LexError::InvalidNumber(error_msg, self.line, start)
             }));
         }
 
@@ -151,7 +207,7 @@ impl<'a> Iterator for Lexer<'a> {
         if let Some(label) = word.strip_suffix(':') {
             if label.is_empty() {
                 // invalid label (no label name)
-                return Some(Err(LexError::InvalidCharacter(':', start, self.line)));
+                return Some(Err(LexError::InvalidCharacter(':', self.line, start)));
             }
 
             self.last_was_newline = false;
@@ -168,40 +224,32 @@ impl<'a> Iterator for Lexer<'a> {
         // check for mnemonics and label references
         if let Some(instruction) = Mnemonic::from_str(word) {
             self.last_was_newline = false;
-            return Some(Ok(SpannedToken {
+            Some(Ok(SpannedToken {
                 token: Token::Mnemonic(instruction),
                 span: Span {
                     start,
                     end: self.pos,
                     line: self.line
                 }
-            }));
+            }))
         } else {
             self.last_was_newline = false;
-            return Some(Ok(SpannedToken {
+            Some(Ok(SpannedToken {
                 token: Token::LabelRef(String::from(word)),
                 span: Span {
                     start,
                     end: self.pos,
                     line: self.line
                 }
-            }));
-        }
-    }
-}
-
-impl<'a> Lexer<'a> {
-    pub fn new(src: &'a str) -> Self {
-        Self {
-            src, pos: 0, line: 1, eof_reached: false, last_was_newline: false
+            }))
         }
     }
 
     pub fn tokenize(src: &'a str) -> Result<Vec<SpannedToken>, LexError> {
-        let mut lexer = Lexer::new(src);
+        let lexer = Lexer::new(src);
         let mut tokens = Vec::new();
 
-        while let Some(result) = lexer.next() {
+        for result in lexer {
             match result {
                 Ok(token) => tokens.push(token),
                 Err(e) => return Err(e),
@@ -221,29 +269,20 @@ impl<'a> Lexer<'a> {
 
     /// skip whitespace and comments (but not newlines)
     fn skip(&mut self) {
-        loop {
-            while let Some(c) = self.peek() {
-                if c == ' ' || c == '\t' {
-                    self.advance(c.len_utf8());
-                } else if c == ';' {
-                    // skip comment until newline
-                    while let Some(c2) = self.peek() {
-                        if c2 == '\n' {
-                            break;
-                        }
-                        self.advance(c2.len_utf8());
+        while let Some(c) = self.peek() {
+            if c == ' ' || c == '\t' {
+                self.advance(c.len_utf8());
+            } else if c == ';' {
+                // skip comment until newline
+                while let Some(c2) = self.peek() {
+                    if c2 == '\n' {
+                        break;
                     }
-                    break; // exit inner loop to check for newline
-                } else {
-                    break; // reached a real token (including newlines)
+                    self.advance(c2.len_utf8());
                 }
-            }
-            
-            // if we're at a newline, don't skip it yet
-            if let Some('\n') = self.peek() {
-                break;
+                break; // stop at the newline so it becomes a token
             } else {
-                break;
+                break; // reached a real token (including newlines)
             }
         }
     }
@@ -274,6 +313,27 @@ impl<'a> Lexer<'a> {
 
     fn is_punctuation(c: char) -> bool {
         matches!(c, ',' | '@' | '\n' | '(' | ')' | '[' | ']')
+            || matches!(c, '+' | '-' | '*' | '/' | '%' | '<' | '>' | '&' | '|' | '^' | '~')
+    }
+
+    /// The operator starting with `c`, and its length in bytes.
+    fn operator(&self, c: char) -> Option<(Operator, usize)> {
+        let next = self.src[self.pos + c.len_utf8()..].chars().next();
+        let operator = match (c, next) {
+            ('+', _) => Operator::Plus,
+            ('-', _) => Operator::Minus,
+            ('*', _) => Operator::Star,
+            ('/', _) => Operator::Slash,
+            ('%', _) => Operator::Percent,
+            ('&', _) => Operator::Ampersand,
+            ('|', _) => Operator::Pipe,
+            ('^', _) => Operator::Caret,
+            ('~', _) => Operator::Tilde,
+            ('<', Some('<')) => return Some((Operator::ShiftLeft, 2)),
+            ('>', Some('>')) => return Some((Operator::ShiftRight, 2)),
+            _ => return None,
+        };
+        Some((operator, 1))
     }
 
     fn process_single_char_token(char: char) -> Option<Token> {
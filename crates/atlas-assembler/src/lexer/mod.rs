@@ -1,9 +1,12 @@
+#[allow(clippy::module_inception)]
 mod lexer;
 mod token;
 mod error;
 
 pub use lexer::Lexer;
 pub use token::Directive;
+pub use token::Immediate;
+pub use token::Operator;
 pub use token::Token;
 pub use token::SpannedToken;
 pub use error::LexError;
@@ -14,6 +14,7 @@ pub enum Token {
     Immediate(Immediate),
     LabelDef(String),
     LabelRef(String),
+    Operator(Operator),
 
     Comma,
     AtSign,
@@ -27,6 +28,40 @@ pub enum Token {
 }
 
 
+/// Operators of constant expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    ShiftLeft,
+    ShiftRight,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+}
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Percent => "%",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::Ampersand => "&",
+            Operator::Pipe => "|",
+            Operator::Caret => "^",
+            Operator::Tilde => "~",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Span {
     pub start: usize,
@@ -53,9 +88,12 @@ pub enum Directive {
     Byte,
     Word,
     Ascii,
+
+    NoRelax,    // report out-of-range label branches instead of relaxing them
 }
 
 impl Directive {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Directive> {
         match s {
             "global" | "export" => Some(Directive::Global),
@@ -68,6 +106,7 @@ impl Directive {
             "byte" => Some(Directive::Byte),
             "word" => Some(Directive::Word),
             "ascii" => Some(Directive::Ascii),
+            "norelax" => Some(Directive::NoRelax),
             _ => None,
         }
     }
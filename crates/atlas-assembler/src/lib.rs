@@ -1,5 +1,6 @@
 pub mod lexer;
 pub mod parser;
+pub mod macros;
 pub mod error;
 
 pub use lexer::Lexer;
@@ -7,56 +8,16 @@ pub use parser::Parser;
 pub use error::AssemblerError;
 
 use atlas_isa::EncodingError;
-use atlas_isa::operands::Operand;
-use atlas_isa::ParsedInstruction;
-use atlas_files::{ObjectFile, Symbol, SymbolBinding, FileFormat};
+use atlas_isa::operands::{MOffset, Operand, RegisterPairIdentifier};
+use atlas_isa::{ImmOp, ParsedInstruction};
+use atlas_files::{ObjectFile, Symbol, SymbolBinding, FileFormat, LineTable, LINE_TABLE_SECTION, RelocationKind, OBJECT_VERSION};
 use atlas_files::formats::obj::{Section, Relocation};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use crate::parser::ParsedItem;
-use crate::parser::symbols::UnresolvedReference;
-
-
-/// Try to encode an instruction. If it contains an unresolved label, emit a
-/// placeholder (all-zero) encoding and return the label name so the caller can
-/// record a relocation.
-fn encode_or_placeholder(instr: &ParsedInstruction) -> Result<(u16, Option<String>), atlas_isa::EncodingError> {
-    // Check if the instruction contains a label operand.  If so, substitute
-    // Immediate(0) so the encoder succeeds and return the label name.
-    match instr {
-        ParsedInstruction::I { op, dest, immediate: Operand::Label(label), line, source_file } => {
-            let placeholder = ParsedInstruction::I {
-                op: *op,
-                dest: *dest,
-                immediate: Operand::Immediate(0),
-                line: *line,
-                source_file: source_file.clone(),
-            };
-            Ok((placeholder.encode()?, Some(label.clone())))
-        }
-        ParsedInstruction::BI { absolute, cond, operand: Operand::Label(label), line, source_file } => {
-            let placeholder = ParsedInstruction::BI {
-                absolute: *absolute,
-                cond: *cond,
-                operand: Operand::Immediate(0),
-                line: *line,
-                source_file: source_file.clone(),
-            };
-            Ok((placeholder.encode()?, Some(label.clone())))
-        }
-        ParsedInstruction::P { op, register, offset: Operand::Label(label), line, source_file } => {
-            let placeholder = ParsedInstruction::P {
-                op: *op,
-                register: *register,
-                offset: Operand::Immediate(0),
-                line: *line,
-                source_file: source_file.clone(),
-            };
-            Ok((placeholder.encode()?, Some(label.clone())))
-        }
-        other => Ok((other.encode()?, None)),
-    }
-}
+use crate::macros::Expansion;
+use crate::parser::{ParseError, ParsedItem};
+use crate::parser::expr::{AddressPart, Expr, ExprError, Value, in_range};
+use crate::parser::symbols::{Symbol as ParsedSymbol, SymbolTable, UnresolvedReference};
 
 
 /// Assemble source file into an object file (.o format)
@@ -66,9 +27,29 @@ pub fn assemble(src: &str, output: &str) -> Result<(), AssemblerError> {
         operation: format!("Failed to read input file '{}'", src),
         source: e,
     })?;
-    
+
+    let object_file = assemble_source(&source, src)?;
+
+    object_file.to_file(output).map_err(|e| AssemblerError::IoError {
+        operation: format!("Failed to write to output file '{}'", output),
+        source: e,
+    })?;
+
+    Ok(())
+}
+
+/// Assemble source text into an in-memory object file.
+/// `src` is the name recorded as the source file of each instruction.
+pub fn assemble_source(source: &str, src: &str) -> Result<ObjectFile, AssemblerError> {
+    let expansion = macros::expand(source)?;
+    assemble_expansion(&expansion, src).map_err(|e| expansion.locate(e))
+}
+
+/// Assemble a source whose macros are expanded. Errors refer to lines of the
+/// expanded source.
+fn assemble_expansion(expansion: &Expansion, src: &str) -> Result<ObjectFile, AssemblerError> {
     // ── Pass 1: parse everything, collect items & symbols ──────────────
-    let mut parser = Parser::new(&source);
+    let mut parser = Parser::new(&expansion.source);
     
     // Collect all parsed items first (resolves the borrow issue)
     let mut items: Vec<ParsedItem> = Vec::new();
@@ -77,46 +58,90 @@ pub fn assemble(src: &str, output: &str) -> Result<(), AssemblerError> {
     }
 
     // Now we can freely access parser.symbols()
-    let symbols_table = parser.symbols().clone();
+    let mut symbols_table = parser.symbols().clone();
+    let long_branches = if parser.relaxes() {
+        relax_branches(&items, &mut symbols_table)?
+    } else {
+        HashSet::new()
+    };
 
     // ── Pass 2: encode items into section data ─────────────────────────
     let mut section_data: BTreeMap<String, Vec<u8>> = BTreeMap::new();
     let mut current_section = ".text".to_string();
     let mut unresolved: Vec<UnresolvedReference> = Vec::new();
+    let mut line_table = LineTable::new();
 
-    for item in items {
+    for (index, item) in items.into_iter().enumerate() {
         match item {
             ParsedItem::SectionChange(name) => {
                 current_section = name;
                 section_data.entry(current_section.clone()).or_default();
             }
-            ParsedItem::Instruction(instr) => {
+            ParsedItem::Instruction(instr, operand) => {
                 let instr = instr.with_source_file(Some(src.to_string()));
                 let data = section_data.entry(current_section.clone()).or_default();
                 let byte_offset = data.len() as u32;
 
-                // Try to resolve local constants/labels inline first.
-                let instr = resolve_local_operands(&instr, &symbols_table);
+                let lowered = match &operand {
+                    Some(expr) => match label_branch(&instr, expr, &symbols_table)? {
+                        Some((symbol, addend)) => {
+                            let long = long_branches.contains(&index);
+                            let place = (current_section.as_str(), byte_offset);
+                            branch_to_label(instr, symbol, addend, &symbols_table, place, long)?
+                        }
+                        None => vec![resolve_operand(instr, expr, &symbols_table)?],
+                    },
+                    None => vec![(instr, None)],
+                };
 
-                let (encoded, maybe_label) = encode_or_placeholder(&instr)
-                    .map_err(|e| AssemblerError::EncodingError(e))?;
+                for (instr, reference) in lowered {
+                    let offset = data.len() as u32;
+                    let line = expansion.source_line(instr.line());
+                    line_table.push(&current_section, offset, src, line as u32);
+                    let encoded = instr.encode().map_err(AssemblerError::EncodingError)?;
 
-                if let Some(label_name) = maybe_label {
-                    unresolved.push(UnresolvedReference {
-                        offset: byte_offset,
-                        section: current_section.clone(),
-                        symbol: label_name,
-                        addend: 0,
-                    });
-                }
+                    if let Some((symbol, addend, kind)) = reference {
+                        unresolved.push(UnresolvedReference {
+                            offset,
+                            section: current_section.clone(),
+                            symbol,
+                            addend,
+                            kind,
+                        });
+                    }
 
-                data.push((encoded >> 8) as u8);
-                data.push(encoded as u8);
+                    data.push((encoded >> 8) as u8);
+                    data.push(encoded as u8);
+                }
             }
             ParsedItem::Data(bytes) => {
                 let data = section_data.entry(current_section.clone()).or_default();
                 data.extend_from_slice(&bytes);
             }
+            ParsedItem::Words { values, line } => {
+                let data = section_data.entry(current_section.clone()).or_default();
+                for expr in &values {
+                    let word = match evaluate(expr, &symbols_table, line)? {
+                        Value::Constant(value) => in_range(value, -32768, 65535, line)?,
+                        Value::Relative { symbol, addend, part: AddressPart::Whole } => {
+                            unresolved.push(UnresolvedReference {
+                                offset: data.len() as u32,
+                                section: current_section.clone(),
+                                symbol,
+                                addend,
+                                kind: RelocationKind::Word16,
+                            });
+                            0
+                        }
+                        Value::Relative { symbol, .. } => {
+                            return Err(ExprError::AddressPart(symbol).at_line(line).into());
+                        }
+                    };
+                    // little-endian
+                    data.push(word as u8);
+                    data.push((word >> 8) as u8);
+                }
+            }
         }
     }
 
@@ -129,6 +154,13 @@ pub fn assemble(src: &str, output: &str) -> Result<(), AssemblerError> {
             data: data.clone(),
         });
     }
+    if !line_table.entries.is_empty() {
+        sections.push(Section {
+            name: LINE_TABLE_SECTION.to_string(),
+            start: 0,
+            data: line_table.encode(),
+        });
+    }
 
     // ── Build symbol list ──────────────────────────────────────────────
     let mut symbols = Vec::new();
@@ -190,8 +222,8 @@ pub fn assemble(src: &str, output: &str) -> Result<(), AssemblerError> {
 
     // ── Build relocation list ──────────────────────────────────────────
     // Only keep relocations for symbols that are NOT fully resolved locally.
-    // A locally-resolved constant was already patched inline by
-    // `resolve_local_operands`, so we don't need a relocation for it.
+    // A constant operand was already substituted by `resolve_operand`, so we
+    // don't need a relocation for it.
     let mut relocations = Vec::new();
     for uref in &unresolved {
         relocations.push(Relocation {
@@ -199,84 +231,285 @@ pub fn assemble(src: &str, output: &str) -> Result<(), AssemblerError> {
             symbol: uref.symbol.clone(),
             addend: uref.addend,
             section: uref.section.clone(),
+            kind: uref.kind,
         });
     }
 
-    // ── Write object file ──────────────────────────────────────────────
-    let object_file = ObjectFile {
+    Ok(ObjectFile {
         sections,
         symbols,
         relocations,
-        version: 1,
-    };
-
-    object_file.to_file(output).map_err(|e| AssemblerError::IoError {
-        operation: format!("Failed to write to output file '{}'", output),
-        source: e,
-    })?;
+        version: OBJECT_VERSION,
+    })
+}
 
-    Ok(())
+/// Evaluate `expr`, found at `line`, now that every symbol of the file is
+/// known. Symbols the file does not define are assumed to be labels defined
+/// elsewhere.
+fn evaluate(expr: &Expr, symbols: &SymbolTable, line: usize) -> Result<Value, ParseError> {
+    expr.evaluate(&|name| Some(symbols.value(name).unwrap_or_else(|| Value::label(name))))
+        .map_err(|e| e.at_line(line))
 }
 
-/// Try to resolve label operands that refer to locally-defined constants or
-/// labels.  Returns the instruction unchanged if the operand is already
-/// resolved or refers to an unknown (imported) symbol.
-fn resolve_local_operands(
+/// A symbol plus addend left for the linker, and how it is patched in.
+type Reference = (String, i32, RelocationKind);
+
+/// Bytes a relaxed branch adds: `ldi r10, hi(x)` / `ldi r11, lo(x)` /
+/// `br r10, r11` replaces the 2-byte BI instruction.
+const RELAXED_GROWTH: u32 = 4;
+
+/// The label and addend a BI instruction branches to, if its operand is a
+/// label rather than a constant address or offset.
+fn label_branch(
     instr: &ParsedInstruction,
-    symbols: &crate::parser::symbols::SymbolTable,
-) -> ParsedInstruction {
-    match instr {
-        ParsedInstruction::I { op, dest, immediate: Operand::Label(name), line, source_file } => {
-            if let Some(sym) = symbols.resolve(name) {
-                let value = match sym {
-                    crate::parser::symbols::Symbol::Constant(v) => *v as u16,
-                    crate::parser::symbols::Symbol::Label { offset, .. } => *offset as u16,
-                };
-                ParsedInstruction::I {
-                    op: *op,
-                    dest: *dest,
-                    immediate: Operand::Immediate(value),
-                    line: *line,
-                    source_file: source_file.clone(),
-                }
-            } else {
-                instr.clone()
+    expr: &Expr,
+    symbols: &SymbolTable,
+) -> Result<Option<(String, i32)>, ParseError> {
+    if !matches!(instr, ParsedInstruction::BI { .. }) {
+        return Ok(None);
+    }
+    match evaluate(expr, symbols, instr.line())? {
+        Value::Relative { symbol, addend, part: AddressPart::Whole } => Ok(Some((symbol, addend))),
+        _ => Ok(None),
+    }
+}
+
+/// Find the label branches whose target lies in their own section but out
+/// of reach of an 8-bit offset, and move every label to where it ends up once
+/// those branches are relaxed into a branch through TR. Relaxing a branch
+/// moves the labels after it, which can push further branches out of range,
+/// so the layout is recomputed until it is stable; branches only ever grow,
+/// so this terminates. Returns the indices of the relaxed items.
+fn relax_branches(items: &[ParsedItem], symbols: &mut SymbolTable) -> Result<HashSet<usize>, ParseError> {
+    // Every label branch: item index, section, offset, target and addend
+    let mut branches: Vec<(usize, String, u32, String, i32)> = Vec::new();
+    let mut sizes: HashMap<String, u32> = HashMap::new();
+    let mut section = ".text".to_string();
+    for (index, item) in items.iter().enumerate() {
+        let offset = sizes.get(&section).copied().unwrap_or(0);
+        let size = match item {
+            ParsedItem::SectionChange(name) => {
+                section = name.clone();
+                0
             }
-        }
-        ParsedInstruction::BI { absolute, cond, operand: Operand::Label(name), line, source_file } => {
-            if let Some(sym) = symbols.resolve(name) {
-                let value = match sym {
-                    crate::parser::symbols::Symbol::Constant(v) => *v as u16,
-                    crate::parser::symbols::Symbol::Label { offset, .. } => *offset as u16,
-                };
-                ParsedInstruction::BI {
-                    absolute: *absolute,
-                    cond: *cond,
-                    operand: Operand::Immediate(value),
-                    line: *line,
-                    source_file: source_file.clone(),
+            ParsedItem::Instruction(instr, Some(expr)) => {
+                if let Some((symbol, addend)) = label_branch(instr, expr, symbols)? {
+                    branches.push((index, section.clone(), offset, symbol, addend));
                 }
-            } else {
-                instr.clone()
+                2
+            }
+            ParsedItem::Instruction(_, None) => 2,
+            ParsedItem::Data(bytes) => bytes.len() as u32,
+            ParsedItem::Words { values, .. } => 2 * values.len() as u32,
+        };
+        *sizes.entry(section.clone()).or_default() += size;
+    }
+
+    // Where `offset` in `section` moves once the `long` branches are relaxed
+    let moved = |section: &str, offset: u32, long: &HashSet<usize>| {
+        let before = branches
+            .iter()
+            .filter(|(index, s, o, ..)| long.contains(index) && s == section && *o < offset)
+            .count();
+        offset + RELAXED_GROWTH * before as u32
+    };
+
+    let mut long = HashSet::new();
+    loop {
+        let mut grew = false;
+        for (index, section, offset, symbol, addend) in &branches {
+            if long.contains(index) {
+                continue;
+            }
+            let Some(ParsedSymbol::Label { offset: target, section: target_section }) = symbols.resolve(symbol) else {
+                continue;
+            };
+            if target_section != section {
+                continue;
+            }
+            let target = moved(section, *target, &long) as i32 + addend;
+            let distance = target - (moved(section, *offset, &long) as i32 + 2);
+            if !(-128..=127).contains(&distance) {
+                long.insert(*index);
+                grew = true;
             }
         }
-        ParsedInstruction::P { op, register, offset: Operand::Label(name), line, source_file } => {
-            if let Some(sym) = symbols.resolve(name) {
-                let value = match sym {
-                    crate::parser::symbols::Symbol::Constant(v) => *v as u16,
-                    crate::parser::symbols::Symbol::Label { offset, .. } => *offset as u16,
-                };
-                ParsedInstruction::P {
-                    op: *op,
-                    register: *register,
-                    offset: Operand::Immediate(value),
-                    line: *line,
-                    source_file: source_file.clone(),
+        if !grew {
+            break;
+        }
+    }
+
+    symbols.move_labels(|section, offset| moved(section, offset, &long));
+    Ok(long)
+}
+
+/// Lower a BI branch to `symbol + addend`, placed at `place` (section and
+/// offset). A target in the same section becomes a PC-relative offset; any
+/// other target a `rel8` relocation. A `long` branch is relaxed into loading
+/// the target's address into TR and branching through it, which overwrites
+/// r10 and r11.
+fn branch_to_label(
+    instr: ParsedInstruction,
+    symbol: String,
+    addend: i32,
+    symbols: &SymbolTable,
+    place: (&str, u32),
+    long: bool,
+) -> Result<Vec<(ParsedInstruction, Option<Reference>)>, ParseError> {
+    let ParsedInstruction::BI { cond, line, source_file, .. } = instr else {
+        return Ok(vec![(instr, None)]);
+    };
+    let relative = |offset: i32| ParsedInstruction::BI {
+        absolute: false,
+        cond,
+        operand: Operand::Immediate(offset as u8 as u16),
+        line,
+        source_file: source_file.clone(),
+    };
+
+    if long {
+        let load = |dest: u8| ParsedInstruction::I {
+            op: ImmOp::LDI,
+            dest,
+            immediate: Operand::Immediate(0),
+            line,
+            source_file: source_file.clone(),
+        };
+        let jump = ParsedInstruction::BR {
+            absolute: true,
+            cond,
+            source: RegisterPairIdentifier { high: 10, low: 11 },
+            line,
+            source_file: source_file.clone(),
+        };
+        return Ok(vec![
+            (load(10), Some((symbol.clone(), addend, RelocationKind::Hi8))),
+            (load(11), Some((symbol, addend, RelocationKind::Lo8))),
+            (jump, None),
+        ]);
+    }
+
+    match symbols.resolve(&symbol) {
+        Some(ParsedSymbol::Label { offset, section }) if section == place.0 => {
+            let distance = *offset as i32 + addend - (place.1 as i32 + 2);
+            Ok(vec![(relative(in_range(distance, -128, 127, line)?), None)])
+        }
+        _ => Ok(vec![(relative(0), Some((symbol, addend, RelocationKind::Rel8)))]),
+    }
+}
+
+/// Evaluate the operand expression of an I, BI or P instruction, or the
+/// offset of an M instruction. A constant is substituted directly. A label
+/// plus addend is left for the linker, which alone knows where the section
+/// is placed: the operand becomes a zero placeholder and the symbol, addend
+/// and relocation kind are returned with the instruction.
+fn resolve_operand(
+    instr: ParsedInstruction,
+    expr: &Expr,
+    symbols: &SymbolTable,
+) -> Result<(ParsedInstruction, Option<Reference>), ParseError> {
+    let line = instr.line();
+    // The range of a constant operand and the kind of relocation for a label
+    let (min, max, kind) = match instr {
+        ParsedInstruction::BI { absolute: false, .. } => (-128, 127, None),
+        ParsedInstruction::M { .. } => (-5, 7, Some(RelocationKind::Offset4)),
+        _ => (-128, 255, Some(RelocationKind::Imm8)),
+    };
+
+    let (value, reference) = match evaluate(expr, symbols, line)? {
+        Value::Constant(value) => (in_range(value, min, max, line)?, None),
+        Value::Relative { symbol, addend, part } => {
+            let kind = match (part, kind) {
+                (AddressPart::Whole, Some(kind)) => kind,
+                (AddressPart::High, Some(RelocationKind::Imm8)) => RelocationKind::Hi8,
+                (AddressPart::Low, Some(RelocationKind::Imm8)) => RelocationKind::Lo8,
+                (AddressPart::Whole, None) => return Err(ExprError::NotConstant(symbol).at_line(line)),
+                _ => {
+                    return Err(ParseError::InvalidExpression {
+                        line,
+                        details: format!("hi() and lo() of label '{}' can only be 8-bit immediates", symbol),
+                    });
                 }
-            } else {
-                instr.clone()
-            }
+            };
+            (0, Some((symbol, addend, kind)))
+        }
+    };
+
+    let operand = Operand::Immediate(value as u8 as u16);
+    let instr = match instr {
+        ParsedInstruction::I { op, dest, line, source_file, .. } => {
+            ParsedInstruction::I { op, dest, immediate: operand, line, source_file }
+        }
+        ParsedInstruction::BI { absolute, cond, line, source_file, .. } => {
+            ParsedInstruction::BI { absolute, cond, operand, line, source_file }
         }
-        _ => instr.clone(),
+        ParsedInstruction::P { op, register, line, source_file, .. } => {
+            ParsedInstruction::P { op, register, offset: operand, line, source_file }
+        }
+        ParsedInstruction::M { op, dest, base, line, source_file, .. } => {
+            ParsedInstruction::M { op, dest, base, offset: MOffset::Offset8(value as u8), line, source_file }
+        }
+        other => other,
+    };
+    Ok((instr, reference))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_expressions_and_offsets_labels() {
+        let source = "\
+SIZE: .imm 4 * (2 + 1)
+MASK: .imm ~0x0F & 0xFF
+    ldi  r1, SIZE - 2
+    ldi  r2, MASK >> 4 | LATE
+    ldi  r3, table + 2
+    ld   r4, [r1 - 1]
+    br   +2 * 2
+table:
+    .byte SIZE, -SIZE
+LATE: .imm 0x80
+";
+        let obj = assemble_source(source, "expr.asm").unwrap();
+        let text = &obj.sections.iter().find(|s| s.name == ".text").unwrap().data;
+        assert_eq!(text, &[0x11, 0x0a, 0x12, 0x8f, 0x13, 0x00, 0x64, 0x1f, 0x80, 0x04, 0x0c, 0xf4]);
+        assert_eq!(obj.relocations.len(), 1);
+        assert_eq!((obj.relocations[0].symbol.as_str(), obj.relocations[0].addend), ("table", 2));
+
+        assert!(assemble_source("X: .imm 1 / (2 - 2)\n", "x.asm").is_err());
+        assert!(assemble_source("x:\n    ldi r1, x * 2\n", "x.asm").is_err());
+    }
+
+    #[test]
+    fn branches_to_labels_are_relative_and_relaxed_when_out_of_range() {
+        let short = assemble_source(".import far\nloop:\n    subi r1, 1\n    bne loop\n    br far\n", "a.asm").unwrap();
+        assert_eq!(&short.sections[0].data, &[0x31, 0x01, 0x82, 0xfc, 0x80, 0x00]);
+        assert_eq!(short.relocations.len(), 1);
+        assert_eq!((short.relocations[0].offset, short.relocations[0].kind), (4, RelocationKind::Rel8));
+
+        // `beq far` is out of range; relaxing it pushes `mid` out of reach of
+        // `bne mid`, which is relaxed on the next iteration
+        let source = format!(
+            "    bne mid\n    beq far\n{}mid:\n{}far:\n    halt\n",
+            "    nop\n".repeat(61),
+            "    nop\n".repeat(70)
+        );
+        let obj = assemble_source(&source, "b.asm").unwrap();
+        let text = &obj.sections.iter().find(|s| s.name == ".text").unwrap().data;
+        assert_eq!(&text[..12], &[0x1a, 0x00, 0x1b, 0x00, 0x9a, 0xba, 0x1a, 0x00, 0x1b, 0x00, 0x99, 0xba]);
+        let address = |name: &str| obj.symbols.iter().find(|s| s.name == name).unwrap().value;
+        assert_eq!((address("mid"), address("far")), (134, 274));
+        let kinds: Vec<_> = obj.relocations.iter().map(|r| (r.offset, r.symbol.as_str(), r.kind)).collect();
+        assert_eq!(kinds, [
+            (0, "mid", RelocationKind::Hi8),
+            (2, "mid", RelocationKind::Lo8),
+            (6, "far", RelocationKind::Hi8),
+            (8, "far", RelocationKind::Lo8),
+        ]);
+
+        // `.norelax` keeps r10/r11 intact and reports the branch instead
+        assert!(assemble_source(&format!(".norelax\n{source}"), "b.asm").is_err());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,438 @@
+//! Macro expansion (`.macro` / `.endm`).
+//!
+//! Macros are expanded on the source text before it is lexed, so the parser
+//! only ever sees ordinary lines:
+//!
+//! ```text
+//! .macro call routine, ret, scratch=r5
+//!     ldi  \scratch, 0
+//!     br   \routine
+//! \ret:
+//! .endm
+//!
+//!     call multiply, mul_ret
+//! ```
+//!
+//! A call names the macro where a mnemonic would go, followed by its
+//! comma-separated arguments. In the body, `\param` is replaced by the
+//! argument, or by the parameter's default when the argument is left out,
+//! and `\@` by a number unique to each expansion, for labels that must not
+//! clash between expansions (`loop\@:`). Bodies may call other macros but not
+//! define them, and a macro must be defined before it is called.
+//!
+//! Every line of the expanded source remembers where it came from: errors in
+//! expanded lines are reported at the line of the definition together with
+//! the calls that led there, and debug line tables attribute expanded code to
+//! the outermost call.
+
+use std::collections::HashMap;
+
+use atlas_isa::{EncodingError, Mnemonic};
+
+use crate::error::AssemblerError;
+use crate::parser::ParseError;
+
+/// How deeply macro calls may nest before the expansion is taken to recurse
+/// forever.
+const MAX_DEPTH: usize = 64;
+
+/// A macro call that an expanded line came through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroCall {
+    pub name: String,
+    /// Line of the call: in the source, or in the definition of the macro
+    /// that made it.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    /// Parameter names and defaults.
+    params: Vec<(String, Option<String>)>,
+    /// Body lines without comments, with their lines in the source.
+    body: Vec<(usize, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct Origin {
+    /// The line in the source; for an expanded line, the line of the body.
+    line: usize,
+    /// Calls the line was expanded through, outermost first.
+    calls: Vec<MacroCall>,
+}
+
+/// A source with every macro expanded.
+pub struct Expansion {
+    pub source: String,
+    /// Origin of each line of `source`.
+    origins: Vec<Origin>,
+}
+
+impl Expansion {
+    /// The source line that `line` of the expanded source is attributed to:
+    /// the line itself, or the outermost call it was expanded from.
+    pub fn source_line(&self, line: usize) -> usize {
+        match self.origin(line) {
+            Some(origin) => origin.calls.first().map_or(origin.line, |call| call.line),
+            None => line,
+        }
+    }
+
+    /// Report an error in the expanded source against the original source.
+    pub fn locate(&self, error: AssemblerError) -> AssemblerError {
+        let line = match &error {
+            AssemblerError::ParseError(e) => e.line(),
+            AssemblerError::EncodingError(e) => e.line,
+            _ => return error,
+        };
+        let Some(origin) = self.origin(line) else {
+            return error;
+        };
+        let error = match error {
+            AssemblerError::ParseError(e) => AssemblerError::ParseError(e.with_line(origin.line)),
+            AssemblerError::EncodingError(e) => {
+                AssemblerError::EncodingError(EncodingError { line: origin.line, message: e.message })
+            }
+            other => other,
+        };
+        in_macro(error, &origin.calls)
+    }
+
+    fn origin(&self, line: usize) -> Option<&Origin> {
+        line.checked_sub(1).and_then(|index| self.origins.get(index))
+    }
+}
+
+/// Expand every macro in `source`.
+pub fn expand(source: &str) -> Result<Expansion, AssemblerError> {
+    let mut expander = Expander::default();
+    let mut lines = source.lines().enumerate().map(|(index, text)| (index + 1, text));
+    while let Some((line, text)) = lines.next() {
+        match directive(code(text)) {
+            Some((".macro", header)) => expander.define(line, header, &mut lines)?,
+            Some((".endm", _)) => return Err(invalid(line, "'.endm' without '.macro'".to_string())),
+            _ => expander.emit(text, Origin { line, calls: Vec::new() })?,
+        }
+    }
+    Ok(Expansion { source: expander.lines.join("\n"), origins: expander.origins })
+}
+
+#[derive(Default)]
+struct Expander {
+    macros: HashMap<String, Macro>,
+    /// Expansions so far, numbering the next one for `\@`.
+    expansions: usize,
+    lines: Vec<String>,
+    origins: Vec<Origin>,
+}
+
+impl Expander {
+    fn push(&mut self, text: String, origin: Origin) {
+        self.lines.push(text);
+        self.origins.push(origin);
+    }
+
+    /// Read the definition whose header is at `line`, up to its `.endm`.
+    /// The definition is replaced by blank lines.
+    fn define<'a>(
+        &mut self,
+        line: usize,
+        header: &str,
+        lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    ) -> Result<(), AssemblerError> {
+        let (name, params) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+        if !is_identifier(name) {
+            return Err(invalid(line, format!("'{}' is not a valid macro name", name)));
+        }
+        if Mnemonic::from_str(name).is_some() {
+            return Err(invalid(line, format!("macro '{}' would hide the instruction of that name", name)));
+        }
+        if self.macros.contains_key(name) {
+            return Err(invalid(line, format!("macro '{}' is already defined", name)));
+        }
+
+        let mut parsed: Vec<(String, Option<String>)> = Vec::new();
+        for param in split_arguments(params) {
+            let (param, default) = match param.split_once('=') {
+                Some((param, default)) => (param.trim(), Some(default.trim().to_string())),
+                None => (param, None),
+            };
+            if !is_identifier(param) {
+                return Err(invalid(line, format!("'{}' is not a valid parameter name", param)));
+            }
+            if parsed.iter().any(|(p, _)| p == param) {
+                return Err(invalid(line, format!("parameter '{}' is declared twice", param)));
+            }
+            parsed.push((param.to_string(), default));
+        }
+        self.push(String::new(), Origin { line, calls: Vec::new() });
+
+        let mut body = Vec::new();
+        loop {
+            let Some((body_line, text)) = lines.next() else {
+                return Err(invalid(line, format!("macro '{}' has no matching '.endm'", name)));
+            };
+            self.push(String::new(), Origin { line: body_line, calls: Vec::new() });
+            match directive(code(text)) {
+                Some((".endm", _)) => break,
+                Some((".macro", _)) => {
+                    return Err(invalid(body_line, "macro definitions cannot be nested".to_string()));
+                }
+                _ => {}
+            }
+            for reference in references(code(text)) {
+                if reference != "@" && !parsed.iter().any(|(p, _)| p == reference) {
+                    let details = format!("'\\{}' is not a parameter of macro '{}'", reference, name);
+                    return Err(invalid(body_line, details));
+                }
+            }
+            body.push((body_line, code(text).to_string()));
+        }
+
+        self.macros.insert(name.to_string(), Macro { params: parsed, body });
+        Ok(())
+    }
+
+    /// Emit a line, expanding it if it calls a macro.
+    fn emit(&mut self, text: &str, origin: Origin) -> Result<(), AssemblerError> {
+        let code = code(text).trim();
+        let (label, statement) = match code.split_once(char::is_whitespace) {
+            Some((label, rest)) if label.ends_with(':') => (Some(label), rest.trim_start()),
+            _ => (None, code),
+        };
+        let (name, arguments) = statement.split_once(char::is_whitespace).unwrap_or((statement, ""));
+        let Some(definition) = self.macros.get(name).cloned() else {
+            self.push(text.to_string(), origin);
+            return Ok(());
+        };
+
+        let fail = |details: String| in_macro(invalid(origin.line, details), &origin.calls);
+        if origin.calls.len() >= MAX_DEPTH {
+            return Err(fail(format!("macro calls nest more than {} deep; is '{}' recursive?", MAX_DEPTH, name)));
+        }
+        let arguments = split_arguments(arguments);
+        if arguments.len() > definition.params.len() {
+            let details = format!(
+                "macro '{}' takes {} arguments but was given {}",
+                name,
+                definition.params.len(),
+                arguments.len()
+            );
+            return Err(fail(details));
+        }
+        let mut values: HashMap<&str, &str> = HashMap::new();
+        for (index, (param, default)) in definition.params.iter().enumerate() {
+            let value = match (arguments.get(index), default) {
+                (Some(argument), _) if !argument.is_empty() => argument,
+                (_, Some(default)) => default.as_str(),
+                _ => return Err(fail(format!("missing argument '{}' of macro '{}'", param, name))),
+            };
+            values.insert(param, value);
+        }
+
+        if let Some(label) = label {
+            self.push(label.to_string(), origin.clone());
+        }
+        let expansion = self.expansions.to_string();
+        self.expansions += 1;
+        let mut calls = origin.calls.clone();
+        calls.push(MacroCall { name: name.to_string(), line: origin.line });
+        for (line, body) in &definition.body {
+            let text = substitute(body, &values, &expansion);
+            self.emit(&text, Origin { line: *line, calls: calls.clone() })?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(line: usize, details: String) -> AssemblerError {
+    AssemblerError::ParseError(ParseError::InvalidMacro { line, details })
+}
+
+fn in_macro(error: AssemblerError, calls: &[MacroCall]) -> AssemblerError {
+    if calls.is_empty() {
+        error
+    } else {
+        AssemblerError::InMacro { error: Box::new(error), calls: calls.to_vec() }
+    }
+}
+
+/// A line without its comment.
+fn code(text: &str) -> &str {
+    text.split(';').next().unwrap_or("")
+}
+
+/// `.macro` or `.endm` and the rest of the line, if the line is one.
+fn directive(code: &str) -> Option<(&str, &str)> {
+    let code = code.trim();
+    let (word, rest) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+    matches!(word, ".macro" | ".endm").then(|| (word, rest.trim()))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split comma-separated arguments, leaving commas inside parentheses and
+/// brackets (`[r3, 0]`) alone.
+fn split_arguments(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut arguments = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    arguments.push(text[start..].trim());
+    arguments
+}
+
+/// The names after each `\` in `text`; `@` for `\@`.
+fn references(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(index) = rest.find('\\') {
+        rest = &rest[index + 1..];
+        let length = match rest.chars().next() {
+            Some('@') => 1,
+            _ => rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len()),
+        };
+        names.push(&rest[..length]);
+        rest = &rest[length..];
+    }
+    names
+}
+
+/// Replace every `\param` in `text` with its value and `\@` with `expansion`.
+fn substitute(text: &str, values: &HashMap<&str, &str>, expansion: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    for name in references(text) {
+        let index = rest.find('\\').unwrap_or(rest.len());
+        result.push_str(&rest[..index]);
+        result.push_str(if name == "@" { expansion } else { values.get(name).copied().unwrap_or("") });
+        rest = &rest[index + 1 + name.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_nested_calls_with_defaults_and_unique_labels() {
+        let source = "\
+.macro delay count=3
+    ldi  r1, \\count
+wait\\@:
+    subi r1, 1
+    bne  wait\\@
+.endm
+.macro call routine, ret
+    delay
+    br   \\routine
+\\ret:
+.endm
+start: call work, back    ; comment
+    delay 0x10
+";
+        let expansion = expand(source).unwrap();
+        let code: Vec<&str> = expansion.source.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        assert_eq!(code, [
+            "start:", "ldi  r1, 3", "wait1:", "subi r1, 1", "bne  wait1", "br   work", "back:",
+            "ldi  r1, 0x10", "wait2:", "subi r1, 1", "bne  wait2",
+        ]);
+        let line = |text: &str| expansion.source.lines().position(|l| l.trim() == text).unwrap() + 1;
+        assert_eq!(expansion.source_line(line("subi r1, 1")), 12);
+        assert_eq!(expansion.source_line(line("bne  wait2")), 13);
+
+        let Err(error) = crate::assemble_source(&source.replace("subi r1, 1", "subi r1, 300"), "m.asm") else {
+            panic!("out-of-range immediate assembled");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Immediate value 300 out of range at line 4 (expected -128..=255), \
+             in macro 'delay' called at line 8, in macro 'call' called at line 12"
+        );
+        assert!(expand(".macro m a\n    ldi r1, \\b\n.endm\n").is_err());
+        assert!(expand(".macro m a\n.endm\n    m 1, 2\n").is_err());
+        assert!(expand(".macro m\n    m\n.endm\n    m\n").is_err());
+    }
+
+    /// The message of the error expanding `source`.
+    fn error(source: &str) -> String {
+        match expand(source) {
+            Ok(_) => panic!("{source:?} expanded"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn label_before_a_call_marks_the_first_expanded_instruction() {
+        let source = "\
+.macro twice op
+    \\op
+    \\op
+.endm
+    nop
+start: twice nop
+after:
+";
+        let expansion = expand(source).unwrap();
+        let line = expansion.source.lines().position(|l| l == "start:").unwrap() + 1;
+        assert_eq!(expansion.source_line(line), 6);
+
+        let obj = crate::assemble_source(source, "l.asm").unwrap();
+        let address = |name: &str| obj.symbols.iter().find(|s| s.name == name).unwrap().value;
+        assert_eq!((address("start"), address("after")), (2, 6));
+    }
+
+    #[test]
+    fn arguments_keep_commas_inside_brackets_and_parentheses() {
+        assert_eq!(split_arguments(" r4, [r3, 0] ,hi(x, y), "), ["r4", "[r3, 0]", "hi(x, y)", ""]);
+        assert_eq!(split_arguments("   "), Vec::<&str>::new());
+
+        let expansion = expand(".macro load dest, addr\n    ld \\dest, \\addr\n.endm\n    load r4, [r3 + 1]\n").unwrap();
+        assert_eq!(expansion.source.trim(), "ld r4, [r3 + 1]");
+    }
+
+    #[test]
+    fn rejects_malformed_definitions_and_calls() {
+        assert_eq!(error("    nop\n.endm\n"), "Invalid macro at line 2: '.endm' without '.macro'");
+        assert_eq!(
+            error(".macro outer\n.macro inner\n.endm\n.endm\n"),
+            "Invalid macro at line 2: macro definitions cannot be nested"
+        );
+        assert_eq!(error(".macro m\n    nop\n"), "Invalid macro at line 1: macro 'm' has no matching '.endm'");
+        assert_eq!(
+            error(".macro m\n.endm\n.macro m a\n.endm\n"),
+            "Invalid macro at line 3: macro 'm' is already defined"
+        );
+        assert_eq!(
+            error(".macro ldi a\n.endm\n"),
+            "Invalid macro at line 1: macro 'ldi' would hide the instruction of that name"
+        );
+        assert_eq!(
+            error(".macro m a, b=1\n    ldi \\a, \\b\n.endm\n    m\n"),
+            "Invalid macro at line 4: missing argument 'a' of macro 'm'"
+        );
+        assert_eq!(
+            error(".macro m a, b\n.endm\n    m r1, \n"),
+            "Invalid macro at line 3: missing argument 'b' of macro 'm'"
+        );
+    }
+}
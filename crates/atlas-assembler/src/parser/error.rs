@@ -8,6 +8,8 @@ pub enum ParseError {
     ImmediateOutOfRange { line: usize, value: i32, min: i32, max: i32 },
     LexError { line: usize, details: String },
     WriteToR0 { line: usize, instruction: String },
+    InvalidExpression { line: usize, details: String },
+    InvalidMacro { line: usize, details: String },
 }
 
 impl Display for ParseError {
@@ -31,7 +33,43 @@ impl Display for ParseError {
             ParseError::WriteToR0 { line, instruction } => {
                 write!(f, "Cannot write to r0 at line {} ({}): r0 is hardwired to zero", line, instruction)
             }
+            ParseError::InvalidExpression { line, details } => {
+                write!(f, "Invalid expression at line {}: {}", line, details)
+            }
+            ParseError::InvalidMacro { line, details } => {
+                write!(f, "Invalid macro at line {}: {}", line, details)
+            }
+        }
+    }
+}
+
+impl ParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            ParseError::InvalidParameters { line, .. }
+            | ParseError::UnknownSymbol { line, .. }
+            | ParseError::UnexpectedToken { line, .. }
+            | ParseError::ImmediateOutOfRange { line, .. }
+            | ParseError::LexError { line, .. }
+            | ParseError::WriteToR0 { line, .. }
+            | ParseError::InvalidExpression { line, .. }
+            | ParseError::InvalidMacro { line, .. } => *line,
+        }
+    }
+
+    /// The same error, reported at `line`.
+    pub fn with_line(mut self, new_line: usize) -> Self {
+        match &mut self {
+            ParseError::InvalidParameters { line, .. }
+            | ParseError::UnknownSymbol { line, .. }
+            | ParseError::UnexpectedToken { line, .. }
+            | ParseError::ImmediateOutOfRange { line, .. }
+            | ParseError::LexError { line, .. }
+            | ParseError::WriteToR0 { line, .. }
+            | ParseError::InvalidExpression { line, .. }
+            | ParseError::InvalidMacro { line, .. } => *line = new_line,
         }
+        self
     }
 }
 
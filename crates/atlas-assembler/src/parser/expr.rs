@@ -0,0 +1,306 @@
+//! Constant expressions in operands and `.imm` values.
+//!
+//! Expressions combine numbers and symbols with the C operators, from
+//! lowest to highest precedence:
+//!
+//! ```text
+//! |    ^    &    << >>    + -    * / %    unary - ~ +    ( )
+//! ```
+//!
+//! Arithmetic is on 32-bit signed integers. An expression evaluates either
+//! to a constant or, when it refers to a label whose address only the linker
+//! knows, to that label plus a constant addend: `label + 4`, `label - 1`,
+//! `4 + label`. The assembler emits the latter as a relocation. The
+//! difference of two references to the same label is a constant.
+//!
+//! `hi(x)` and `lo(x)` select the high and low byte of a 16-bit value. Applied
+//! to a label they select a byte of its address, which the linker patches in;
+//! such a value cannot be combined any further.
+
+use std::fmt::Display;
+
+use crate::lexer::Operator;
+use crate::parser::ParseError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i32),
+    Symbol(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    /// Bits 8–15, `hi(x)`.
+    Hi,
+    /// Bits 0–7, `lo(x)`.
+    Lo,
+}
+
+/// The part of a label's address a relative value stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressPart {
+    Whole,
+    High,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+}
+
+/// The value of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Constant(i32),
+    /// `part` of the address of `symbol` plus `addend`.
+    Relative { symbol: String, addend: i32, part: AddressPart },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    UnknownSymbol(String),
+    /// A label was used where only a constant is allowed.
+    NotConstant(String),
+    /// `hi()` or `lo()` of a label was combined with something else.
+    AddressPart(String),
+    DivisionByZero,
+    InvalidShift(i32),
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprError::UnknownSymbol(name) => write!(f, "unknown symbol '{}'", name),
+            ExprError::NotConstant(name) => {
+                write!(f, "label '{}' is not a constant; only a constant can be added to or subtracted from it", name)
+            }
+            ExprError::AddressPart(name) => {
+                write!(f, "hi() or lo() of label '{}' must be the whole operand", name)
+            }
+            ExprError::DivisionByZero => write!(f, "division by zero"),
+            ExprError::InvalidShift(amount) => write!(f, "shift amount {} is not in 0..=31", amount),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl ExprError {
+    /// The parse error reporting this error at `line`.
+    pub fn at_line(self, line: usize) -> ParseError {
+        match self {
+            ExprError::UnknownSymbol(name) => ParseError::UnknownSymbol { line, name },
+            other => ParseError::InvalidExpression { line, details: other.to_string() },
+        }
+    }
+}
+
+impl Value {
+    /// The address of `symbol`.
+    pub fn label(symbol: &str) -> Self {
+        Value::Relative { symbol: symbol.to_string(), addend: 0, part: AddressPart::Whole }
+    }
+
+    pub fn constant(self) -> Result<i32, ExprError> {
+        match self {
+            Value::Constant(value) => Ok(value),
+            Value::Relative { symbol, part: AddressPart::Whole, .. } => Err(ExprError::NotConstant(symbol)),
+            Value::Relative { symbol, .. } => Err(ExprError::AddressPart(symbol)),
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn from_operator(operator: Operator) -> Option<Self> {
+        match operator {
+            Operator::Plus => Some(BinaryOp::Add),
+            Operator::Minus => Some(BinaryOp::Sub),
+            Operator::Star => Some(BinaryOp::Mul),
+            Operator::Slash => Some(BinaryOp::Div),
+            Operator::Percent => Some(BinaryOp::Rem),
+            Operator::ShiftLeft => Some(BinaryOp::Shl),
+            Operator::ShiftRight => Some(BinaryOp::Shr),
+            Operator::Ampersand => Some(BinaryOp::And),
+            Operator::Pipe => Some(BinaryOp::Or),
+            Operator::Caret => Some(BinaryOp::Xor),
+            Operator::Tilde => None,
+        }
+    }
+
+    /// Binding strength; higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::Xor => 2,
+            BinaryOp::And => 3,
+            BinaryOp::Shl | BinaryOp::Shr => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 6,
+        }
+    }
+
+    fn apply(&self, lhs: i32, rhs: i32) -> Result<i32, ExprError> {
+        Ok(match self {
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+            BinaryOp::Mul => lhs.wrapping_mul(rhs),
+            BinaryOp::Div | BinaryOp::Rem if rhs == 0 => return Err(ExprError::DivisionByZero),
+            BinaryOp::Div => lhs.wrapping_div(rhs),
+            BinaryOp::Rem => lhs.wrapping_rem(rhs),
+            BinaryOp::Shl | BinaryOp::Shr if !(0..32).contains(&rhs) => return Err(ExprError::InvalidShift(rhs)),
+            BinaryOp::Shl => lhs << rhs,
+            BinaryOp::Shr => lhs >> rhs,
+            BinaryOp::And => lhs & rhs,
+            BinaryOp::Or => lhs | rhs,
+            BinaryOp::Xor => lhs ^ rhs,
+        })
+    }
+}
+
+impl Expr {
+    /// Evaluate the expression, looking symbols up with `resolve`.
+    pub fn evaluate(&self, resolve: &dyn Fn(&str) -> Option<Value>) -> Result<Value, ExprError> {
+        match self {
+            Expr::Number(value) => Ok(Value::Constant(*value)),
+            Expr::Symbol(name) => resolve(name).ok_or_else(|| ExprError::UnknownSymbol(name.clone())),
+            Expr::Unary(op, operand) => match (op, operand.evaluate(resolve)?) {
+                (UnaryOp::Hi, Value::Relative { symbol, addend, part: AddressPart::Whole }) => {
+                    Ok(Value::Relative { symbol, addend, part: AddressPart::High })
+                }
+                (UnaryOp::Lo, Value::Relative { symbol, addend, part: AddressPart::Whole }) => {
+                    Ok(Value::Relative { symbol, addend, part: AddressPart::Low })
+                }
+                (op, value) => {
+                    let value = value.constant()?;
+                    Ok(Value::Constant(match op {
+                        UnaryOp::Neg => value.wrapping_neg(),
+                        UnaryOp::Not => !value,
+                        UnaryOp::Hi => (value >> 8) & 0xFF,
+                        UnaryOp::Lo => value & 0xFF,
+                    }))
+                }
+            },
+            Expr::Binary(op, lhs, rhs) => match (op, lhs.evaluate(resolve)?, rhs.evaluate(resolve)?) {
+                (BinaryOp::Add, Value::Relative { symbol, addend, part: AddressPart::Whole }, Value::Constant(value))
+                | (BinaryOp::Add, Value::Constant(value), Value::Relative { symbol, addend, part: AddressPart::Whole }) => {
+                    Ok(Value::Relative { symbol, addend: addend.wrapping_add(value), part: AddressPart::Whole })
+                }
+                (BinaryOp::Sub, Value::Relative { symbol, addend, part: AddressPart::Whole }, Value::Constant(value)) => {
+                    Ok(Value::Relative { symbol, addend: addend.wrapping_sub(value), part: AddressPart::Whole })
+                }
+                (
+                    BinaryOp::Sub,
+                    Value::Relative { symbol: a, addend: x, part: AddressPart::Whole },
+                    Value::Relative { symbol: b, addend: y, part: AddressPart::Whole },
+                ) if a == b => Ok(Value::Constant(x.wrapping_sub(y))),
+                (op, lhs, rhs) => Ok(Value::Constant(op.apply(lhs.constant()?, rhs.constant()?)?)),
+            },
+        }
+    }
+}
+
+/// Check that `value`, found at `line`, lies in `min..=max`.
+pub fn in_range(value: i32, min: i32, max: i32, line: usize) -> Result<i32, ParseError> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(ParseError::ImmediateOutOfRange { line, value, min, max })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParsedItem, Parser};
+    use crate::{AssemblerError, assemble_source};
+    use atlas_files::RelocationKind;
+
+    /// Parse `source` as the operand of an `ldi` and evaluate it with the
+    /// labels `start` and `end` and the constant `FOUR`.
+    fn eval(source: &str) -> Result<Value, ExprError> {
+        let line = format!("    ldi r1, {source}\n");
+        let Some(Ok(ParsedItem::Instruction(_, Some(expr)))) = Parser::new(&line).next() else {
+            panic!("'{source}' is not an operand expression");
+        };
+        expr.evaluate(&|name| match name {
+            "start" | "end" => Some(Value::label(name)),
+            "FOUR" => Some(Value::Constant(4)),
+            _ => None,
+        })
+    }
+
+    fn relative(symbol: &str, addend: i32, part: AddressPart) -> Value {
+        Value::Relative { symbol: symbol.to_string(), addend, part }
+    }
+
+    #[test]
+    fn operators_bind_by_precedence_and_associate_left() {
+        for (source, value) in [
+            ("1 | 6 ^ 3 & 2", 5),
+            ("1 + 2 << 3", 24),
+            ("(1 | 2) << 2", 12),
+            ("2 + 7 % 4 * 3", 11),
+            ("-7 % 3", -1),
+            ("0x0F ^ 0xFF", 0xF0),
+            ("16 - 4 - 2", 10),
+            ("64 / 4 / 2", 8),
+            ("-16 >> 2", -4),
+            ("1 << 31", i32::MIN),
+            ("FOUR << FOUR >> 2", 16),
+            ("~FOUR & 0xFF", 0xFB),
+            ("hi(0x1234) + lo(0x1234)", 0x46),
+        ] {
+            assert_eq!(eval(source), Ok(Value::Constant(value)), "{source}");
+        }
+    }
+
+    #[test]
+    fn reports_each_error_variant() {
+        assert_eq!(eval("1 << 32"), Err(ExprError::InvalidShift(32)));
+        assert_eq!(eval("1 >> -1"), Err(ExprError::InvalidShift(-1)));
+        assert_eq!(eval("FOUR % (2 - 2)"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("missing + 1"), Err(ExprError::UnknownSymbol("missing".to_string())));
+        assert_eq!(eval("~start"), Err(ExprError::NotConstant("start".to_string())));
+        assert_eq!(eval("start * 2"), Err(ExprError::NotConstant("start".to_string())));
+        assert_eq!(eval("end - start"), Err(ExprError::NotConstant("end".to_string())));
+        assert_eq!(eval("hi(start) + 1"), Err(ExprError::AddressPart("start".to_string())));
+        assert_eq!(eval("lo(end) | 0"), Err(ExprError::AddressPart("end".to_string())));
+        assert_eq!(eval("hi(lo(start))"), Err(ExprError::AddressPart("start".to_string())));
+    }
+
+    #[test]
+    fn folds_labels_and_constants() {
+        assert_eq!(eval("end - end"), Ok(Value::Constant(0)));
+        assert_eq!(eval("(start + 6) - (start + 2)"), Ok(Value::Constant(4)));
+        assert_eq!(eval("4 + start - 1"), Ok(relative("start", 3, AddressPart::Whole)));
+        assert_eq!(eval("end - FOUR * 2"), Ok(relative("end", -8, AddressPart::Whole)));
+        assert_eq!(eval("hi(start + 2)"), Ok(relative("start", 2, AddressPart::High)));
+        assert_eq!(eval("lo(end)"), Ok(relative("end", 0, AddressPart::Low)));
+    }
+
+    #[test]
+    fn memory_offsets_take_labels_and_four_bits() {
+        let obj = assemble_source("    ld r4, [r1 + table + 1]\ntable:\n", "m.asm").unwrap();
+        let relocation = &obj.relocations[0];
+        assert_eq!((relocation.symbol.as_str(), relocation.addend, relocation.kind), ("table", 1, RelocationKind::Offset4));
+
+        let error = assemble_source("    ld r4, [r1 + 8]\n", "m.asm").err();
+        assert!(matches!(error, Some(AssemblerError::ParseError(ParseError::ImmediateOutOfRange { value: 8, min: -5, max: 7, .. }))));
+        let error = assemble_source("    ld r4, [r1 + hi(table)]\ntable:\n", "m.asm").err();
+        assert!(matches!(error, Some(AssemblerError::ParseError(ParseError::InvalidExpression { .. }))));
+    }
+}
@@ -1,5 +1,7 @@
+#[allow(clippy::module_inception)]
 mod parser;
 pub mod symbols;
+pub mod expr;
 mod error;
 
 pub use parser::Parser;
@@ -1,7 +1,8 @@
 use atlas_isa::{AluOp, BranchCond, BranchOperand, ImmOp, Mnemonic, MemOp, Operand, PeekPokeOp, ParsedInstruction, StackOp, XTypeOp, instruction::InstructionFormat, operands::{MOffset, RegisterPairIdentifier, XOperand}};
-use crate::lexer::{Directive, LexError, Lexer, SpannedToken, Token};
+use crate::lexer::{Directive, LexError, Lexer, Operator, SpannedToken, Token, Immediate};
 
 use crate::{parser::error::ParseError, parser::symbols::{ParsedItem, SymbolTable}};
+use crate::parser::expr::{BinaryOp, Expr, UnaryOp, in_range};
 
 
 pub struct Parser<'a> {
@@ -13,6 +14,9 @@ pub struct Parser<'a> {
     pending: Option<SpannedToken>,
     /// The current section (defaults to ".text").
     current_section: String,
+    /// Whether out-of-range label branches may be relaxed (cleared by
+    /// `.norelax`).
+    relax: bool,
 }
 
 impl<'a> Iterator for Parser<'a> {
@@ -37,9 +41,9 @@ impl<'a> Iterator for Parser<'a> {
             Token::Directive(name) => {
                 // handle directives
                 match self.handle_directive(name) {
-                    Ok(Some(item)) => return Some(Ok(item)),
-                    Ok(None) => return self.next(),
-                    Err(err) => return Some(Err(err)),
+                    Ok(Some(item)) => Some(Ok(item)),
+                    Ok(None) => self.next(),
+                    Err(err) => Some(Err(err)),
                 }
             }
             Token::LabelDef(name) => {
@@ -52,21 +56,11 @@ impl<'a> Iterator for Parser<'a> {
 
                 match next {
                     Some(SpannedToken { token: Token::Directive(Directive::Imm), .. }) => {
-                        // label: .imm <value>
-                        let val_tok = match self.next_token() {
-                            Ok(t) => t,
+                        // label: .imm <expression>
+                        let value = match self.expect_constant(-32768, 65535) {
+                            Ok(value) => value as u16,
                             Err(e) => return Some(Err(e)),
                         };
-                        let value = match val_tok.token {
-                            Token::Immediate(imm) => imm.value as u16,
-                            other => {
-                                return Some(Err(ParseError::UnexpectedToken {
-                                    line: val_tok.span.line,
-                                    expected: "immediate value after .imm",
-                                    found: Self::token_description(&other),
-                                }));
-                            }
-                        };
                         self.symbols.insert(name, crate::parser::symbols::Symbol::Constant(value));
                     }
                     Some(tok) => {
@@ -89,9 +83,9 @@ impl<'a> Iterator for Parser<'a> {
             Token::Mnemonic(mnemonic) => {
                 let result = self.process_instruction(mnemonic, spanned.span.line);
                 match result {
-                    Ok(instr) => {
+                    Ok((instr, operand)) => {
                         self.pos += 2;
-                        Some(Ok(ParsedItem::Instruction(instr)))
+                        Some(Ok(ParsedItem::Instruction(instr, operand)))
                     }
                     Err(e) => Some(Err(e)),
                 }
@@ -117,6 +111,7 @@ impl<'a> Parser<'a> {
             last_line: 1,
             pending: None,
             current_section: ".text".to_string(),
+            relax: true,
         }
     }
 
@@ -124,6 +119,11 @@ impl<'a> Parser<'a> {
         &self.symbols
     }
 
+    /// False once the source has said `.norelax`.
+    pub fn relaxes(&self) -> bool {
+        self.relax
+    }
+
     fn skip_to_line_end(&mut self) -> Result<(), ParseError> {
         loop {
             match self.lexer.next() {
@@ -221,15 +221,21 @@ impl<'a> Parser<'a> {
                 Ok(Some(ParsedItem::Data(data)))
             }
             Directive::Word => {
-                let data = self.collect_word_list()?;
-                self.pos += data.len() as u32;
-                Ok(Some(ParsedItem::Data(data)))
+                let line = self.last_line;
+                let values = self.collect_word_list()?;
+                self.pos += 2 * values.len() as u32;
+                Ok(Some(ParsedItem::Words { values, line }))
             }
             Directive::Ascii => {
                 let data = self.collect_ascii_string()?;
                 self.pos += data.len() as u32;
                 Ok(Some(ParsedItem::Data(data)))
             }
+            Directive::NoRelax => {
+                self.relax = false;
+                self.skip_to_line_end()?;
+                Ok(None)
+            }
         }
     }
 
@@ -237,28 +243,11 @@ impl<'a> Parser<'a> {
     fn collect_byte_list(&mut self) -> Result<Vec<u8>, ParseError> {
         let mut bytes = Vec::new();
         loop {
-            let tok = self.next_token()?;
-            match tok.token {
-                Token::Immediate(imm) => {
-                    if imm.value < -128 || imm.value > 255 {
-                        return Err(ParseError::ImmediateOutOfRange {
-                            line: tok.span.line,
-                            value: imm.value,
-                            min: -128,
-                            max: 255,
-                        });
-                    }
-                    bytes.push(imm.value as u8);
-                }
-                Token::NewLine | Token::EoF => break,
-                other => {
-                    return Err(ParseError::UnexpectedToken {
-                        line: tok.span.line,
-                        expected: "byte value",
-                        found: Self::token_description(&other),
-                    });
-                }
+            if matches!(self.peek_token()?.token, Token::NewLine | Token::EoF) {
+                self.next_token()?;
+                break;
             }
+            bytes.push(self.expect_constant(-128, 255)? as u8);
             // check for comma or end of line
             let next = self.next_token()?;
             match next.token {
@@ -276,35 +265,17 @@ impl<'a> Parser<'a> {
         Ok(bytes)
     }
 
-    /// Collect a comma-separated list of 16-bit word values: `.word 0x1234, 0x5678`
-    fn collect_word_list(&mut self) -> Result<Vec<u8>, ParseError> {
-        let mut bytes = Vec::new();
+    /// Collect a comma-separated list of 16-bit word expressions:
+    /// `.word 0x1234, table + 2`. They may refer to labels, so they are
+    /// evaluated in pass 2.
+    fn collect_word_list(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut values = Vec::new();
         loop {
-            let tok = self.next_token()?;
-            match tok.token {
-                Token::Immediate(imm) => {
-                    if imm.value < -32768 || imm.value > 65535 {
-                        return Err(ParseError::ImmediateOutOfRange {
-                            line: tok.span.line,
-                            value: imm.value,
-                            min: -32768,
-                            max: 65535,
-                        });
-                    }
-                    let word = imm.value as u16;
-                    // little-endian
-                    bytes.push(word as u8);
-                    bytes.push((word >> 8) as u8);
-                }
-                Token::NewLine | Token::EoF => break,
-                other => {
-                    return Err(ParseError::UnexpectedToken {
-                        line: tok.span.line,
-                        expected: "word value",
-                        found: Self::token_description(&other),
-                    });
-                }
+            if matches!(self.peek_token()?.token, Token::NewLine | Token::EoF) {
+                self.next_token()?;
+                break;
             }
+            values.push(self.expect_expression()?);
             // check for comma or end of line
             let next = self.next_token()?;
             match next.token {
@@ -319,7 +290,7 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-        Ok(bytes)
+        Ok(values)
     }
 
     /// Collect an ASCII string literal. Since the lexer doesn't have string tokens yet,
@@ -348,6 +319,15 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Look at the next token without consuming it.
+    fn peek_token(&mut self) -> Result<&SpannedToken, ParseError> {
+        if self.pending.is_none() {
+            let token = self.next_token()?;
+            self.pending = Some(token);
+        }
+        Ok(self.pending.as_ref().expect("token was just buffered"))
+    }
+
     fn expect_register(&mut self) -> Result<atlas_isa::RegisterIdentifier, ParseError> {
         let token = self.next_token()?;
         match token.token {
@@ -363,20 +343,79 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Expect either an immediate value or a label reference, returning an `Operand`.
-    fn expect_immediate_or_label(&mut self) -> Result<Operand, ParseError> {
+    /// Expect an expression; see [`crate::parser::expr`].
+    fn expect_expression(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(0)
+    }
+
+    /// Parse operands joined by binary operators that bind at least as
+    /// tightly as `min_precedence`.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut lhs = self.parse_unary()?;
+        while let Token::Operator(operator) = self.peek_token()?.token {
+            let Some(op) = BinaryOp::from_operator(operator) else {
+                break;
+            };
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.next_token()?;
+            let rhs = self.parse_binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
         let token = self.next_token()?;
         match token.token {
-            Token::Immediate(imm) => Ok(Operand::Immediate(imm.value as u16)),
-            Token::LabelRef(name) => Ok(Operand::Label(name)),
+            Token::Immediate(imm) => Ok(Expr::Number(imm.value)),
+            Token::LabelRef(name) if matches!(name.as_str(), "hi" | "lo")
+                && matches!(self.peek_token()?.token, Token::OpenParen) =>
+            {
+                let op = if name == "hi" { UnaryOp::Hi } else { UnaryOp::Lo };
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            Token::LabelRef(name) => Ok(Expr::Symbol(name)),
+            Token::Operator(Operator::Minus) => Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.parse_unary()?))),
+            Token::Operator(Operator::Tilde) => Ok(Expr::Unary(UnaryOp::Not, Box::new(self.parse_unary()?))),
+            Token::Operator(Operator::Plus) => self.parse_unary(),
+            Token::OpenParen => {
+                let expr = self.expect_expression()?;
+                let close = self.next_token()?;
+                match close.token {
+                    Token::CloseParen => Ok(expr),
+                    other => Err(ParseError::UnexpectedToken {
+                        line: close.span.line,
+                        expected: "')'",
+                        found: Self::token_description(&other),
+                    }),
+                }
+            }
             other => Err(ParseError::UnexpectedToken {
                 line: token.span.line,
-                expected: "immediate or label",
+                expected: "expression",
                 found: Self::token_description(&other),
             }),
         }
     }
 
+    /// Expect an expression that evaluates to a constant in `min..=max`.
+    /// Only constants defined earlier in the file can be referenced.
+    fn expect_constant(&mut self, min: i32, max: i32) -> Result<i32, ParseError> {
+        let line = self.peek_token()?.span.line;
+        let expr = self.expect_expression()?;
+        self.constant(&expr, line, min, max)
+    }
+
+    fn constant(&self, expr: &Expr, line: usize, min: i32, max: i32) -> Result<i32, ParseError> {
+        let value = expr
+            .evaluate(&|name| self.symbols.value(name))
+            .and_then(|value| value.constant())
+            .map_err(|e| e.at_line(line))?;
+        in_range(value, min, max, line)
+    }
+
     fn expect_comma(&mut self) -> Result<(), ParseError> {
         let token = self.next_token()?;
         match token.token {
@@ -401,7 +440,10 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn process_instruction(&mut self, instruction: Mnemonic, line: usize) -> Result<ParsedInstruction, ParseError> {
+    /// Parse the operands of `instruction`. The expression of an I, BI or P
+    /// operand is returned alongside the instruction, whose operand is left
+    /// as a placeholder.
+    fn process_instruction(&mut self, instruction: Mnemonic, line: usize) -> Result<(ParsedInstruction, Option<Expr>), ParseError> {
         match instruction.get_type() {
             InstructionFormat::A => {
                 // A-type: rd, rs
@@ -424,19 +466,19 @@ impl<'a> Parser<'a> {
                     });
                 }
                 
-                Ok(ParsedInstruction::A {
+                Ok((ParsedInstruction::A {
                     op,
                     dest: rd,
                     source: rs,
                     line,
                     source_file: None,
-                })
+                }, None))
             },
             InstructionFormat::I => {
-                // I-type: rd, immediate or label
+                // I-type: rd, expression
                 let rd = self.expect_register()?;
                 self.expect_comma()?;
-                let imm = self.expect_immediate_or_label()?;
+                let imm = self.expect_expression()?;
                 self.expect_newline()?;
 
                 let op = ImmOp::from_instruction(instruction)
@@ -452,13 +494,13 @@ impl<'a> Parser<'a> {
                     });
                 }
 
-                Ok(ParsedInstruction::I {
+                Ok((ParsedInstruction::I {
                     op,
                     dest: rd,
-                    immediate: imm,
+                    immediate: Operand::Immediate(0),
                     line,
                     source_file: None,
-                })
+                }, Some(imm)))
             },
             InstructionFormat::M => {
                 // M-type: rd, [base + offset]
@@ -485,8 +527,8 @@ impl<'a> Parser<'a> {
                 let op_token = self.next_token()?;
                 let op = match op_token.token {
                     Token::Comma => "+",
-                    Token::LabelRef(ref name) if name == "+" => "+",
-                    Token::LabelRef(ref name) if name == "-" => "-",
+                    Token::Operator(Operator::Plus) => "+",
+                    Token::Operator(Operator::Minus) => "-",
                     other => {
                         return Err(ParseError::UnexpectedToken {
                             line: op_token.span.line,
@@ -496,42 +538,30 @@ impl<'a> Parser<'a> {
                     }
                 };
 
-                // Get offset: register or immediate
-                let offset_token = self.next_token()?;
-                let offset = match offset_token.token {
-                    Token::Register(reg) => {
+                // Get offset: register or constant expression
+                let offset_line = self.peek_token()?.span.line;
+                let mut offset_expr = None;
+                let offset = match self.peek_token()?.token {
+                    Token::Register(_) => {
+                        let reg = self.expect_register()?;
                         if op == "-" {
                             return Err(ParseError::InvalidParameters {
-                                line: offset_token.span.line,
+                                line: offset_line,
                                 details: "negative register offsets are not supported".to_string(),
                             });
                         }
                         MOffset::SR(reg)
                     }
-                    Token::Immediate(imm) => {
-                        let mut imm_val = imm.value;
-                        if op == "-" && imm_val > 0 {
-                            imm_val = -imm_val;
-                        }
-                        if imm_val < -5 || imm_val > 7 {
-                            return Err(ParseError::ImmediateOutOfRange {
-                                line: offset_token.span.line,
-                                value: imm_val,
-                                min: -5,
-                                max: 7,
-                            });
+                    _ => {
+                        let mut expr = self.expect_expression()?;
+                        if op == "-" {
+                            expr = Expr::Unary(UnaryOp::Neg, Box::new(expr));
                         }
-                        // Map special offsets to SPR codes via negative sentinels
-                        // (values -6, -7, -8 are outside the valid range and
-                        //  correspond to the reserved SPR codes in the 4-bit field)
-                        MOffset::Offset8(imm_val as u8)
-                    }
-                    other => {
-                        return Err(ParseError::UnexpectedToken {
-                            line: offset_token.span.line,
-                            expected: "offset immediate or register",
-                            found: Self::token_description(&other),
-                        });
+                        // Evaluated in pass 2, which checks it against -5..=7
+                        // (-6, -7 and -8 are the reserved SPR codes of the
+                        // 4-bit field)
+                        offset_expr = Some(expr);
+                        MOffset::Offset8(0)
                     }
                 };
 
@@ -563,14 +593,14 @@ impl<'a> Parser<'a> {
                     });
                 }
 
-                Ok(ParsedInstruction::M {
+                Ok((ParsedInstruction::M {
                     op,
                     dest: rd,
                     base,
                     offset,
                     line,
                     source_file: None,
-                })
+                }, offset_expr))
             },
             InstructionFormat::B => {
                 // B-type: condition and either immediate address, label, or register pair
@@ -580,83 +610,50 @@ impl<'a> Parser<'a> {
                         details: format!("Instruction '{}' is not a valid branch op", instruction.mnemonic()),
                     })?;
 
-                let next_tok = self.next_token()?;
-                
-                match next_tok.token {
-                    Token::Immediate(imm) => {
-                        // Check if signed (+/-) to determine if relative or absolute
-                        if imm.signed {
-                            // Relative offset branch
-                            if imm.value < -128 || imm.value > 127 {
-                                return Err(ParseError::ImmediateOutOfRange {
-                                    line: next_tok.span.line,
-                                    value: imm.value,
-                                    min: -128,
-                                    max: 127,
-                                });
-                            }
-                            self.expect_newline()?;
-                            Ok(ParsedInstruction::BI {
-                                absolute: false,
-                                cond,
-                                operand: BranchOperand::Immediate(imm.value as u16),
-                                line,
-                                source_file: None,
-                            })
-                        } else {
-                            // Absolute immediate branch
-                            self.expect_newline()?;
-                            Ok(ParsedInstruction::BI {
-                                absolute: true,
-                                cond,
-                                operand: BranchOperand::Immediate(imm.value as u16),
-                                line,
-                                source_file: None,
-                            })
-                        }
-                    },
-                    Token::LabelRef(label_name) => {
-                        // Branch to label - store label reference (do NOT resolve yet)
-                        self.expect_newline()?;
-                        
-                        // Branches to labels are always absolute (will be resolved by linker)
-                        Ok(ParsedInstruction::BI {
-                            absolute: true,
-                            cond,
-                            operand: BranchOperand::Label(label_name),
-                            line,
-                            source_file: None,
-                        })
-                    },
+                match self.peek_token()?.token {
                     Token::Register(reg1) => {
                         // Register pair branch (reg1 and next register)
+                        self.next_token()?;
                         self.expect_comma()?;
                         let reg2 = self.expect_register()?;
                         self.expect_newline()?;
-                        
-                        Ok(ParsedInstruction::BR {
+
+                        Ok((ParsedInstruction::BR {
                             absolute: true,
                             cond,
                             source: RegisterPairIdentifier { high: reg1, low: reg2 },
                             line,
                             source_file: None,
-                        })
+                        }, None))
+                    },
+                    ref token => {
+                        // A leading sign (`+4`, `-(2 * 3)`) makes a constant
+                        // branch relative; any other constant is an absolute
+                        // target. Branches to labels are always relative and
+                        // are lowered in pass 2.
+                        let absolute = !matches!(
+                            token,
+                            Token::Immediate(Immediate { signed: true, .. })
+                                | Token::Operator(Operator::Plus | Operator::Minus)
+                        );
+                        let operand = self.expect_expression()?;
+                        self.expect_newline()?;
+
+                        Ok((ParsedInstruction::BI {
+                            absolute,
+                            cond,
+                            operand: BranchOperand::Immediate(0),
+                            line,
+                            source_file: None,
+                        }, Some(operand)))
                     },
-                    other => {
-                        Err(ParseError::UnexpectedToken {
-                            line: next_tok.span.line,
-                            expected: "immediate, label, or register",
-                            found: Self::token_description(&other),
-                        })
-                    }
                 }
             },
             InstructionFormat::S => {
                 // S-type: push/pop take a register; subsp/addsp take register OR immediate
-                let next_tok = self.next_token()?;
-
                 match instruction {
                     Mnemonic::PUSH => {
+                        let next_tok = self.next_token()?;
                         let reg = match next_tok.token {
                             Token::Register(r) => r,
                             other => return Err(ParseError::UnexpectedToken {
@@ -666,14 +663,15 @@ impl<'a> Parser<'a> {
                             }),
                         };
                         self.expect_newline()?;
-                        Ok(ParsedInstruction::S {
+                        Ok((ParsedInstruction::S {
                             op: StackOp::PUSH,
                             operand: reg,
                             line,
                             source_file: None,
-                        })
+                        }, None))
                     }
                     Mnemonic::POP => {
+                        let next_tok = self.next_token()?;
                         let reg = match next_tok.token {
                             Token::Register(r) => r,
                             other => return Err(ParseError::UnexpectedToken {
@@ -689,82 +687,33 @@ impl<'a> Parser<'a> {
                             });
                         }
                         self.expect_newline()?;
-                        Ok(ParsedInstruction::S {
+                        Ok((ParsedInstruction::S {
                             op: StackOp::POP,
                             operand: reg,
                             line,
                             source_file: None,
-                        })
-                    }
-                    Mnemonic::SUBSP => {
-                        match next_tok.token {
-                            Token::Register(reg) => {
-                                self.expect_newline()?;
-                                Ok(ParsedInstruction::S {
-                                    op: StackOp::SUBSP_REG,
-                                    operand: reg,
-                                    line,
-                                    source_file: None,
-                                })
-                            }
-                            Token::Immediate(imm) => {
-                                if imm.value < 0 || imm.value > 255 {
-                                    return Err(ParseError::ImmediateOutOfRange {
-                                        line: self.last_line,
-                                        value: imm.value,
-                                        min: 0,
-                                        max: 255,
-                                    });
-                                }
-                                self.expect_newline()?;
-                                Ok(ParsedInstruction::S {
-                                    op: StackOp::SUBSP_IMM,
-                                    operand: imm.value as u8,
-                                    line,
-                                    source_file: None,
-                                })
-                            }
-                            other => Err(ParseError::UnexpectedToken {
-                                line: self.last_line,
-                                expected: "register or immediate",
-                                found: Self::token_description(&other),
-                            }),
-                        }
+                        }, None))
                     }
-                    Mnemonic::ADDSP => {
-                        match next_tok.token {
-                            Token::Register(reg) => {
-                                self.expect_newline()?;
-                                Ok(ParsedInstruction::S {
-                                    op: StackOp::ADDSP_REG,
-                                    operand: reg,
-                                    line,
-                                    source_file: None,
-                                })
+                    Mnemonic::SUBSP | Mnemonic::ADDSP => {
+                        let (op, operand) = match self.peek_token()?.token {
+                            Token::Register(_) => {
+                                let reg = self.expect_register()?;
+                                let op = if instruction == Mnemonic::SUBSP { StackOp::SUBSP_REG } else { StackOp::ADDSP_REG };
+                                (op, reg)
                             }
-                            Token::Immediate(imm) => {
-                                if imm.value < 0 || imm.value > 255 {
-                                    return Err(ParseError::ImmediateOutOfRange {
-                                        line: self.last_line,
-                                        value: imm.value,
-                                        min: 0,
-                                        max: 255,
-                                    });
-                                }
-                                self.expect_newline()?;
-                                Ok(ParsedInstruction::S {
-                                    op: StackOp::ADDSP_IMM,
-                                    operand: imm.value as u8,
-                                    line,
-                                    source_file: None,
-                                })
+                            _ => {
+                                let value = self.expect_constant(0, 255)?;
+                                let op = if instruction == Mnemonic::SUBSP { StackOp::SUBSP_IMM } else { StackOp::ADDSP_IMM };
+                                (op, value as u8)
                             }
-                            other => Err(ParseError::UnexpectedToken {
-                                line: self.last_line,
-                                expected: "register or immediate",
-                                found: Self::token_description(&other),
-                            }),
-                        }
+                        };
+                        self.expect_newline()?;
+                        Ok((ParsedInstruction::S {
+                            op,
+                            operand,
+                            line,
+                            source_file: None,
+                        }, None))
                     }
                     _ => Err(ParseError::InvalidParameters {
                         line,
@@ -773,10 +722,10 @@ impl<'a> Parser<'a> {
                 }
             },
             InstructionFormat::P => {
-                // P-type: register, offset (immediate or label)
+                // P-type: register, offset expression
                 let reg = self.expect_register()?;
                 self.expect_comma()?;
-                let offset = self.expect_immediate_or_label()?;
+                let offset = self.expect_expression()?;
                 self.expect_newline()?;
 
                 let op = PeekPokeOp::from_instruction(instruction)
@@ -792,28 +741,24 @@ impl<'a> Parser<'a> {
                     });
                 }
 
-                Ok(ParsedInstruction::P {
+                Ok((ParsedInstruction::P {
                     op,
                     register: reg,
-                    offset,
+                    offset: Operand::Immediate(0),
                     line,
                     source_file: None,
-                })
+                }, Some(offset)))
             },
             InstructionFormat::X => {
                 // X-type: may have no operands or various operand formats
-                let next_tok = self.next_token()?;
-                
-                let operand = match next_tok.token {
+                let operand = match self.peek_token()?.token {
                     Token::NewLine | Token::EoF => {
                         // No operands
+                        self.next_token()?;
                         XOperand::None
                     },
-                    Token::Immediate(imm) => {
-                        self.expect_newline()?;
-                        XOperand::Immediate(imm.value as u8)
-                    },
                     Token::Register(reg) => {
+                        self.next_token()?;
                         // Check if followed by comma for register pair
                         let check_next = self.next_token()?;
                         match check_next.token {
@@ -834,12 +779,10 @@ impl<'a> Parser<'a> {
                             }
                         }
                     },
-                    other => {
-                        return Err(ParseError::UnexpectedToken {
-                            line: next_tok.span.line,
-                            expected: "immediate, register, or end of line",
-                            found: Self::token_description(&other),
-                        });
+                    _ => {
+                        let value = self.expect_constant(-128, 255)?;
+                        self.expect_newline()?;
+                        XOperand::Immediate(value as u8)
                     }
                 };
 
@@ -849,25 +792,25 @@ impl<'a> Parser<'a> {
                         details: format!("Instruction '{}' is not a valid extended op", instruction.mnemonic()),
                     })?;
 
-                Ok(ParsedInstruction::X {
+                Ok((ParsedInstruction::X {
                     op,
                     operand,
                     line,
                     source_file: None,
-                })
+                }, None))
             },
             InstructionFormat::Virtual => {
                 match instruction {
                     Mnemonic::NOP => {
                         // NOP = add r0, r0  (encodes as 0x0000)
                         self.expect_newline()?;
-                        Ok(ParsedInstruction::A {
+                        Ok((ParsedInstruction::A {
                             op: AluOp::ADD,
                             dest: 0,
                             source: 0,
                             line,
                             source_file: None,
-                        })
+                        }, None))
                     }
                     Mnemonic::INC => {
                         // INC rd = addi rd, 1
@@ -879,13 +822,13 @@ impl<'a> Parser<'a> {
                                 instruction: instruction.mnemonic().to_string(),
                             });
                         }
-                        Ok(ParsedInstruction::I {
+                        Ok((ParsedInstruction::I {
                             op: ImmOp::ADDI,
                             dest: rd,
                             immediate: Operand::Immediate(1),
                             line,
                             source_file: None,
-                        })
+                        }, None))
                     }
                     Mnemonic::DEC => {
                         // DEC rd = subi rd, 1
@@ -897,13 +840,13 @@ impl<'a> Parser<'a> {
                                 instruction: instruction.mnemonic().to_string(),
                             });
                         }
-                        Ok(ParsedInstruction::I {
+                        Ok((ParsedInstruction::I {
                             op: ImmOp::SUBI,
                             dest: rd,
                             immediate: Operand::Immediate(1),
                             line,
                             source_file: None,
-                        })
+                        }, None))
                     }
                     _ => {
                         Err(ParseError::InvalidParameters {
@@ -930,6 +873,7 @@ impl<'a> Parser<'a> {
             },
             Token::LabelDef(name) => format!("label definition '{}'", name),
             Token::LabelRef(name) => format!("label reference '{}'", name),
+            Token::Operator(op) => format!("'{}'", op.symbol()),
             Token::Comma => ",".to_string(),
             Token::AtSign => "'@'".to_string(),
             Token::OpenParen => "'('".to_string(),
@@ -1,6 +1,9 @@
 use std::collections::{HashMap, HashSet};
+use atlas_files::RelocationKind;
 use atlas_isa::ParsedInstruction;
 
+use crate::parser::expr::{Expr, Value};
+
 #[derive(Debug, Clone)]
 pub enum Symbol {
     Label { offset: u32, section: String },
@@ -10,8 +13,13 @@ pub enum Symbol {
 /// An item emitted by the parser: either an instruction or raw data bytes.
 #[derive(Debug)]
 pub enum ParsedItem {
-    Instruction(ParsedInstruction),
+    /// An instruction, with the expression of its I, BI or P operand or M
+    /// offset. The expression is evaluated in pass 2, once every symbol is
+    /// known.
+    Instruction(ParsedInstruction, Option<Expr>),
     Data(Vec<u8>),
+    /// The values of a `.word` directive, evaluated in pass 2.
+    Words { values: Vec<Expr>, line: usize },
     SectionChange(String),
 }
 
@@ -25,8 +33,10 @@ pub struct UnresolvedReference {
     pub section: String,
     /// Name of the referenced symbol.
     pub symbol: String,
-    /// Addend (usually 0).
+    /// Constant added to the symbol's address (`label + 4`).
     pub addend: i32,
+    /// How the linker patches the value in.
+    pub kind: RelocationKind,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -80,6 +90,26 @@ impl SymbolTable {
         self.symbols.get(name)
     }
 
+    /// The value of `name` in an expression. Constants are known; labels and
+    /// imports are relative to an address the linker assigns.
+    pub fn value(&self, name: &str) -> Option<Value> {
+        match self.symbols.get(name) {
+            Some(Symbol::Constant(value)) => Some(Value::Constant(i32::from(*value))),
+            Some(Symbol::Label { .. }) => Some(Value::label(name)),
+            None if self.is_imported(name) => Some(Value::label(name)),
+            None => None,
+        }
+    }
+
+    /// Move every label to `new_offset(section, offset)`.
+    pub fn move_labels(&mut self, new_offset: impl Fn(&str, u32) -> u32) {
+        for symbol in self.symbols.values_mut() {
+            if let Symbol::Label { offset, section } = symbol {
+                *offset = new_offset(section, *offset);
+            }
+        }
+    }
+
     pub fn iter(&self) -> std::collections::hash_map::Iter<'_, String, Symbol> {
         self.symbols.iter()
     }
@@ -0,0 +1,163 @@
+//! Debugger commands and their parser.
+//!
+//! Locations stay unresolved strings here; the [`Debugger`] turns them into
+//! addresses against the image's symbols.
+//!
+//! [`Debugger`]: crate::debugger::Debugger
+
+use atlas_sim::WatchKind;
+
+use crate::error::DebugError;
+
+/// Bytes shown by `x` without an explicit length.
+pub const DEFAULT_DUMP_LENGTH: u16 = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `break <location>`
+    Break(String),
+    /// `delete [<location>]`; without a location, delete all breakpoints.
+    Delete(Option<String>),
+    /// `breakpoints`: list breakpoints and watchpoints.
+    Breakpoints,
+    /// `watch`, `rwatch` or `awatch <location>[..<end>]`
+    Watch { kind: WatchKind, range: String },
+    /// `unwatch [<location>]`; without a location, delete all watchpoints.
+    Unwatch(Option<String>),
+    /// `step [<count>]`
+    Step(u64),
+    /// `next`: step over the current instruction.
+    Next,
+    /// `continue`
+    Continue,
+    /// `reverse-step [<count>]`: undo instructions.
+    ReverseStep(u64),
+    /// `reverse-continue`: run backwards to the previous breakpoint.
+    ReverseContinue,
+    /// `regs`
+    Registers,
+    /// `x <location> [<length>]`
+    Memory { location: String, length: u16 },
+    /// `list [<location>]`: disassemble around a location, PC by default.
+    List(Option<String>),
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, DebugError> {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Err(DebugError::UnknownCommand(String::new()));
+        };
+        let mut arg = || words.next().map(str::to_string);
+
+        Ok(match name {
+            "b" | "break" => Command::Break(arg().ok_or(DebugError::MissingArgument {
+                command: "break",
+                argument: "an address or symbol",
+            })?),
+            "d" | "delete" => Command::Delete(arg()),
+            "bl" | "breakpoints" => Command::Breakpoints,
+            "w" | "watch" | "rwatch" | "awatch" => {
+                let kind = match name {
+                    "rwatch" => WatchKind::Read,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+                let range = arg().ok_or(DebugError::MissingArgument {
+                    command: "watch",
+                    argument: "an address, symbol or range",
+                })?;
+                Command::Watch { kind, range }
+            }
+            "unwatch" => Command::Unwatch(arg()),
+            "s" | "step" => Command::Step(match arg() {
+                Some(count) => parse_number(&count)? as u64,
+                None => 1,
+            }),
+            "n" | "next" => Command::Next,
+            "c" | "continue" => Command::Continue,
+            "rs" | "reverse-step" => Command::ReverseStep(match arg() {
+                Some(count) => parse_number(&count)? as u64,
+                None => 1,
+            }),
+            "rc" | "reverse-continue" => Command::ReverseContinue,
+            "r" | "regs" => Command::Registers,
+            "x" => {
+                let location = arg().ok_or(DebugError::MissingArgument {
+                    command: "x",
+                    argument: "an address or symbol",
+                })?;
+                let length = match arg() {
+                    Some(length) => u16::try_from(parse_number(&length)?)
+                        .map_err(|_| DebugError::InvalidAddress(length))?,
+                    None => DEFAULT_DUMP_LENGTH,
+                };
+                Command::Memory { location, length }
+            }
+            "l" | "list" | "disas" => Command::List(arg()),
+            "h" | "help" | "?" => Command::Help,
+            "q" | "quit" | "exit" => Command::Quit,
+            other => return Err(DebugError::UnknownCommand(other.to_string())),
+        })
+    }
+}
+
+/// Parse a `0x`-prefixed hexadecimal or a decimal number.
+pub fn parse_number(text: &str) -> Result<u32, DebugError> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| DebugError::InvalidNumber(text.to_string()))
+}
+
+pub const HELP: &str = "\
+  break <loc>      set a breakpoint (b)
+  delete [<loc>]   delete one or all breakpoints (d)
+  breakpoints      list breakpoints and watchpoints (bl)
+  watch <range>    stop when a store writes to <range> (w)
+  rwatch <range>   stop when a load reads from <range>
+  awatch <range>   stop on any load or store in <range>
+  unwatch [<loc>]  delete the watchpoints covering loc, or all
+  step [<n>]       execute n instructions (s)
+  next             step over the current instruction (n)
+  continue         run to the next breakpoint or exit (c)
+  rs [<n>]         reverse-step: undo n instructions
+  rc               reverse-continue: run back to the previous breakpoint
+  regs             show registers (r)
+  x <loc> [<len>]  dump memory
+  list [<loc>]     disassemble around a location or PC (l)
+  quit             leave the debugger (q)
+
+  <loc> is an address (0x1a, 26), a symbol (loop), symbol+offset (main+4)
+  or a source line (main.asm:12).
+  <range> is a <loc> or a half-open range <loc>..<loc> (0x80..0x84).
+  An empty line repeats the previous command.";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_with_aliases_and_defaults() {
+        assert_eq!(Command::parse("b main+4"), Ok(Command::Break("main+4".into())));
+        assert_eq!(Command::parse("step 0x10"), Ok(Command::Step(16)));
+        assert_eq!(Command::parse("s"), Ok(Command::Step(1)));
+        assert_eq!(
+            Command::parse("x 0x80"),
+            Ok(Command::Memory { location: "0x80".into(), length: DEFAULT_DUMP_LENGTH })
+        );
+        assert_eq!(
+            Command::parse("break"),
+            Err(DebugError::MissingArgument { command: "break", argument: "an address or symbol" })
+        );
+        assert_eq!(
+            Command::parse("rwatch 0x80..0x84"),
+            Ok(Command::Watch { kind: WatchKind::Read, range: "0x80..0x84".into() })
+        );
+        assert_eq!(Command::parse("x 0x80 0x10000"), Err(DebugError::InvalidAddress("0x10000".into())));
+        assert_eq!(Command::parse("jump"), Err(DebugError::UnknownCommand("jump".into())));
+    }
+}
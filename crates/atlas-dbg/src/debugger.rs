@@ -0,0 +1,421 @@
+//! Breakpoints and execution control on top of the simulator.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use atlas_files::SourceLine;
+use atlas_inspect::{bold, dim, format_word, symbolize};
+use atlas_sim::history::DEFAULT_SNAPSHOT_INTERVAL;
+use atlas_sim::{Access, ExitReason, SimError, Simulator, StepResult, WatchHit, Watchpoint};
+
+use crate::command::parse_number;
+use crate::error::DebugError;
+
+/// Why execution stopped and control returned to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested steps completed.
+    Stepped,
+    /// PC reached a breakpoint.
+    Breakpoint(u16),
+    /// The last instruction accessed watched memory.
+    Watchpoint(Vec<WatchHit>),
+    /// The program halted, trapped to the host or ran out of steps.
+    Exited(ExitReason),
+    /// Stepping backwards reached the first recorded step.
+    StartOfHistory,
+}
+
+pub struct Debugger {
+    pub sim: Simulator,
+    /// Address → name of the image's labels.
+    pub symbols: BTreeMap<u16, String>,
+    /// Address → source line of the image's instructions.
+    pub lines: BTreeMap<u16, SourceLine>,
+    /// Lines of the source files named in `lines`, where readable.
+    sources: BTreeMap<String, Vec<String>>,
+    breakpoints: BTreeSet<u16>,
+    /// Upper bound on the instructions one `continue` or `next` executes.
+    pub max_steps: u64,
+}
+
+impl Debugger {
+    /// Takes over `sim` and starts recording its history, so that every
+    /// step from here on can be undone. Machines with memory-mapped devices
+    /// record no history and cannot step backwards.
+    pub fn new(mut sim: Simulator, symbols: BTreeMap<u16, String>) -> Self {
+        sim.record_history(DEFAULT_SNAPSHOT_INTERVAL);
+        Self {
+            sim,
+            symbols,
+            lines: BTreeMap::new(),
+            sources: BTreeMap::new(),
+            breakpoints: BTreeSet::new(),
+            max_steps: 1_000_000,
+        }
+    }
+
+    /// Attach the image's line table and read the source files it names.
+    pub fn with_lines(mut self, lines: BTreeMap<u16, SourceLine>) -> Self {
+        for source in lines.values() {
+            if !self.sources.contains_key(&source.file)
+                && let Ok(text) = std::fs::read_to_string(&source.file)
+            {
+                self.sources.insert(source.file.clone(), text.lines().map(str::to_string).collect());
+            }
+        }
+        self.lines = lines;
+        self
+    }
+
+    /// Resolve an address (`0x1a`, `26`), symbol (`loop`), symbol plus
+    /// offset (`main+4`) or source line (`main.asm:12`). A source line
+    /// without code resolves to the first instruction after it.
+    pub fn resolve(&self, location: &str) -> Result<u16, DebugError> {
+        if let Some((file, line)) = location.rsplit_once(':')
+            && let Ok(line) = line.parse::<u32>()
+        {
+            return self
+                .lines
+                .iter()
+                .filter(|(_, source)| source.line >= line && Path::new(&source.file).ends_with(file))
+                .min_by_key(|(address, source)| (source.line, **address))
+                .map(|(address, _)| *address)
+                .ok_or_else(|| DebugError::NoCode(location.to_string()));
+        }
+        resolve(&self.symbols, location)
+    }
+
+    /// The source line of the instruction at `address` and its text, if the
+    /// source file could be read.
+    pub fn source_line(&self, address: u16) -> Option<(&SourceLine, Option<&str>)> {
+        let source = self.lines.get(&address)?;
+        let text = self
+            .sources
+            .get(&source.file)
+            .and_then(|lines| lines.get((source.line as usize).checked_sub(1)?))
+            .map(String::as_str);
+        Some((source, text))
+    }
+
+    /// Describe `address` relative to the nearest preceding symbol, e.g.
+    /// `loop+0x4`.
+    pub fn symbolize(&self, address: u16) -> Option<String> {
+        symbolize(&self.symbols, address)
+    }
+
+    /// Returns false if a breakpoint was already set at `address`.
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Returns false if no breakpoint was set at `address`.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Returns false if the same watchpoint was already set.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.sim.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+        self.sim.watchpoints.push(watchpoint);
+        true
+    }
+
+    /// Remove the watchpoints covering `address`. Returns false if there
+    /// were none.
+    pub fn remove_watchpoints(&mut self, address: u16) -> bool {
+        let before = self.sim.watchpoints.len();
+        self.sim.watchpoints.retain(|w| !(w.start..=w.end).contains(&address));
+        self.sim.watchpoints.len() != before
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.sim.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.sim.watchpoints
+    }
+
+    /// Execute up to `count` instructions, stopping early at a breakpoint,
+    /// a watchpoint hit or when the program exits.
+    pub fn step(&mut self, count: u64) -> Result<Stop, SimError> {
+        for _ in 0..count {
+            if let Some(exit) = self.step_once()? {
+                return Ok(Stop::Exited(exit));
+            }
+            let hits = self.sim.take_watch_hits();
+            if !hits.is_empty() {
+                return Ok(Stop::Watchpoint(hits));
+            }
+            let pc = self.sim.cpu.pc();
+            if count > 1 && self.breakpoints.contains(&pc) {
+                return Ok(Stop::Breakpoint(pc));
+            }
+        }
+        Ok(Stop::Stepped)
+    }
+
+    /// Run until the instruction after the current one is reached. This steps
+    /// over `sysc` and over branches into routines that return to the next
+    /// instruction, and finishes loops that close at the current branch.
+    pub fn step_over(&mut self) -> Result<Stop, SimError> {
+        let resume = self.sim.cpu.pc().wrapping_add(2);
+        self.run_until(Some(resume))
+    }
+
+    /// Run until a breakpoint is reached or the program exits.
+    pub fn cont(&mut self) -> Result<Stop, SimError> {
+        self.run_until(None)
+    }
+
+    /// Undo up to `count` steps.
+    pub fn step_back(&mut self, count: u64) -> Result<Stop, SimError> {
+        let (start, position) = self.sim.history().map_or((0, 0), |h| (h.start(), h.position()));
+        self.sim.rewind(position.saturating_sub(count).max(start))?;
+        Ok(if count > position - start { Stop::StartOfHistory } else { Stop::Stepped })
+    }
+
+    /// Run backwards to the most recent breakpoint, or to the oldest recorded
+    /// step if none was passed.
+    pub fn reverse_cont(&mut self) -> Result<Stop, SimError> {
+        let Some(history) = self.sim.history() else {
+            return Ok(Stop::StartOfHistory);
+        };
+        let start = history.start();
+        let breakpoint = (start..history.position())
+            .rev()
+            .find(|&p| history.pc_at(p).is_some_and(|pc| self.breakpoints.contains(&pc)));
+        match breakpoint {
+            Some(position) => {
+                self.sim.rewind(position)?;
+                Ok(Stop::Breakpoint(self.sim.cpu.pc()))
+            }
+            None => {
+                self.sim.rewind(start)?;
+                Ok(Stop::StartOfHistory)
+            }
+        }
+    }
+
+    fn run_until(&mut self, target: Option<u16>) -> Result<Stop, SimError> {
+        for _ in 0..self.max_steps {
+            if let Some(exit) = self.step_once()? {
+                return Ok(Stop::Exited(exit));
+            }
+            let hits = self.sim.take_watch_hits();
+            if !hits.is_empty() {
+                return Ok(Stop::Watchpoint(hits));
+            }
+            let pc = self.sim.cpu.pc();
+            if target == Some(pc) {
+                return Ok(Stop::Stepped);
+            }
+            if self.breakpoints.contains(&pc) {
+                return Ok(Stop::Breakpoint(pc));
+            }
+        }
+        Ok(Stop::Exited(ExitReason::StepLimit))
+    }
+
+    /// One simulator step. A halted CPU that an interrupt could still wake
+    /// idles for the step, as in `Simulator::run`.
+    fn step_once(&mut self) -> Result<Option<ExitReason>, SimError> {
+        Ok(match self.sim.step()? {
+            StepResult::Continue => None,
+            StepResult::Halt if self.sim.may_wake() => None,
+            StepResult::Halt => Some(ExitReason::Halted),
+            StepResult::Syscall(n) => Some(ExitReason::Syscall(n)),
+        })
+    }
+}
+
+/// Resolve a location against `symbols`; see [`Debugger::resolve`].
+pub fn resolve(symbols: &BTreeMap<u16, String>, location: &str) -> Result<u16, DebugError> {
+    u16::try_from(resolve_wide(symbols, location)?).map_err(|_| DebugError::InvalidAddress(location.to_string()))
+}
+
+/// [`resolve`] without the 16-bit limit, for the exclusive end of a range.
+fn resolve_wide(symbols: &BTreeMap<u16, String>, location: &str) -> Result<u32, DebugError> {
+    let (base, offset) = match location.split_once('+') {
+        Some((base, offset)) => (base, parse_number(offset)?),
+        None => (location, 0),
+    };
+    let address = if base.starts_with(|c: char| c.is_ascii_digit()) {
+        parse_number(base)?
+    } else {
+        symbols
+            .iter()
+            .find(|(_, name)| name.as_str() == base)
+            .map(|(address, _)| *address as u32)
+            .ok_or_else(|| DebugError::UnknownSymbol(base.to_string()))?
+    };
+    address.checked_add(offset).ok_or_else(|| DebugError::InvalidAddress(location.to_string()))
+}
+
+/// Resolve a single location or a half-open range `start..end` of
+/// locations to the first and last address covered. The end may be
+/// 0x10000, so that a range can cover 0xffff.
+pub fn resolve_range(symbols: &BTreeMap<u16, String>, range: &str) -> Result<(u16, u16), DebugError> {
+    let Some((start, end)) = range.split_once("..") else {
+        let address = resolve(symbols, range)?;
+        return Ok((address, address));
+    };
+    let start = resolve(symbols, start)?;
+    let end = resolve_wide(symbols, end)?;
+    if end <= start as u32 || end > 0x10000 {
+        return Err(DebugError::InvalidRange(range.to_string()));
+    }
+    Ok((start, (end - 1) as u16))
+}
+
+/// One line describing a watchpoint hit: the access, the instruction that
+/// made it and its source line, and the value before and after.
+pub fn format_watch_hit(
+    sim: &Simulator,
+    hit: &WatchHit,
+    labels: &BTreeMap<u16, String>,
+    lines: &BTreeMap<u16, SourceLine>,
+) -> String {
+    let mut location = match symbolize(labels, hit.pc) {
+        Some(symbol) => format!("0x{:04x} <{}>", hit.pc, symbol),
+        None => format!("0x{:04x}", hit.pc),
+    };
+    if let Some(source) = lines.get(&hit.pc) {
+        location.push_str(&format!(" ({})", source));
+    }
+    let instruction = match (sim.peek(hit.pc), sim.peek(hit.pc.wrapping_add(1))) {
+        (Some(high), Some(low)) => format_word(u16::from_be_bytes([high, low]), hit.pc, labels)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => "<unmapped>".to_string(),
+    };
+    let values = match hit.access {
+        Access::Write => format!("0x{:02x} -> 0x{:02x}", hit.old, hit.new),
+        _ => format!("0x{:02x}", hit.new),
+    };
+    format!(
+        "{} {} at {}  {}  {}",
+        hit.access,
+        bold(&format!("[0x{:04x}]", hit.address)),
+        location,
+        dim(&instruction),
+        values,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use atlas_sim::{Access, WatchKind};
+
+    use super::*;
+
+    fn debugger(source: &str) -> Debugger {
+        let obj = atlas_assembler::assemble_source(source, "test.asm").unwrap();
+        let image = atlas_linker::link_objects(&[("test.asm".to_string(), obj)]).unwrap();
+        let mut sim = Simulator::new();
+        sim.load(&image.data, 0).unwrap();
+        Debugger::new(sim, image.symbols).with_lines(image.lines)
+    }
+
+    #[test]
+    fn breaks_on_symbols_and_steps_over_routine_calls() {
+        let mut dbg = debugger(
+            "
+                ldi  r1, 0x03
+                br   double
+            double_ret:
+                ldi  r2, 0x00
+            loop:
+                inc  r2
+                cmp  r2, r1
+                bne  loop
+                halt
+            double:
+                add  r1, r1
+                br   double_ret
+            ",
+        );
+        assert_eq!(dbg.resolve("loop"), Ok(0x06));
+        assert_eq!(dbg.resolve("loop+2"), Ok(0x08));
+        assert_eq!(dbg.resolve("0x10"), Ok(0x10));
+        assert_eq!(dbg.resolve("nowhere"), Err(DebugError::UnknownSymbol("nowhere".into())));
+        assert_eq!(dbg.resolve("0x12345"), Err(DebugError::InvalidAddress("0x12345".into())));
+        assert_eq!(dbg.resolve("loop+0x10000"), Err(DebugError::InvalidAddress("loop+0x10000".into())));
+        assert_eq!(dbg.symbolize(0x0a).as_deref(), Some("loop+0x4"));
+        assert_eq!(dbg.resolve("test.asm:4"), Ok(0x04));
+        assert_eq!(dbg.resolve("test.asm:99"), Err(DebugError::NoCode("test.asm:99".into())));
+        assert_eq!(dbg.source_line(0x06).map(|(source, _)| source.to_string()).as_deref(), Some("test.asm:7"));
+
+        assert_eq!(dbg.step(1).unwrap(), Stop::Stepped);
+        assert_eq!(dbg.step_over().unwrap(), Stop::Stepped);
+        assert_eq!(dbg.sim.cpu.pc(), 0x04);
+        assert_eq!(dbg.sim.cpu.reg(1), 0x06);
+
+        dbg.add_breakpoint(dbg.resolve("loop").unwrap());
+        assert_eq!(dbg.cont().unwrap(), Stop::Breakpoint(0x06));
+        assert_eq!(dbg.cont().unwrap(), Stop::Breakpoint(0x06));
+        assert_eq!(dbg.sim.cpu.reg(2), 0x01);
+
+        dbg.step(2).unwrap();
+        assert_eq!(dbg.sim.cpu.pc(), 0x0a);
+        assert!(dbg.remove_breakpoint(0x06));
+        assert_eq!(dbg.step_over().unwrap(), Stop::Stepped);
+        assert_eq!(dbg.sim.cpu.reg(2), 0x06);
+        assert_eq!(dbg.cont().unwrap(), Stop::Exited(ExitReason::Halted));
+
+        // Back into the loop: r2 counts down again.
+        assert_eq!(dbg.reverse_cont().unwrap(), Stop::StartOfHistory);
+        assert_eq!(dbg.sim.cpu.pc(), 0x00);
+        dbg.add_breakpoint(0x08);
+        dbg.cont().unwrap();
+        dbg.cont().unwrap();
+        assert_eq!(dbg.sim.cpu.reg(2), 0x02);
+        assert_eq!(dbg.step_back(2).unwrap(), Stop::Stepped);
+        assert_eq!((dbg.sim.cpu.pc(), dbg.sim.cpu.reg(2)), (0x0a, 0x01));
+        assert_eq!(dbg.reverse_cont().unwrap(), Stop::Breakpoint(0x08));
+        assert_eq!(dbg.sim.cpu.reg(2), 0x01);
+    }
+
+    #[test]
+    fn stops_on_watched_stores_and_loads() {
+        let mut dbg = debugger(
+            "
+                ldi  r2, 0x80
+                ldi  r1, 0x11
+                st   r1, [r2, 0]
+                st   r1, [r2, 4]
+                ld   r3, [r2, 0]
+                halt
+            ",
+        );
+        let (start, end) = resolve_range(&dbg.symbols, "0x82..0x86").unwrap();
+        assert!(dbg.add_watchpoint(Watchpoint::new(start, end, WatchKind::Write)));
+        assert!(dbg.add_watchpoint(Watchpoint::new(0x80, 0x80, WatchKind::Read)));
+        assert_eq!(resolve_range(&dbg.symbols, "0x86..0x82"), Err(DebugError::InvalidRange("0x86..0x82".into())));
+        assert_eq!(resolve_range(&dbg.symbols, "0xff00..0x10000"), Ok((0xFF00, 0xFFFF)));
+        assert_eq!(
+            resolve_range(&dbg.symbols, "0xff00..0x10001"),
+            Err(DebugError::InvalidRange("0xff00..0x10001".into()))
+        );
+
+        let hit = WatchHit { pc: 0x06, address: 0x84, access: Access::Write, old: 0x00, new: 0x11 };
+        assert_eq!(dbg.cont().unwrap(), Stop::Watchpoint(vec![hit]));
+        let hit = WatchHit { pc: 0x08, address: 0x80, access: Access::Read, old: 0x11, new: 0x11 };
+        assert_eq!(dbg.cont().unwrap(), Stop::Watchpoint(vec![hit]));
+        assert!(dbg.remove_watchpoints(0x83));
+        assert_eq!(dbg.watchpoints().len(), 1);
+        assert_eq!(dbg.cont().unwrap(), Stop::Exited(ExitReason::Halted));
+    }
+}
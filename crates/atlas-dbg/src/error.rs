@@ -0,0 +1,39 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugError {
+    /// The input does not start with a known command.
+    UnknownCommand(String),
+    /// A required argument was not given.
+    MissingArgument { command: &'static str, argument: &'static str },
+    /// A numeric argument does not parse.
+    InvalidNumber(String),
+    /// A location names a symbol the image does not define.
+    UnknownSymbol(String),
+    /// A location or length lies outside the 16-bit address space.
+    InvalidAddress(String),
+    /// An address range is empty or runs past the end of memory.
+    InvalidRange(String),
+    /// A `file:line` location has no instruction at or after that line.
+    NoCode(String),
+}
+
+impl Display for DebugError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DebugError::UnknownCommand(name) => {
+                write!(f, "unknown command '{}' (try 'help')", name)
+            }
+            DebugError::MissingArgument { command, argument } => {
+                write!(f, "'{}' needs {}", command, argument)
+            }
+            DebugError::InvalidNumber(text) => write!(f, "'{}' is not a number", text),
+            DebugError::UnknownSymbol(name) => write!(f, "no symbol named '{}'", name),
+            DebugError::InvalidAddress(text) => write!(f, "'{}' is outside the 64 KB address space", text),
+            DebugError::InvalidRange(text) => write!(f, "'{}' is not a valid address range", text),
+            DebugError::NoCode(location) => write!(f, "no code at or after '{}'", location),
+        }
+    }
+}
+
+impl std::error::Error for DebugError {}
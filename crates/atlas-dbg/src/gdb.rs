@@ -0,0 +1,585 @@
+//! GDB remote serial protocol stub.
+//!
+//! Serves one GDB connection over TCP, driving a [`Debugger`]. Supported
+//! packets cover what a frontend needs for basic debugging: register and
+//! memory access (`g`/`G`, `p`/`P`, `m`/`M`), software and hardware
+//! breakpoints (`Z0`/`Z1`), write, read and access watchpoints
+//! (`Z2`–`Z4`), `s`tep and `c`ontinue (interruptible with Ctrl-C), reverse
+//! step and continue (`bs`/`bc`), and the target description through
+//! `qXfer:features:read`.
+//!
+//! GDB register numbers 0–15 are r0–r15, followed by `sr`, and the `tr`,
+//! `sp` and `pc` pairs as 16-bit registers. Multi-byte registers are sent
+//! little-endian. The pairs alias r10–r15, so a `G` packet only applies a
+//! pair whose value actually changed.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+use atlas_sim::cpu::{PC_HIGH, PC_LOW, SP_HIGH, SP_LOW, TR_HIGH, TR_LOW};
+use atlas_sim::{ExitReason, SimError, StatusRegister, WatchHit, WatchKind, Watchpoint};
+
+use crate::debugger::{Debugger, Stop};
+
+/// GDB register table: name, size in bits, GDB type.
+const REGISTERS: [(&str, u32, &str); 20] = [
+    ("r0", 8, "uint8"),
+    ("r1", 8, "uint8"),
+    ("r2", 8, "uint8"),
+    ("r3", 8, "uint8"),
+    ("r4", 8, "uint8"),
+    ("r5", 8, "uint8"),
+    ("r6", 8, "uint8"),
+    ("r7", 8, "uint8"),
+    ("r8", 8, "uint8"),
+    ("r9", 8, "uint8"),
+    ("r10", 8, "uint8"),
+    ("r11", 8, "uint8"),
+    ("r12", 8, "uint8"),
+    ("r13", 8, "uint8"),
+    ("r14", 8, "uint8"),
+    ("r15", 8, "uint8"),
+    ("sr", 8, "uint8"),
+    ("tr", 16, "data_ptr"),
+    ("sp", 16, "data_ptr"),
+    ("pc", 16, "code_ptr"),
+];
+
+const REG_SR: usize = 16;
+const PAIRS: [(usize, u8, u8); 3] = [(17, TR_HIGH, TR_LOW), (18, SP_HIGH, SP_LOW), (19, PC_HIGH, PC_LOW)];
+
+/// Largest packet we accept or send, advertised in `qSupported`.
+const PACKET_SIZE: u32 = 0x1000;
+
+/// Instructions executed between checks for a Ctrl-C from the client.
+const INTERRUPT_POLL_STEPS: u64 = 4096;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Atlas-8 target description served as `target.xml`.
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n  <feature name=\"org.atlas.atlas8.core\">\n",
+    );
+    for (regnum, (name, bits, kind)) in REGISTERS.iter().enumerate() {
+        xml.push_str(&format!(
+            "    <reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n",
+            name, bits, kind, regnum
+        ));
+    }
+    xml.push_str("  </feature>\n</target>\n");
+    xml
+}
+
+/// Serve GDB on `stream` until it detaches, kills the target or hangs up.
+pub fn serve(dbg: &mut Debugger, stream: TcpStream) -> io::Result<()> {
+    // Packets are small and strictly request/response.
+    stream.set_nodelay(true)?;
+    GdbStub { dbg, stream, no_ack: false }.run()
+}
+
+struct GdbStub<'a> {
+    dbg: &'a mut Debugger,
+    stream: TcpStream,
+    no_ack: bool,
+}
+
+/// What to do after answering a packet.
+enum Flow {
+    Continue,
+    Close,
+}
+
+impl GdbStub<'_> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let (reply, flow) = self.handle(&packet)?;
+            if let Some(reply) = reply {
+                self.send(&reply)?;
+            }
+            if let Flow::Close = flow {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Next packet payload, or `None` once the client has disconnected.
+    /// Acknowledgements and stray interrupts between packets are skipped.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => break,
+                Some(_) => {}
+            }
+        }
+        let mut payload = Vec::new();
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(byte) => payload.push(byte),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum)?;
+        let expected = std::str::from_utf8(&checksum).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+        if !self.no_ack {
+            let valid = expected == Some(checksum_of(&payload));
+            self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            if !valid {
+                return self.read_packet();
+            }
+        }
+        Ok(Some(String::from_utf8_lossy(&payload).into_owned()))
+    }
+
+    fn send(&mut self, payload: &str) -> io::Result<()> {
+        let mut escaped = Vec::with_capacity(payload.len());
+        for &byte in payload.as_bytes() {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                escaped.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                escaped.push(byte);
+            }
+        }
+        let mut packet = Vec::with_capacity(escaped.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(&escaped);
+        packet.extend_from_slice(format!("#{:02x}", checksum_of(&escaped)).as_bytes());
+        self.stream.write_all(&packet)
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<(Option<String>, Flow)> {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => stop_reply(SIGTRAP),
+            Some(b'g') => self.read_registers(),
+            Some(b'G') => self.write_registers(&packet[1..]),
+            Some(b'p') => self.read_register(&packet[1..]),
+            Some(b'P') => self.write_register(&packet[1..]),
+            Some(b'm') => self.read_memory(&packet[1..]),
+            Some(b'M') => self.write_memory(&packet[1..]),
+            Some(b'Z') | Some(b'z') => self.breakpoint(packet),
+            Some(b's') => self.resume(Some(1))?,
+            Some(b'c') => self.resume(None)?,
+            Some(b'b') if packet == "bs" => reverse_reply(self.dbg.step_back(1)),
+            Some(b'b') if packet == "bc" => reverse_reply(self.dbg.reverse_cont()),
+            Some(b'H') => "OK".to_string(),
+            Some(b'D') => return Ok((Some("OK".to_string()), Flow::Close)),
+            Some(b'k') => return Ok((None, Flow::Close)),
+            Some(b'q') | Some(b'Q') => self.query(packet),
+            _ => String::new(),
+        };
+        Ok((Some(reply), Flow::Continue))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;ReverseStep+;ReverseContinue+",
+                PACKET_SIZE
+            )
+        } else if packet == "QStartNoAckMode" {
+            // The client acknowledges this reply; later packets are unacked.
+            self.no_ack = true;
+            "OK".to_string()
+        } else if let Some(request) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_range(request) else {
+                return "E01".to_string();
+            };
+            let xml = target_xml();
+            let start = (offset as usize).min(xml.len());
+            let end = start.saturating_add(length as usize).min(xml.len());
+            let marker = if end == xml.len() { 'l' } else { 'm' };
+            format!("{}{}", marker, &xml[start..end])
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    fn register_bytes(&self, regnum: usize) -> Option<Vec<u8>> {
+        let cpu = &self.dbg.sim.cpu;
+        match regnum {
+            0..=15 => Some(vec![cpu.reg(regnum as u8)]),
+            REG_SR => Some(vec![cpu.sr.0]),
+            _ => {
+                let &(_, high, low) = PAIRS.iter().find(|(n, _, _)| *n == regnum)?;
+                Some(cpu.pair(high, low).to_le_bytes().to_vec())
+            }
+        }
+    }
+
+    fn set_register_bytes(&mut self, regnum: usize, bytes: &[u8]) -> Option<()> {
+        let cpu = &mut self.dbg.sim.cpu;
+        match regnum {
+            0..=15 => cpu.set_reg(regnum as u8, *bytes.first()?),
+            REG_SR => cpu.sr = StatusRegister(*bytes.first()?),
+            _ => {
+                let &(_, high, low) = PAIRS.iter().find(|(n, _, _)| *n == regnum)?;
+                cpu.set_pair(high, low, u16::from_le_bytes(bytes.get(..2)?.try_into().ok()?));
+            }
+        }
+        Some(())
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTERS.len()).filter_map(|n| self.register_bytes(n)).map(|b| to_hex(&b)).collect()
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        let Some(bytes) = from_hex(data) else {
+            return "E01".to_string();
+        };
+        let before: Vec<Vec<u8>> = (0..REGISTERS.len()).filter_map(|n| self.register_bytes(n)).collect();
+        let mut offset = 0;
+        for (regnum, old) in before.iter().enumerate() {
+            let Some(new) = bytes.get(offset..offset + old.len()) else {
+                break;
+            };
+            if regnum < REG_SR || new != old.as_slice() {
+                self.set_register_bytes(regnum, new);
+            }
+            offset += old.len();
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match usize::from_str_radix(args, 16).ok().and_then(|n| self.register_bytes(n)) {
+            Some(bytes) => to_hex(&bytes),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let written = args.split_once('=').and_then(|(regnum, value)| {
+            let regnum = usize::from_str_radix(regnum, 16).ok()?;
+            self.set_register_bytes(regnum, &from_hex(value)?)
+        });
+        match written {
+            Some(()) => "OK".to_string(),
+            None => "E01".to_string(),
+        }
+    }
+
+    /// Read memory; replies are cut to what fits in a packet, two hex
+    /// digits per byte.
+    fn read_memory(&self, args: &str) -> String {
+        let Some((address, length)) = parse_range(args) else {
+            return "E01".to_string();
+        };
+        let length = length.min(PACKET_SIZE / 2);
+        let bytes: Option<Vec<u8>> =
+            (0..length).map(|i| self.dbg.sim.peek((address + i) as u16)).collect();
+        match bytes {
+            Some(bytes) => to_hex(&bytes),
+            None => "E14".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)));
+        let Some(((address, length), bytes)) = parsed else {
+            return "E01".to_string();
+        };
+        for (i, byte) in bytes.iter().take(length as usize).enumerate() {
+            if self.dbg.sim.poke((address + i as u32) as u16, *byte).is_none() {
+                return "E14".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn breakpoint(&mut self, packet: &str) -> String {
+        let mut fields = packet[1..].split(',');
+        let kind = fields.next();
+        let address = fields.next().and_then(|a| u32::from_str_radix(a, 16).ok());
+        let length = fields.next().and_then(|l| u32::from_str_radix(l, 16).ok());
+        let insert = packet.starts_with('Z');
+        let watch = match kind {
+            Some("2") => WatchKind::Write,
+            Some("3") => WatchKind::Read,
+            Some("4") => WatchKind::Access,
+            Some("0") | Some("1") => {
+                let Some(address) = address.and_then(|a| u16::try_from(a).ok()) else {
+                    return "E01".to_string();
+                };
+                if insert {
+                    self.dbg.add_breakpoint(address);
+                } else {
+                    self.dbg.remove_breakpoint(address);
+                }
+                return "OK".to_string();
+            }
+            _ => return String::new(),
+        };
+        let (Some(address), Some(length @ 1..)) = (address, length) else {
+            return "E01".to_string();
+        };
+        let last = address.checked_add(length - 1).and_then(|end| u16::try_from(end).ok());
+        let Some(end) = last else {
+            return "E01".to_string();
+        };
+        let watchpoint = Watchpoint::new(address as u16, end, watch);
+        if insert {
+            self.dbg.add_watchpoint(watchpoint);
+        } else {
+            self.dbg.sim.watchpoints.retain(|w| *w != watchpoint);
+        }
+        "OK".to_string()
+    }
+
+    /// Step `count` instructions, or continue until something stops the
+    /// program if `count` is `None`.
+    fn resume(&mut self, count: Option<u64>) -> io::Result<String> {
+        let stop = match count {
+            Some(count) => self.dbg.step(count),
+            None => loop {
+                match self.dbg.step(INTERRUPT_POLL_STEPS) {
+                    Ok(Stop::Stepped) => {
+                        if self.interrupted()? {
+                            return Ok(stop_reply(SIGINT));
+                        }
+                    }
+                    other => break other,
+                }
+            },
+        };
+        Ok(match stop {
+            Ok(Stop::Stepped | Stop::Breakpoint(_) | Stop::StartOfHistory) => stop_reply(SIGTRAP),
+            Ok(Stop::Watchpoint(hits)) => self.watch_reply(&hits[0]),
+            Ok(Stop::Exited(ExitReason::Halted)) => "W00".to_string(),
+            Ok(Stop::Exited(_)) => stop_reply(SIGTRAP),
+            Err(SimError::InvalidInstruction { .. } | SimError::IllegalInstruction { .. }) => stop_reply(SIGILL),
+            Err(SimError::Exception { exception, .. }) if exception.address().is_none() => stop_reply(SIGILL),
+            Err(_) => stop_reply(SIGSEGV),
+        })
+    }
+
+    /// `T05watch:<addr>;` naming the kind of watchpoint the hit matched.
+    fn watch_reply(&self, hit: &WatchHit) -> String {
+        let kind = self
+            .dbg
+            .watchpoints()
+            .iter()
+            .find(|w| w.matches(hit.address, hit.access))
+            .map(|w| w.kind);
+        let name = match kind {
+            Some(WatchKind::Read) => "rwatch",
+            Some(WatchKind::Access) => "awatch",
+            _ => "watch",
+        };
+        format!("T{:02x}{}:{:x};", SIGTRAP, name, hit.address)
+    }
+
+    /// Whether the client sent Ctrl-C (0x03) while the target was running.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = match self.stream.peek(&mut byte) {
+            Ok(1) if byte[0] == 0x03 => {
+                self.stream.read_exact(&mut byte)?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.stream.set_nonblocking(false)?;
+        result
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+/// Stop reply after running backwards.
+fn reverse_reply(stop: Result<Stop, SimError>) -> String {
+    match stop {
+        Ok(Stop::StartOfHistory) => format!("T{:02x}replaylog:begin;", SIGTRAP),
+        Ok(_) => stop_reply(SIGTRAP),
+        Err(_) => stop_reply(SIGSEGV),
+    }
+}
+
+fn checksum_of(payload: &[u8]) -> u8 {
+    payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// An `address,length` pair in hex, rejecting ranges that run past the
+/// end of the 16-bit address space.
+fn parse_range(text: &str) -> Option<(u32, u32)> {
+    let (address, length) = text.split_once(',')?;
+    let (address, length) = (u32::from_str_radix(address, 16).ok()?, u32::from_str_radix(length, 16).ok()?);
+    (address.checked_add(length)? <= 0x10000).then_some((address, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use atlas_sim::Simulator;
+
+    use super::*;
+
+    /// Minimal RSP client: sends each packet and collects the replies.
+    fn client(address: std::net::SocketAddr, packets: &'static [&'static str]) -> Vec<String> {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut replies = Vec::new();
+        for packet in packets {
+            let frame = format!("${}#{:02x}", packet, checksum_of(packet.as_bytes()));
+            stream.write_all(frame.as_bytes()).unwrap();
+            if *packet == "k" {
+                break;
+            }
+            let mut reply = Vec::new();
+            let mut byte = [0];
+            // Skip the ack, read up to '#', then the two checksum digits.
+            loop {
+                stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            loop {
+                stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+                reply.push(byte[0]);
+            }
+            stream.read_exact(&mut [0; 2]).unwrap();
+            stream.write_all(b"+").unwrap();
+            replies.push(String::from_utf8(reply).unwrap());
+        }
+        replies
+    }
+
+    #[test]
+    fn serves_registers_memory_breakpoints_and_execution() {
+        let source = "
+                ldi  r1, 0x05
+            loop:
+                dec  r1
+                bne  loop
+                ldi  r2, 0x80
+                st   r1, [r2, 0]
+                halt
+            ";
+        let obj = atlas_assembler::assemble_source(source, "test.asm").unwrap();
+        let image = atlas_linker::link_objects(&[("test.asm".to_string(), obj)]).unwrap();
+        let mut sim = Simulator::new();
+        sim.load(&image.data, 0).unwrap();
+        let mut dbg = Debugger::new(sim, image.symbols);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            client(
+                address,
+                &[
+                    "qSupported:xmlRegisters=i386",
+                    "qXfer:features:read:target.xml:0,40",
+                    "s",
+                    "p1",
+                    "p13",
+                    "Z0,4,2",
+                    "c",
+                    "p1",
+                    "z0,4,2",
+                    "M80,1:aa",
+                    "m80,2",
+                    "P13=0600",
+                    "c",
+                    "m80,1",
+                    "k",
+                ],
+            )
+        });
+        let (stream, _) = listener.accept().unwrap();
+        serve(&mut dbg, stream).unwrap();
+        let replies = client.join().unwrap();
+
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert!(replies[1].starts_with("m<?xml"));
+        assert_eq!(replies[2], "S05");
+        assert_eq!(replies[3], "05");
+        assert_eq!(replies[4], "0200");
+        assert_eq!(replies[5], "OK");
+        assert_eq!(replies[6], "S05");
+        assert_eq!(replies[7], "04");
+        assert_eq!(&replies[8..11], ["OK", "OK", "aa00"]);
+        // Jump past the loop with r1 still 4.
+        assert_eq!(replies[11], "OK");
+        assert_eq!(replies[12], "W00");
+        assert_eq!(replies[13], "04");
+    }
+
+    #[test]
+    fn rejects_overflowing_ranges_and_caps_reads() {
+        let mut dbg = Debugger::new(Simulator::new(), Default::default());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            client(
+                address,
+                &[
+                    "Z2,ffffffff,2",
+                    "z2,fffffffe,0",
+                    "mffffffff,2",
+                    "Mffffffff,2:aabb",
+                    "m10000,2",
+                    "Mffff,2:aabb",
+                    "Z0,10000,2",
+                    "Z2,ffff,2",
+                    "Z2,ffff,1",
+                    "m0,10000",
+                    "k",
+                ],
+            )
+        });
+        let (stream, _) = listener.accept().unwrap();
+        serve(&mut dbg, stream).unwrap();
+        let replies = client.join().unwrap();
+
+        assert_eq!(&replies[..8], ["E01"; 8]);
+        assert_eq!(replies[8], "OK");
+        assert_eq!(replies[9].len(), PACKET_SIZE as usize);
+    }
+}
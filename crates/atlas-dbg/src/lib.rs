@@ -0,0 +1,18 @@
+//! Atlas Dbg - debugger for Atlas-8 programs
+//!
+//! A [`Debugger`] drives an `atlas-sim` [`Simulator`] one instruction at a
+//! time, stopping at breakpoints. Locations are given as addresses or as
+//! symbols of the linked image. The [`repl`] module provides the
+//! line-oriented command interface behind `atlas dbg`.
+//!
+//! [`Simulator`]: atlas_sim::Simulator
+
+pub mod command;
+pub mod debugger;
+pub mod error;
+pub mod gdb;
+pub mod repl;
+
+pub use command::Command;
+pub use debugger::{Debugger, Stop};
+pub use error::DebugError;
@@ -0,0 +1,228 @@
+//! Line-oriented command loop for `atlas dbg`.
+
+use std::io::{BufRead, Write};
+
+use atlas_inspect::{disassemble_window, format_word, print_registers};
+use atlas_sim::{SimError, Watchpoint};
+
+use crate::command::{Command, HELP};
+use crate::debugger::{Debugger, Stop, format_watch_hit, resolve_range};
+use crate::error::DebugError;
+
+/// Bytes disassembled before and after the location shown by `list`.
+const LIST_BEFORE: u16 = 8;
+const LIST_AFTER: u16 = 16;
+
+/// Read commands from `input` until `quit` or end of input.
+pub fn run(dbg: &mut Debugger, mut input: impl BufRead) {
+    let mut previous: Option<Command> = None;
+    print_location(dbg);
+    loop {
+        print!("(dbg) ");
+        let _ = std::io::stdout().flush();
+
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                println!();
+                return;
+            }
+            Ok(_) => {}
+        }
+
+        let command = if line.trim().is_empty() {
+            match previous.clone() {
+                Some(command) => command,
+                None => continue,
+            }
+        } else {
+            match Command::parse(&line) {
+                Ok(command) => command,
+                Err(e) => {
+                    println!("  error: {}", e);
+                    continue;
+                }
+            }
+        };
+
+        match execute(dbg, &command) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => println!("  error: {}", e),
+        }
+        previous = Some(command);
+    }
+}
+
+/// Run one command. Returns `Ok(false)` when the user quits.
+pub fn execute(dbg: &mut Debugger, command: &Command) -> Result<bool, DebugError> {
+    match command {
+        Command::Break(location) => {
+            let address = dbg.resolve(location)?;
+            if dbg.add_breakpoint(address) {
+                println!("  Breakpoint at {}", describe(dbg, address));
+            } else {
+                println!("  Breakpoint at {} already set", describe(dbg, address));
+            }
+        }
+        Command::Delete(Some(location)) => {
+            let address = dbg.resolve(location)?;
+            if dbg.remove_breakpoint(address) {
+                println!("  Deleted breakpoint at {}", describe(dbg, address));
+            } else {
+                println!("  No breakpoint at {}", describe(dbg, address));
+            }
+        }
+        Command::Delete(None) => {
+            dbg.clear_breakpoints();
+            println!("  Deleted all breakpoints");
+        }
+        Command::Breakpoints => {
+            let breakpoints: Vec<u16> = dbg.breakpoints().collect();
+            if breakpoints.is_empty() && dbg.watchpoints().is_empty() {
+                println!("  No breakpoints or watchpoints");
+            }
+            for address in breakpoints {
+                println!("  {}", describe(dbg, address));
+            }
+            for watchpoint in dbg.watchpoints() {
+                println!("  Watchpoint ({})", watchpoint);
+            }
+        }
+        Command::Watch { kind, range } => {
+            let (start, end) = resolve_range(&dbg.symbols, range)?;
+            let watchpoint = Watchpoint::new(start, end, *kind);
+            if dbg.add_watchpoint(watchpoint) {
+                println!("  Watchpoint ({})", watchpoint);
+            } else {
+                println!("  Watchpoint ({}) already set", watchpoint);
+            }
+        }
+        Command::Unwatch(Some(location)) => {
+            let address = dbg.resolve(location)?;
+            if dbg.remove_watchpoints(address) {
+                println!("  Deleted watchpoints covering 0x{:04x}", address);
+            } else {
+                println!("  No watchpoint covers 0x{:04x}", address);
+            }
+        }
+        Command::Unwatch(None) => {
+            dbg.clear_watchpoints();
+            println!("  Deleted all watchpoints");
+        }
+        Command::Step(count) => {
+            let stop = dbg.step(*count);
+            report(dbg, stop);
+        }
+        Command::ReverseStep(count) => {
+            let stop = dbg.step_back(*count);
+            report(dbg, stop);
+        }
+        Command::ReverseContinue => {
+            let stop = dbg.reverse_cont();
+            report(dbg, stop);
+        }
+        Command::Next => {
+            let stop = dbg.step_over();
+            report(dbg, stop);
+        }
+        Command::Continue => {
+            let stop = dbg.cont();
+            report(dbg, stop);
+        }
+        Command::Registers => {
+            print_registers(&dbg.sim.cpu.registers(), &dbg.sim.cpu.sr.to_string());
+        }
+        Command::Memory { location, length } => {
+            let address = dbg.resolve(location)?;
+            dump_memory(dbg, address, *length);
+        }
+        Command::List(location) => {
+            let address = match location {
+                Some(location) => dbg.resolve(location)?,
+                None => dbg.sim.cpu.pc(),
+            };
+            list(dbg, address);
+        }
+        Command::Help => println!("{}", HELP),
+        Command::Quit => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn describe(dbg: &Debugger, address: u16) -> String {
+    let mut description = match dbg.symbolize(address) {
+        Some(symbol) => format!("0x{:04x} <{}>", address, symbol),
+        None => format!("0x{:04x}", address),
+    };
+    if let Some((source, _)) = dbg.source_line(address) {
+        description.push_str(&format!(" ({})", source));
+    }
+    description
+}
+
+fn report(dbg: &Debugger, stop: Result<Stop, SimError>) {
+    match stop {
+        Ok(Stop::Stepped) => {}
+        Ok(Stop::Breakpoint(address)) => println!("  Breakpoint at {}", describe(dbg, address)),
+        Ok(Stop::Watchpoint(hits)) => {
+            for hit in &hits {
+                println!("  Watchpoint: {}", format_watch_hit(&dbg.sim, hit, &dbg.symbols, &dbg.lines));
+            }
+        }
+        Ok(Stop::StartOfHistory) => println!("  Reached the start of the recorded history"),
+        Ok(Stop::Exited(reason)) => {
+            println!(
+                "  Program {} ({} instructions, {} cycles)",
+                reason,
+                dbg.sim.steps(),
+                dbg.sim.cycles()
+            );
+            return;
+        }
+        Err(e) => println!("  error: {}", e),
+    }
+    print_location(dbg);
+}
+
+/// Print the instruction at PC and the source line it came from.
+fn print_location(dbg: &Debugger) {
+    let pc = dbg.sim.cpu.pc();
+    let instruction = match (dbg.sim.peek(pc), dbg.sim.peek(pc.wrapping_add(1))) {
+        (Some(high), Some(low)) => format_word(u16::from_be_bytes([high, low]), pc, &dbg.symbols),
+        _ => "<unmapped>".to_string(),
+    };
+    println!("  {}  {}", describe(dbg, pc), instruction);
+    if let Some((source, Some(text))) = dbg.source_line(pc) {
+        println!("  {:>5} | {}", source.line, text.trim_end());
+    }
+}
+
+fn list(dbg: &Debugger, address: u16) {
+    let start = address.saturating_sub(LIST_BEFORE) & !1;
+    let bytes: Vec<u8> = (0..LIST_BEFORE + LIST_AFTER)
+        .map_while(|i| dbg.sim.peek(start.wrapping_add(i)))
+        .collect();
+    disassemble_window(&bytes, start, &dbg.symbols, &dbg.lines, dbg.sim.cpu.pc());
+}
+
+fn dump_memory(dbg: &Debugger, address: u16, length: u16) {
+    for row in (0..length).step_by(16) {
+        let row_address = address.wrapping_add(row);
+        let bytes: Vec<Option<u8>> = (row..length.min(row.saturating_add(16)))
+            .map(|i| dbg.sim.peek(address.wrapping_add(i)))
+            .collect();
+        let hex: Vec<String> = bytes
+            .iter()
+            .map(|b| b.map_or("--".to_string(), |b| format!("{:02x}", b)))
+            .collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|b| match b {
+                Some(b) if b.is_ascii_graphic() || *b == b' ' => *b as char,
+                _ => '.',
+            })
+            .collect();
+        println!("  {:04x}: {:<47}  |{}|", row_address, hex.join(" "), ascii);
+    }
+}
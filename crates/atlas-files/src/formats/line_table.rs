@@ -0,0 +1,164 @@
+//! Debug line tables (`.debug_line` sections).
+//!
+//! The assembler records, for every instruction it emits, the section
+//! offset of the instruction and the source file and line it came from. The
+//! table travels in the object file as an ordinary section named
+//! [`LINE_TABLE_SECTION`] whose data is:
+//!
+//! ```text
+//! u32 string count, then per string: u32 length, UTF-8 bytes
+//! u32 entry count, then per entry:   u32 section, u32 offset, u32 file, u32 line
+//! ```
+//!
+//! All integers are little-endian; `section` and `file` index the string
+//! table. The linker relocates the offsets and merges the tables of its
+//! inputs into an address → [`SourceLine`] map of the linked image.
+
+use std::fmt::Display;
+use std::io::{Error, ErrorKind};
+
+/// Name of the section holding an object file's line table.
+pub const LINE_TABLE_SECTION: &str = ".debug_line";
+
+/// A line of an assembly source.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLine {
+    pub file: String,
+    /// 1-based line number.
+    pub line: u32,
+}
+
+impl Display for SourceLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEntry {
+    pub section: String,
+    /// Offset of the instruction within `section`.
+    pub offset: u32,
+    pub source: SourceLine,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTable {
+    pub entries: Vec<LineEntry>,
+}
+
+impl LineTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, section: &str, offset: u32, file: &str, line: u32) {
+        self.entries.push(LineEntry {
+            section: section.to_string(),
+            offset,
+            source: SourceLine { file: file.to_string(), line },
+        });
+    }
+
+    /// Serialize into the data of a [`LINE_TABLE_SECTION`].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut strings: Vec<&str> = Vec::new();
+        let mut entries: Vec<[u32; 4]> = Vec::new();
+        for entry in &self.entries {
+            let section = intern(&mut strings, &entry.section);
+            let file = intern(&mut strings, &entry.source.file);
+            entries.push([section, entry.offset, file, entry.source.line]);
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        for string in &strings {
+            data.extend_from_slice(&(string.len() as u32).to_le_bytes());
+            data.extend_from_slice(string.as_bytes());
+        }
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for entry in &entries {
+            for field in entry {
+                data.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    /// Parse the data of a [`LINE_TABLE_SECTION`].
+    pub fn decode(data: &[u8]) -> std::io::Result<Self> {
+        let mut reader = Reader { data };
+        let mut strings = Vec::new();
+        for _ in 0..reader.u32()? {
+            let length = reader.u32()? as usize;
+            let bytes = reader.take(length)?;
+            let string = String::from_utf8(bytes.to_vec())
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid UTF-8 in line table"))?;
+            strings.push(string);
+        }
+        let string = |index: u32| {
+            strings
+                .get(index as usize)
+                .cloned()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid string index in line table"))
+        };
+
+        let mut table = LineTable::new();
+        for _ in 0..reader.u32()? {
+            let section = string(reader.u32()?)?;
+            let offset = reader.u32()?;
+            let file = string(reader.u32()?)?;
+            let line = reader.u32()?;
+            table.entries.push(LineEntry { section, offset, source: SourceLine { file, line } });
+        }
+        Ok(table)
+    }
+}
+
+/// Index of `string` in `strings`, appending it if new.
+fn intern<'a>(strings: &mut Vec<&'a str>, string: &'a str) -> u32 {
+    match strings.iter().position(|s| *s == string) {
+        Some(index) => index as u32,
+        None => {
+            strings.push(string);
+            strings.len() as u32 - 1
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> std::io::Result<&'a [u8]> {
+        if self.data.len() < length {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated line table"));
+        }
+        let (head, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> std::io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_and_decodes() {
+        let mut table = LineTable::new();
+        table.push(".text", 0, "main.asm", 3);
+        table.push(".text", 2, "main.asm", 4);
+        table.push(".data", 0, "data.asm", 10);
+        let data = table.encode();
+        assert_eq!(LineTable::decode(&data).unwrap(), table);
+        assert!(LineTable::decode(&data[..data.len() - 1]).is_err());
+        assert_eq!(table.entries[2].source.to_string(), "data.asm:10");
+    }
+}
@@ -1,13 +1,16 @@
 pub mod bin;
 pub mod elf;
 pub mod hex;
+pub mod line_table;
 pub mod obj;
+pub mod snapshot;
 
 pub enum FileType {
     Bin,
     Elf,
     Hex,
     Obj,
+    Snapshot,
 }
 
 pub trait FileFormat {
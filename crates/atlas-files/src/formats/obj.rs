@@ -2,10 +2,16 @@ use std::fs::File;
 use std::io::Write;
 
 use crate::formats::FileFormat;
+use crate::formats::line_table::{LineTable, LINE_TABLE_SECTION};
 
 // constants
 const MAGIC: &[u8; 4] = b"ATOB";
 
+/// Current object file format version. Version 2 added relocation kinds and
+/// version 3 the `rel8`, `off4` and `word16` kinds; version 1 files are still
+/// read, with every relocation an [`RelocationKind::Imm8`].
+pub const OBJECT_VERSION: u32 = 3;
+
 pub struct Section {
     pub name: String,
     pub start: u32,
@@ -25,12 +31,59 @@ pub struct Symbol {
     pub binding: SymbolBinding,
 }
 
+/// How the linker computes a relocation's value and patches it in. Every
+/// kind except [`RelocationKind::Word16`] patches the instruction word at the
+/// relocation's offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// The value, as the 8-bit immediate of an I, BI or P instruction.
+    Imm8 = 0,
+    /// Bits 0–7 of the 16-bit value (`lo(label)`), as an 8-bit immediate.
+    Lo8 = 1,
+    /// Bits 8–15 of the 16-bit value (`hi(label)`), as an 8-bit immediate.
+    Hi8 = 2,
+    /// The signed 8-bit offset of a relative BI branch: the value minus the
+    /// address of the following instruction.
+    Rel8 = 3,
+    /// The signed 4-bit offset of an M instruction.
+    Offset4 = 4,
+    /// A 16-bit little-endian data word (`.word label`).
+    Word16 = 5,
+}
+
+impl RelocationKind {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(RelocationKind::Imm8),
+            1 => Some(RelocationKind::Lo8),
+            2 => Some(RelocationKind::Hi8),
+            3 => Some(RelocationKind::Rel8),
+            4 => Some(RelocationKind::Offset4),
+            5 => Some(RelocationKind::Word16),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RelocationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RelocationKind::Imm8 => write!(f, "imm8"),
+            RelocationKind::Lo8 => write!(f, "lo8"),
+            RelocationKind::Hi8 => write!(f, "hi8"),
+            RelocationKind::Rel8 => write!(f, "rel8"),
+            RelocationKind::Offset4 => write!(f, "off4"),
+            RelocationKind::Word16 => write!(f, "word16"),
+        }
+    }
+}
 
 pub struct Relocation {
     pub offset: u32,
     pub symbol: String,
     pub addend: i32,
     pub section: String,
+    pub kind: RelocationKind,
 }
 
 pub struct ObjectFile {
@@ -41,6 +94,16 @@ pub struct ObjectFile {
     pub version: u32,
 }
 
+impl ObjectFile {
+    /// The object's debug line table; empty if it has none.
+    pub fn line_table(&self) -> std::io::Result<LineTable> {
+        match self.sections.iter().find(|s| s.name == LINE_TABLE_SECTION) {
+            Some(section) => LineTable::decode(&section.data),
+            None => Ok(LineTable::new()),
+        }
+    }
+}
+
 impl FileFormat for ObjectFile {
     fn from_file(path: &str) -> std::io::Result<Self> where Self: Sized {
         use std::io::{Read, Error, ErrorKind};
@@ -54,6 +117,12 @@ impl FileFormat for ObjectFile {
         let mut version_bytes = [0u8; 4];
         file.read_exact(&mut version_bytes)?;
         let version = u32::from_le_bytes(version_bytes);
+        if version == 0 || version > OBJECT_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported object file version {} (expected at most {})", version, OBJECT_VERSION),
+            ));
+        }
 
         let mut count_bytes = [0u8; 4];
         file.read_exact(&mut count_bytes)?;
@@ -145,7 +214,16 @@ impl FileFormat for ObjectFile {
             file.read_exact(&mut section_bytes)?;
             let section = String::from_utf8(section_bytes).map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid UTF-8 in relocation section"))?;
 
-            relocations.push(Relocation { offset, symbol, addend, section });
+            let kind = if version >= 2 {
+                let mut kind_byte = [0u8; 1];
+                file.read_exact(&mut kind_byte)?;
+                RelocationKind::from_u8(kind_byte[0])
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid relocation kind"))?
+            } else {
+                RelocationKind::Imm8
+            };
+
+            relocations.push(Relocation { offset, symbol, addend, section, kind });
         }
 
         Ok(Self {
@@ -159,9 +237,9 @@ impl FileFormat for ObjectFile {
     fn to_file(&self, path: &str) -> std::io::Result<()> {
         let mut file = File::create(path)?;
 
-        // write identifier and version
+        // write identifier and version; always the current format
         file.write_all(MAGIC)?;
-        file.write_all(&(self.version.to_le_bytes()))?;
+        file.write_all(&(OBJECT_VERSION.to_le_bytes()))?;
 
         // write counts
         let section_count = self.sections.len() as u32;
@@ -219,6 +297,7 @@ impl FileFormat for ObjectFile {
             let section_len = section_bytes.len() as u32;
             file.write_all(&section_len.to_le_bytes())?;
             file.write_all(section_bytes)?;
+            file.write_all(&(reloc.kind as u8).to_le_bytes())?;
         }
 
         Ok(())
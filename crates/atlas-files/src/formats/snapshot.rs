@@ -0,0 +1,172 @@
+//! Machine state snapshots (`.snap`).
+//!
+//! A snapshot holds everything needed to resume a simulation: registers,
+//! system, interrupt and MMU registers, cache contents, memory, the state of
+//! memory-mapped devices and the cycle count. The file starts with the magic
+//! `ATSN` and a little-endian format version, followed by the bincode
+//! encoding of [`Snapshot`]. Readers reject versions they do not know.
+
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::formats::{FileFormat, FileType};
+
+const MAGIC: &[u8; 4] = b"ATSN";
+
+/// Current snapshot format version.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub cpu: CpuState,
+    pub instructions: u64,
+    pub cycles: u64,
+    pub system: SystemState,
+    /// Interrupt controller enable mask.
+    pub interrupt_enable: u8,
+    pub mmu: Option<MmuState>,
+    pub caches: Option<CachesState>,
+    /// Non-zero pages of physical memory, in address order.
+    pub memory: Vec<MemoryPage>,
+    pub devices: Vec<DeviceState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuState {
+    pub registers: [u8; 16],
+    pub sr: u8,
+    pub halted: bool,
+}
+
+/// Exception registers at `0xff10`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemState {
+    pub vbr: u16,
+    pub epc: u16,
+    pub esr: u8,
+    pub cause: u8,
+    pub earg: u8,
+    pub eaddr: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmuState {
+    pub enabled: bool,
+    pub pid: u8,
+    pub ptbr: u32,
+    pub fault_address: u16,
+    pub fault_status: u8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachesState {
+    pub l1i: CacheState,
+    pub l1d: CacheState,
+    pub l2: CacheState,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheState {
+    pub line_size: u32,
+    pub ways: u32,
+    pub sets: u32,
+    /// LRU clock.
+    pub clock: u64,
+    pub lines: Vec<CacheLineState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheLineState {
+    pub valid: bool,
+    pub dirty: bool,
+    pub tag: u32,
+    pub last_used: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryPage {
+    /// Physical address of the first byte.
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+/// Device-defined state of the device mapped at `base`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub name: String,
+    pub base: u32,
+    pub data: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self { version: SNAPSHOT_VERSION, ..Default::default() }
+    }
+}
+
+impl FileFormat for Snapshot {
+    fn from_file(path: &str) -> std::io::Result<Self> where Self: Sized {
+        let mut file = File::open(path)?;
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid magic number"));
+        }
+
+        let mut version_bytes = [0u8; 4];
+        file.read_exact(&mut version_bytes)?;
+        let version = u32::from_le_bytes(version_bytes);
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported snapshot version {} (expected {})", version, SNAPSHOT_VERSION),
+            ));
+        }
+
+        let snapshot: Snapshot = bincode::deserialize_from(&mut file)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Corrupt snapshot: {}", e)))?;
+        Ok(Self { version, ..snapshot })
+    }
+
+    fn to_file(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut file, self).map_err(|e| Error::other(e.to_string()))?;
+        Ok(())
+    }
+
+    fn format(&self) -> FileType {
+        FileType::Snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_other_versions() {
+        let path = std::env::temp_dir().join(format!("atlas-snapshot-{}.snap", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut snapshot = Snapshot::new();
+        snapshot.cpu.registers[9] = 6;
+        snapshot.cycles = 1234;
+        snapshot.mmu = Some(MmuState { enabled: true, ptbr: 0x10000, ..Default::default() });
+        snapshot.memory.push(MemoryPage { address: 0x100, data: vec![1, 2, 3] });
+        snapshot.devices.push(DeviceState { name: "timer".into(), base: 0xff38, data: vec![4] });
+        snapshot.to_file(path).unwrap();
+        assert_eq!(Snapshot::from_file(path).unwrap(), snapshot);
+
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[4] = 99;
+        std::fs::write(path, bytes).unwrap();
+        let error = Snapshot::from_file(path).unwrap_err();
+        assert!(error.to_string().contains("version 99"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 pub mod formats;
 
-pub use formats::obj::{ObjectFile, Symbol, SymbolBinding, Relocation};
+pub use formats::obj::{ObjectFile, Symbol, SymbolBinding, Relocation, RelocationKind, OBJECT_VERSION};
 pub use formats::hex;
+pub use formats::line_table::{LineTable, SourceLine, LINE_TABLE_SECTION};
+pub use formats::snapshot::Snapshot;
 
 pub use formats::FileFormat;
@@ -1,5 +1,5 @@
 
-use atlas_files::{ObjectFile, SymbolBinding};
+use atlas_files::{ObjectFile, SourceLine, SymbolBinding, LINE_TABLE_SECTION};
 use atlas_isa::ParsedInstruction;
 use atlas_isa::opcode::*;
 use atlas_isa::operands::*;
@@ -11,19 +11,19 @@ fn use_colour() -> bool {
     std::env::var_os("NO_COLOR").is_none()
 }
 
-fn dim(s: &str) -> String {
+pub fn dim(s: &str) -> String {
     if use_colour() { format!("\x1b[2m{}\x1b[0m", s) } else { s.to_string() }
 }
-fn bold(s: &str) -> String {
+pub fn bold(s: &str) -> String {
     if use_colour() { format!("\x1b[1m{}\x1b[0m", s) } else { s.to_string() }
 }
-fn green(s: &str) -> String {
+pub fn green(s: &str) -> String {
     if use_colour() { format!("\x1b[32m{}\x1b[0m", s) } else { s.to_string() }
 }
-fn cyan(s: &str) -> String {
+pub fn cyan(s: &str) -> String {
     if use_colour() { format!("\x1b[36m{}\x1b[0m", s) } else { s.to_string() }
 }
-fn yellow(s: &str) -> String {
+pub fn yellow(s: &str) -> String {
     if use_colour() { format!("\x1b[33m{}\x1b[0m", s) } else { s.to_string() }
 }
 
@@ -31,7 +31,10 @@ fn yellow(s: &str) -> String {
 
 /// Print a single-line summary after assembly.
 pub fn print_asm_summary(input: &str, output: &str, obj: &ObjectFile) {
-    let total_bytes: usize = obj.sections.iter().map(|s| s.data.len()).sum();
+    let total_bytes: usize = obj.sections.iter()
+        .filter(|s| s.name != LINE_TABLE_SECTION)
+        .map(|s| s.data.len())
+        .sum();
     let sym_count = obj.symbols.len();
     let reloc_count = obj.relocations.len();
     println!(
@@ -58,6 +61,180 @@ pub fn print_link_summary(inputs: &[String], output: &str, byte_count: usize) {
     );
 }
 
+/// Print a single-line summary after running a program.
+pub fn print_run_summary(inputs: &[String], reason: &str, steps: u64, cycles: u64) {
+    let joined = inputs.iter()
+        .map(|s| bold(s))
+        .collect::<Vec<_>>()
+        .join(&dim(" + "));
+    println!(
+        "        {} {} → {} {}",
+        green("Ran"),
+        joined,
+        bold(reason),
+        dim(&format!("({} instructions, {} cycles)", steps, cycles)),
+    );
+}
+
+// ── Register file ──────────────────────────────────────────────────────────
+
+/// Print the register file, the TR/SP/PC pairs and the status flags.
+pub fn print_registers(registers: &[u8; 16], flags: &str) {
+    println!("  {}", bold("Registers:"));
+    for row in 0..4 {
+        let line = (0..4)
+            .map(|col| {
+                let r = row * 4 + col;
+                format!("{:>4} 0x{:02x}", format!("r{}", r), registers[r])
+            })
+            .collect::<Vec<_>>()
+            .join("   ");
+        println!("    {}", line);
+    }
+    let pair = |high: usize| ((registers[high] as u16) << 8) | registers[high + 1] as u16;
+    println!(
+        "    {} 0x{:04x}   {} 0x{:04x}   {} 0x{:04x}   {} {}",
+        cyan("tr"), pair(10),
+        cyan("sp"), pair(12),
+        cyan("pc"), pair(14),
+        cyan("sr"), flags,
+    );
+}
+
+// ── Profiles ───────────────────────────────────────────────────────────────
+
+/// Instructions and cycles charged to one label or function.
+pub struct ProfileRow<'a> {
+    pub name: &'a str,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+/// Print a profile table under `title`, with each row's share of
+/// `total_cycles`.
+pub fn print_profile(title: &str, rows: &[ProfileRow], total_cycles: u64) {
+    println!("  {}", bold(title));
+    let width = rows.iter().map(|row| row.name.len()).max().unwrap_or(0).max(16);
+    for row in rows {
+        let share = if total_cycles == 0 { 0.0 } else { 100.0 * row.cycles as f64 / total_cycles as f64 };
+        println!(
+            "    {} {:>8} instr {:>10} cycles {}",
+            cyan(&format!("{:<width$}", row.name)),
+            row.instructions,
+            row.cycles,
+            dim(&format!("({:.1}%)", share)),
+        );
+    }
+}
+
+// ── Execution traces ───────────────────────────────────────────────────────
+
+/// First line where a trace departs from its golden trace: 1-based line
+/// number and the expected and actual lines (`None` past the end of either).
+pub struct TraceMismatch<'a> {
+    pub line: usize,
+    pub expected: Option<&'a str>,
+    pub actual: Option<&'a str>,
+}
+
+/// Print the result of checking a run's trace against a golden trace.
+pub fn print_trace_check(golden: &str, entries: usize, mismatch: Option<&TraceMismatch>) {
+    let Some(mismatch) = mismatch else {
+        println!(
+            "     {} {} {}",
+            green("Traced"),
+            bold(golden),
+            dim(&format!("({} instructions match)", entries)),
+        );
+        return;
+    };
+    println!(
+        "     {} {} {}",
+        yellow("Traced"),
+        bold(golden),
+        dim(&format!("(first difference at line {})", mismatch.line)),
+    );
+    println!("  {} {}", dim("expected:"), mismatch.expected.unwrap_or("<end of trace>"));
+    println!("  {} {}", dim("  actual:"), mismatch.actual.unwrap_or("<end of trace>"));
+}
+
+// ── Symbols ────────────────────────────────────────────────────────────────
+
+/// Describe `address` relative to the nearest preceding label, e.g.
+/// `loop+0x4`.
+pub fn symbolize(labels: &BTreeMap<u16, String>, address: u16) -> Option<String> {
+    let (base, name) = labels.range(..=address).next_back()?;
+    Some(match address - base {
+        0 => name.clone(),
+        offset => format!("{}+0x{:x}", name, offset),
+    })
+}
+
+// ── Test results ───────────────────────────────────────────────────────────
+
+/// Print the line that starts a test run.
+pub fn print_test_start(count: usize) {
+    println!();
+    println!("running {} test{}", count, if count == 1 { "" } else { "s" });
+}
+
+/// Print one test's outcome as it finishes.
+pub fn print_test_outcome(name: &str, passed: bool) {
+    let outcome = if passed { green("ok") } else { yellow("FAILED") };
+    println!("test {} ... {}", name, outcome);
+}
+
+/// Print the failure details and the closing `test result:` line.
+pub fn print_test_summary(failures: &[(String, Vec<String>)], passed: usize) {
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for (name, reasons) in failures {
+            println!();
+            println!("---- {} ----", name);
+            for reason in reasons {
+                println!("  {}", reason);
+            }
+        }
+        println!();
+        println!("failures:");
+        for (name, _) in failures {
+            println!("    {}", name);
+        }
+    }
+    let result = if failures.is_empty() { green("ok") } else { yellow("FAILED") };
+    println!();
+    println!("test result: {}. {} passed; {} failed", result, passed, failures.len());
+    println!();
+}
+
+// ── Coverage ───────────────────────────────────────────────────────────────
+
+/// Coverage of one source file: (covered, total) lines and branch outcomes.
+pub struct CoverageSummary<'a> {
+    pub file: &'a str,
+    pub lines: (usize, usize),
+    pub branches: (usize, usize),
+}
+
+/// Print a per-file coverage table and where the report was written.
+pub fn print_coverage_summary(files: &[CoverageSummary], report: &str) {
+    fn ratio((hit, total): (usize, usize)) -> String {
+        if total == 0 {
+            return format!("{:>16}", "-");
+        }
+        format!("{:>4}/{:<4} {:>6.1}%", hit, total, 100.0 * hit as f64 / total as f64)
+    }
+    let width = files.iter().map(|f| f.file.len()).max().unwrap_or(0).max(4);
+    println!("  {}", bold("Coverage:"));
+    println!("    {}  {}  {}", dim(&format!("{:<width$}", "file")), dim(&format!("{:>16}", "lines")), dim(&format!("{:>16}", "branches")));
+    for file in files {
+        println!("    {}  {}  {}", cyan(&format!("{:<width$}", file.file)), ratio(file.lines), ratio(file.branches));
+    }
+    println!();
+    println!("        {} {}", green("Wrote"), bold(report));
+}
+
 // ── Verbose: object file details ───────────────────────────────────────────
 
 /// Print detailed object file information (verbose mode).
@@ -113,11 +290,12 @@ pub fn inspect_obj(obj: &ObjectFile) {
             String::new()
         };
         println!(
-            "    {}+0x{:04x} → {}{}",
+            "    {}+0x{:04x} → {}{} {}",
             cyan(&rel.section),
             rel.offset,
             bold(&rel.symbol),
             addend_str,
+            dim(&rel.kind.to_string()),
         );
     }
 }
@@ -135,18 +313,63 @@ pub fn build_label_map(obj: &ObjectFile) -> BTreeMap<u16, String> {
     map
 }
 
+/// Build an address→source line map from an object file's line table,
+/// covering its `.text` section.
+pub fn build_line_map(obj: &ObjectFile) -> BTreeMap<u16, SourceLine> {
+    let Ok(table) = obj.line_table() else {
+        return BTreeMap::new();
+    };
+    table
+        .entries
+        .into_iter()
+        .filter(|entry| entry.section == ".text")
+        .map(|entry| (entry.offset as u16, entry.source))
+        .collect()
+}
+
 // ── Verbose: disassembly ───────────────────────────────────────────────────
 
 /// Disassemble raw bytes (big-endian 16-bit instruction words) and print them
-/// in a human-readable format.
-pub fn disassemble(data: &[u8], labels: &BTreeMap<u16, String>) {
+/// in a human-readable format, noting the source line of each instruction
+/// found in `lines`.
+pub fn disassemble(data: &[u8], labels: &BTreeMap<u16, String>, lines: &BTreeMap<u16, SourceLine>) {
     println!("  {}", bold("Disassembly of .text:"));
-    if data.len() % 2 != 0 {
+    if !data.len().is_multiple_of(2) {
         println!("    {} data length ({}) is not a multiple of 2", yellow("warning:"), data.len());
     }
+    print_disassembly(data, 0, labels, lines, None);
+}
+
+/// Disassemble `data` loaded at `base`, marking the instruction at `pc`.
+/// Used by the debugger to show the code around the program counter.
+pub fn disassemble_window(
+    data: &[u8],
+    base: u16,
+    labels: &BTreeMap<u16, String>,
+    lines: &BTreeMap<u16, SourceLine>,
+    pc: u16,
+) {
+    print_disassembly(data, base, labels, lines, Some(pc));
+}
+
+/// Disassemble the instruction word at `address`, or render it as `.word` if
+/// it does not decode. Relative branches show their target address.
+pub fn format_word(word: u16, address: u16, labels: &BTreeMap<u16, String>) -> String {
+    match ParsedInstruction::decode(word) {
+        Ok(instr) => format_instruction(&instr, address, labels),
+        Err(_) => format!(".word 0x{:04x}", word),
+    }
+}
 
+fn print_disassembly(
+    data: &[u8],
+    base: u16,
+    labels: &BTreeMap<u16, String>,
+    lines: &BTreeMap<u16, SourceLine>,
+    pc: Option<u16>,
+) {
     for offset in (0..data.len()).step_by(2) {
-        let addr = offset as u16;
+        let addr = base.wrapping_add(offset as u16);
 
         // Print label if one exists at this address
         if let Some(name) = labels.get(&addr) {
@@ -154,9 +377,16 @@ pub fn disassemble(data: &[u8], labels: &BTreeMap<u16, String>) {
             println!("  {}:", bold(name));
         }
 
+        let marker = match pc {
+            Some(pc) if pc == addr => green("→ "),
+            Some(_) => "  ".to_string(),
+            None => String::new(),
+        };
+
         if offset + 1 >= data.len() {
             println!(
-                "    {} {}  .byte 0x{:02x}",
+                "  {}  {} {}  .byte 0x{:02x}",
+                marker,
                 dim(&format!("{:04x}:", addr)),
                 dim(&format!("{:02x}", data[offset])),
                 data[offset]
@@ -165,24 +395,24 @@ pub fn disassemble(data: &[u8], labels: &BTreeMap<u16, String>) {
         }
 
         let word = ((data[offset] as u16) << 8) | (data[offset + 1] as u16);
-
-        let disasm = match ParsedInstruction::decode(word) {
-            Ok(instr) => format_instruction(&instr, labels),
-            Err(_) => format!(".word 0x{:04x}", word),
+        let source = match lines.get(&addr) {
+            Some(source) => format!("{:<26}{}", format_word(word, addr, labels), dim(&format!("; {}", source))),
+            None => format_word(word, addr, labels),
         };
 
         println!(
-            "    {} {}  {}",
+            "  {}  {} {}  {}",
+            marker,
             dim(&format!("{:04x}:", addr)),
             dim(&format!("{:04x}", word)),
-            disasm,
+            source,
         );
     }
 }
 
 // ── Instruction formatting ─────────────────────────────────────────────────
 
-fn format_instruction(instr: &ParsedInstruction, labels: &BTreeMap<u16, String>) -> String {
+fn format_instruction(instr: &ParsedInstruction, address: u16, labels: &BTreeMap<u16, String>) -> String {
     match instr {
         ParsedInstruction::A { op, dest, source, .. } => {
             // NOP detection: add r0, r0
@@ -205,9 +435,16 @@ fn format_instruction(instr: &ParsedInstruction, labels: &BTreeMap<u16, String>)
             };
             format!("{:<8} {}, [{}, {}]", mnemonic, reg_name(*dest), reg_name(*base), off_str)
         }
-        ParsedInstruction::BI { cond, operand, .. } => {
+        ParsedInstruction::BI { absolute, cond, operand, .. } => {
             let mnemonic = branch_cond_name(*cond);
-            let target = format_operand(operand, labels);
+            let target = match operand {
+                // Offsets count from the following instruction
+                Operand::Immediate(offset) if !absolute => {
+                    let target = address.wrapping_add(2).wrapping_add(*offset as u8 as i8 as u16);
+                    format_operand(&Operand::Immediate(target), labels)
+                }
+                _ => format_operand(operand, labels),
+            };
             format!("{:<8} {}", mnemonic, target)
         }
         ParsedInstruction::BR { cond, source, .. } => {
@@ -195,7 +195,7 @@ impl ParsedInstruction {
                 // I-type: bits [15:12]=type_field (1+operation), dest in [11:8], imm in [7:0]
                 let op_val = (opcode - 1) as u8;
                 let dest = ((encoded >> 8) & 0xF) as u8;
-                let immediate = (encoded & 0xFF) as u16;
+                let immediate = encoded & 0xFF;
 
                 let op = match op_val {
                     0 => ImmOp::LDI,
@@ -240,7 +240,7 @@ impl ParsedInstruction {
                 // BI-type: bits [15:12]=1000, absolute in [11], condition in [10:8], address in [7:0]
                 let absolute = ((encoded >> 11) & 1) != 0;
                 let cond_val = ((encoded >> 8) & 0x7) as u8;
-                let address = (encoded & 0xFF) as u16;
+                let address = encoded & 0xFF;
 
                 let cond = match cond_val {
                     0 => BranchCond::Unconditional,
@@ -315,7 +315,7 @@ impl ParsedInstruction {
             11 => {
                 // P-type peek: [15:12]=1011, [11:8]=register, [7:0]=offset
                 let register = ((encoded >> 8) & 0xF) as u8;
-                let offset = (encoded & 0xFF) as u16;
+                let offset = encoded & 0xFF;
 
                 Ok(ParsedInstruction::P {
                     op: PeekPokeOp::PEEK,
@@ -328,7 +328,7 @@ impl ParsedInstruction {
             12 => {
                 // P-type poke: [15:12]=1100, [11:8]=register, [7:0]=offset
                 let register = ((encoded >> 8) & 0xF) as u8;
-                let offset = (encoded & 0xFF) as u16;
+                let offset = encoded & 0xFF;
 
                 Ok(ParsedInstruction::P {
                     op: PeekPokeOp::POKE,
@@ -69,6 +69,7 @@ pub enum Mnemonic {
     DEC,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum InstructionFormat {
     A,
     I,
@@ -150,6 +151,34 @@ pub enum ParsedInstruction {
 }
 
 impl ParsedInstruction {
+    /// Encoding format of the instruction. BI and BR are both [`InstructionFormat::B`].
+    pub fn format(&self) -> InstructionFormat {
+        match self {
+            ParsedInstruction::A { .. } => InstructionFormat::A,
+            ParsedInstruction::I { .. } => InstructionFormat::I,
+            ParsedInstruction::M { .. } => InstructionFormat::M,
+            ParsedInstruction::BI { .. } | ParsedInstruction::BR { .. } => InstructionFormat::B,
+            ParsedInstruction::S { .. } => InstructionFormat::S,
+            ParsedInstruction::P { .. } => InstructionFormat::P,
+            ParsedInstruction::X { .. } => InstructionFormat::X,
+        }
+    }
+
+    /// Operation code within the instruction's format (the branch condition
+    /// for B-type instructions).
+    pub fn opcode(&self) -> u8 {
+        match self {
+            ParsedInstruction::A { op, .. } => *op as u8,
+            ParsedInstruction::I { op, .. } => *op as u8,
+            ParsedInstruction::M { op, .. } => *op as u8,
+            ParsedInstruction::BI { cond, .. } => *cond as u8,
+            ParsedInstruction::BR { cond, .. } => *cond as u8,
+            ParsedInstruction::S { op, .. } => *op as u8,
+            ParsedInstruction::P { op, .. } => *op as u8,
+            ParsedInstruction::X { op, .. } => *op as u8,
+        }
+    }
+
     pub fn line(&self) -> usize {
         match self {
             ParsedInstruction::A { line, .. } => *line,
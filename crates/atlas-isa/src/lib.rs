@@ -12,7 +12,7 @@ pub mod encoder;
 pub mod encoding_error;
 
 // Re-export commonly used types
-pub use instruction::{Mnemonic, ParsedInstruction};
+pub use instruction::{InstructionFormat, Mnemonic, ParsedInstruction};
 pub use opcode::{AluOp, BranchCond, ImmOp, MemOp, PeekPokeOp, StackOp, XTypeOp};
 pub use operands::{BranchOperand, MOffset, Operand, RegisterIdentifier, RegisterPairIdentifier, XOperand};
 pub use encoding_error::EncodingError;
\ No newline at end of file
@@ -136,6 +136,7 @@ impl Mnemonic {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(mnemonic: &str) -> Option<Self> {
         let mnemonic = mnemonic.to_lowercase();
         match mnemonic.as_str() {
@@ -4,10 +4,10 @@ pub mod error;
 pub mod linker;
 
 pub use error::{LinkerError, LinkerErrorKind};
-pub use linker::{LabelMap, Linker};
+pub use linker::{LabelMap, LinkedImage, Linker};
 
-use std::collections::BTreeMap;
-use atlas_files::{ObjectFile, FileFormat, SymbolBinding};
+use std::collections::{BTreeMap, HashMap};
+use atlas_files::{ObjectFile, FileFormat, RelocationKind, SourceLine, SymbolBinding, LINE_TABLE_SECTION};
 
 /// Link object files into a final executable binary.
 ///
@@ -17,11 +17,10 @@ use atlas_files::{ObjectFile, FileFormat, SymbolBinding};
 /// 3. Builds a global symbol table (adjusting symbol offsets to account for
 ///    section placement).
 /// 4. Applies relocations – patching the raw bytes wherever an unresolved
-///    label reference was left by the assembler.
+///    label reference was left by the assembler – and relocates the debug
+///    line tables.
 /// 5. Writes the final flat binary to `output`.
 pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
-    let mut linker = Linker::new();
-
     // ── 1. Load all object files ───────────────────────────────────────
     let mut loaded: Vec<(String, ObjectFile)> = Vec::new();
     for obj_path in object_files {
@@ -36,6 +35,35 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
         loaded.push((obj_path.to_string(), obj_file));
     }
 
+    let image = link_objects(&loaded)?;
+
+    // ── 5. Write output ──────────────────────────────────────────────
+    // Choose format based on file extension
+    let write_result = if output.ends_with(".hex") {
+        atlas_files::hex::write_hex_file(output, &image.data, 0x0000)
+    } else {
+        // Raw binary (default for .bin or any other extension)
+        use std::io::Write;
+        std::fs::File::create(output).and_then(|mut f| f.write_all(&image.data))
+    };
+
+    write_result.map_err(|e| {
+        LinkerError::new(
+            LinkerErrorKind::Io,
+            format!("Failed to write output file '{}': {}", output, e),
+            0,
+            Some(output.to_string()),
+        )
+    })?;
+
+    Ok(())
+}
+
+/// Link already-loaded object files (paired with the name used in error
+/// messages) into an in-memory image. This performs steps 2–4 of [`link`].
+pub fn link_objects(loaded: &[(String, ObjectFile)]) -> Result<LinkedImage, LinkerError> {
+    let mut linker = Linker::new();
+
     // ── 2. Merge sections & build section-base-address map ─────────────
     // We merge all sections with the same name, appending data in input
     // order.  `section_bases` records, per (file-index, section-name), the
@@ -47,6 +75,10 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
 
     for (file_idx, (_path, obj)) in loaded.iter().enumerate() {
         for section in &obj.sections {
+            // Line tables are relocated into `LinkedImage::lines` instead
+            if section.name == LINE_TABLE_SECTION {
+                continue;
+            }
             let merged = merged_sections.entry(section.name.clone()).or_default();
             let base = merged.len() as u32;
             section_bases.insert((file_idx, section.name.clone()), base);
@@ -54,7 +86,22 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
         }
     }
 
+    // Sections are laid out in a deterministic order: .text first, then the
+    // rest by name. Symbols resolve to their address in that layout.
+    let mut layout: Vec<String> = merged_sections.keys().filter(|name| *name == ".text").cloned().collect();
+    layout.extend(merged_sections.keys().filter(|name| *name != ".text").cloned());
+    let mut section_addresses: BTreeMap<String, u32> = BTreeMap::new();
+    let mut address = 0;
+    for name in &layout {
+        section_addresses.insert(name.clone(), address);
+        address += merged_sections[name].len() as u32;
+    }
+
     // ── 3. Build global symbol table ───────────────────────────────────
+    // Each file's own symbols are also kept separately so that references
+    // to local labels resolve within the file that defines them.
+    let mut file_symbols: Vec<HashMap<String, u16>> = vec![HashMap::new(); loaded.len()];
+    let mut symbols: BTreeMap<u16, String> = BTreeMap::new();
     for (file_idx, (path, obj)) in loaded.iter().enumerate() {
         for symbol in &obj.symbols {
             // Skip undefined / import symbols (section == None)
@@ -66,6 +113,7 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
             // Absolute constants (e.g. .imm values) are not relocated
             if section_name == ".abs" {
                 linker.register_label(symbol.name.clone(), symbol.value as u16);
+                file_symbols[file_idx].insert(symbol.name.clone(), symbol.value as u16);
                 continue;
             }
 
@@ -73,27 +121,43 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
                 .get(&(file_idx, section_name.clone()))
                 .copied()
                 .unwrap_or(0);
-            let absolute_address = base + symbol.value;
+            let section_address = section_addresses.get(&section_name).copied().unwrap_or(0);
+            let absolute_address = section_address + base + symbol.value;
 
             // For global symbols, check for duplicates
-            if matches!(symbol.binding, SymbolBinding::Global) {
-                if let Some(existing) = linker.label_map.get(&symbol.name) {
-                    return Err(LinkerError::new(
-                        LinkerErrorKind::DuplicateSymbol,
-                        format!(
-                            "Duplicate global symbol '{}' (first defined at 0x{:04x}, also in '{}')",
-                            symbol.name, existing, path
-                        ),
-                        0,
-                        Some(path.clone()),
-                    ));
-                }
+            if matches!(symbol.binding, SymbolBinding::Global)
+                && let Some(existing) = linker.label_map.get(&symbol.name)
+            {
+                return Err(LinkerError::new(
+                    LinkerErrorKind::DuplicateSymbol,
+                    format!(
+                        "Duplicate global symbol '{}' (first defined at 0x{:04x}, also in '{}')",
+                        symbol.name, existing, path
+                    ),
+                    0,
+                    Some(path.clone()),
+                ));
             }
             linker.register_label(symbol.name.clone(), absolute_address as u16);
+            symbols.entry(absolute_address as u16).or_insert_with(|| symbol.name.clone());
+            file_symbols[file_idx].insert(symbol.name.clone(), absolute_address as u16);
         }
     }
 
     // ── 4. Apply relocations ───────────────────────────────────────────
+    // The line tables locate relocations in the source for error messages.
+    let mut line_tables = Vec::new();
+    for (path, obj) in loaded {
+        line_tables.push(obj.line_table().map_err(|e| {
+            LinkerError::new(
+                LinkerErrorKind::ObjectFile,
+                format!("Invalid line table in '{}': {}", path, e),
+                0,
+                Some(path.clone()),
+            )
+        })?);
+    }
+
     for (file_idx, (path, obj)) in loaded.iter().enumerate() {
         for reloc in &obj.relocations {
             let section_name = &reloc.section;
@@ -102,9 +166,12 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
                 .copied()
                 .unwrap_or(0);
             let patch_offset = (base + reloc.offset) as usize;
+            let place = section_addresses.get(section_name).copied().unwrap_or(0) + base + reloc.offset;
 
-            // Resolve the symbol
-            let symbol_value = linker.label_map.get(&reloc.symbol).ok_or_else(|| {
+            // Resolve the symbol, preferring the referencing file's own
+            // definition over one from another file
+            let local_value = file_symbols[file_idx].get(&reloc.symbol).copied();
+            let symbol_value = local_value.or_else(|| linker.label_map.get(&reloc.symbol)).ok_or_else(|| {
                 LinkerError::new(
                     LinkerErrorKind::UnresolvedLabel,
                     format!("Unresolved symbol '{}' referenced in '{}'", reloc.symbol, path),
@@ -113,14 +180,50 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
                 )
             })?;
 
-            let final_value = (symbol_value as i32 + reloc.addend) as u16;
+            let mut value = symbol_value as i32 + reloc.addend;
+            if reloc.kind == RelocationKind::Rel8 {
+                // Branch offsets count from the following instruction
+                value -= place as i32 + 2;
+            }
+
+            let (min, max, field) = relocation_range(reloc.kind);
+            if !(min..=max).contains(&value) {
+                let source = line_tables[file_idx]
+                    .entries
+                    .iter()
+                    .find(|entry| entry.section == *section_name && entry.offset == reloc.offset)
+                    .map(|entry| &entry.source);
+                let hint = match reloc.kind {
+                    RelocationKind::Imm8 => " (use hi()/lo() for 16-bit addresses)",
+                    RelocationKind::Rel8 => {
+                        " (branches across sections and files are not relaxed; branch through TR loaded with hi()/lo())"
+                    }
+                    _ => "",
+                };
+                return Err(LinkerError::new(
+                    LinkerErrorKind::Encoding,
+                    format!(
+                        "{} relocation of '{}' at {}+0x{:04x} in '{}' resolves to {}, outside the {} range {}..={}{}",
+                        reloc.kind,
+                        symbol_with_addend(&reloc.symbol, reloc.addend),
+                        section_name,
+                        reloc.offset,
+                        path,
+                        value,
+                        field,
+                        min,
+                        max,
+                        hint
+                    ),
+                    source.map_or(0, |s| s.line as usize),
+                    Some(source.map_or_else(|| path.clone(), |s| s.file.clone())),
+                ));
+            }
 
             // Patch the merged section data.
             // Instructions are 16-bit big-endian.  The relocation offset
-            // points to the start of the 2-byte instruction.  We need to
-            // patch the lower 8 bits of the instruction word (the
-            // immediate/address field) while keeping the upper bits
-            // (opcode, etc.) intact.
+            // points to the start of the 2-byte instruction, whose upper
+            // byte (opcode, etc.) is kept intact.
             let section_data = merged_sections.get_mut(section_name).ok_or_else(|| {
                 LinkerError::new(
                     LinkerErrorKind::ObjectFile,
@@ -144,61 +247,147 @@ pub fn link(object_files: &[&str], output: &str) -> Result<(), LinkerError> {
                 ));
             }
 
-            // Read current instruction word (big-endian)
-            let hi = section_data[patch_offset];
-            let _lo = section_data[patch_offset + 1];
-
-            // Keep the upper byte (opcode + flags) and replace the lower
-            // byte with the resolved address/immediate.
-            // This works for I-type (imm in [7:0]), BI-type (addr in
-            // [7:0]), and P-type (offset in [7:0]).
-            if final_value > 0xFF {
-                return Err(LinkerError::new(
-                    LinkerErrorKind::Encoding,
-                    format!(
-                        "Resolved value 0x{:04x} for symbol '{}' exceeds 8-bit immediate field",
-                        final_value, reloc.symbol
-                    ),
-                    0,
-                    Some(path.clone()),
-                ));
+            match reloc.kind {
+                // The lower byte holds the I-type immediate, the BI-type
+                // address or offset and the P-type offset
+                RelocationKind::Imm8 | RelocationKind::Lo8 | RelocationKind::Rel8 => {
+                    section_data[patch_offset + 1] = value as u8;
+                }
+                RelocationKind::Hi8 => section_data[patch_offset + 1] = (value >> 8) as u8,
+                // The M-type offset is the low nibble
+                RelocationKind::Offset4 => {
+                    let byte = &mut section_data[patch_offset + 1];
+                    *byte = (*byte & 0xF0) | (value as u8 & 0x0F);
+                }
+                // Data words are little-endian
+                RelocationKind::Word16 => {
+                    section_data[patch_offset] = value as u8;
+                    section_data[patch_offset + 1] = (value >> 8) as u8;
+                }
             }
-
-            section_data[patch_offset] = hi;
-            section_data[patch_offset + 1] = final_value as u8;
         }
     }
 
-    // ── 5. Write output ──────────────────────────────────────────────
-    // Output sections in a deterministic order: .text first, then the rest.
+    // Output sections in layout order.
     let mut output_bytes: Vec<u8> = Vec::new();
-    if let Some(text) = merged_sections.get(".text") {
-        output_bytes.extend_from_slice(text);
+    for name in &layout {
+        output_bytes.extend_from_slice(&merged_sections[name]);
     }
-    for (name, data) in &merged_sections {
-        if name == ".text" {
-            continue;
+
+    // Merge the line tables, relocating each entry to its image address.
+    let mut lines: BTreeMap<u16, SourceLine> = BTreeMap::new();
+    for (file_idx, table) in line_tables.into_iter().enumerate() {
+        for entry in table.entries {
+            let (Some(address), Some(base)) = (
+                section_addresses.get(&entry.section),
+                section_bases.get(&(file_idx, entry.section.clone())),
+            ) else {
+                continue;
+            };
+            lines.insert((address + base + entry.offset) as u16, entry.source);
         }
-        output_bytes.extend_from_slice(data);
     }
 
-    // Choose format based on file extension
-    let write_result = if output.ends_with(".hex") {
-        atlas_files::hex::write_hex_file(output, &output_bytes, 0x0000)
-    } else {
-        // Raw binary (default for .bin or any other extension)
-        use std::io::Write;
-        std::fs::File::create(output).and_then(|mut f| f.write_all(&output_bytes))
-    };
+    Ok(LinkedImage {
+        data: output_bytes,
+        labels: linker.label_map,
+        symbols,
+        lines,
+    })
+}
 
-    write_result.map_err(|e| {
-        LinkerError::new(
-            LinkerErrorKind::Io,
-            format!("Failed to write output file '{}': {}", output, e),
-            0,
-            Some(output.to_string()),
-        )
-    })?;
+/// The values a relocation of `kind` can hold, and the field it patches.
+fn relocation_range(kind: RelocationKind) -> (i32, i32, &'static str) {
+    match kind {
+        RelocationKind::Imm8 => (-128, 255, "8-bit immediate"),
+        RelocationKind::Rel8 => (-128, 127, "branch offset"),
+        RelocationKind::Offset4 => (-5, 7, "4-bit memory offset"),
+        RelocationKind::Lo8 | RelocationKind::Hi8 => (-32768, 65535, "16-bit address"),
+        RelocationKind::Word16 => (-32768, 65535, "16-bit word"),
+    }
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// `symbol`, `symbol+4` or `symbol-4`.
+fn symbol_with_addend(symbol: &str, addend: i32) -> String {
+    match addend {
+        0 => symbol.to_string(),
+        _ => format!("{}{:+}", symbol, addend),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(name: &str, source: &str) -> (String, ObjectFile) {
+        (name.to_string(), atlas_assembler::assemble_source(source, name).unwrap())
+    }
+
+    #[test]
+    fn local_labels_resolve_within_their_own_file() {
+        let a = assemble("a.asm", ".import b\n    br b\nloop:\n    br loop\n");
+        let b = assemble("b.asm", ".export b\nb:\n    nop\nloop:\n    br loop\n");
+        let image = link_objects(&[a, b]).unwrap();
+        assert_eq!(image.data, vec![0x80, 0x02, 0x80, 0xfe, 0x00, 0x00, 0x80, 0xfe]);
+        assert_eq!(image.labels.get("b"), Some(0x04));
+        assert_eq!(image.symbols.get(&0x06).map(String::as_str), Some("loop"));
+    }
+
+    #[test]
+    fn relocation_addends_are_applied() {
+        let a = assemble("a.asm", ".import b\n    ldi r1, b + 3\n    br  b - 2\n");
+        let b = assemble("b.asm", ".export b\n    nop\nb:\n    halt\n");
+        let image = link_objects(&[a, b]).unwrap();
+        assert_eq!(image.data, vec![0x11, 0x09, 0x80, 0x00, 0x00, 0x00, 0xd2, 0x00]);
+    }
+
+    #[test]
+    fn hi_and_lo_relocations_reach_past_the_first_256_bytes() {
+        let code = format!(".import buf\n    ldi r10, hi(buf)\n    ldi r11, lo(buf)\n{}", "    nop\n".repeat(200));
+        let a = assemble("a.asm", &code);
+        let b = assemble("b.asm", ".export buf\n.data\nbuf:\n    .byte 7\n");
+        let image = link_objects(&[a, b]).unwrap();
+        assert_eq!(image.labels.get("buf"), Some(0x194));
+        assert_eq!(&image.data[..4], &[0x1a, 0x01, 0x1b, 0x94]);
+        assert_eq!(image.data[0x194], 7);
+
+        let a = assemble("a.asm", &code.replace("hi(buf)", "buf"));
+        let b = assemble("b.asm", ".export buf\n.data\nbuf:\n    .byte 7\n");
+        assert!(link_objects(&[a, b]).is_err());
+    }
+
+    #[test]
+    fn typed_relocations_are_patched_and_range_checked() {
+        let source = ".import b\n.import off\n    ld r1, [r2 + off]\n    br b\n.data\n    .word b, b - 0x10\n";
+        let a = || assemble("a.asm", source);
+        let b = assemble("b.asm", ".export b\n.export off\noff: .imm 3\n    nop\nb:\n    halt\n");
+        let image = link_objects(&[a(), b]).unwrap();
+        assert_eq!(image.labels.get("b"), Some(0x06));
+        assert_eq!(image.data[1] & 0x0F, 3);
+        assert_eq!(a().1.relocations[1].kind, RelocationKind::Rel8);
+        assert_eq!(image.data[3], 2);
+        assert_eq!(&image.data[8..], &[0x06, 0x00, 0xf6, 0xff]);
+
+        let b = assemble("b.asm", ".export b\n.export off\noff: .imm 9\nb:\n    halt\n");
+        let err = link_objects(&[a(), b]).unwrap_err();
+        assert_eq!((err.source_file.as_deref(), err.line), (Some("a.asm"), 3));
+        assert!(err.message.contains("off4 relocation of 'off' at .text+0x0000 in 'a.asm' resolves to 9"));
+    }
+
+    #[test]
+    fn line_tables_are_relocated_and_merged() {
+        let a = assemble("a.asm", ".import b
+    br b
+");
+        let b = assemble("b.asm", ".export b
+; b
+b:
+    nop
+    halt
+");
+        let image = link_objects(&[a, b]).unwrap();
+        let lines: Vec<(u16, String)> = image.lines.iter().map(|(a, l)| (*a, l.to_string())).collect();
+        assert_eq!(lines, [(0, "a.asm:2".into()), (2, "b.asm:4".into()), (4, "b.asm:5".into())]);
+        assert_eq!(image.data.len(), 6);
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use atlas_files::SourceLine;
 use atlas_isa::{Operand, ParsedInstruction};
 
 use crate::error::{LinkerError, LinkerErrorKind};
@@ -15,6 +16,12 @@ pub struct LabelInfo {
     pub source_file: Option<String>,
 }
 
+impl Default for LabelMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LabelMap {
     pub fn new() -> Self {
         Self {
@@ -53,12 +60,35 @@ impl LabelMap {
     pub fn get_info(&self, label: &str) -> Option<&LabelInfo> {
         self.labels.get(label)
     }
+
+    /// Iterate over all labels and their resolved addresses
+    pub fn iter(&self) -> impl Iterator<Item = (&String, u16)> {
+        self.labels.iter().map(|(name, info)| (name, info.address))
+    }
+}
+
+/// The result of linking: the flat output image and the final symbol table
+#[derive(Debug, Clone)]
+pub struct LinkedImage {
+    pub data: Vec<u8>,
+    pub labels: LabelMap,
+    /// Address of every section label (not `.imm` constants), including
+    /// labels local to a file. The first label defined at an address wins.
+    pub symbols: BTreeMap<u16, String>,
+    /// Source line of every instruction assembled with a line table.
+    pub lines: BTreeMap<u16, SourceLine>,
 }
 
 pub struct Linker {
     pub label_map: LabelMap,
 }
 
+impl Default for Linker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Linker {
     pub fn new() -> Self {
         Self {
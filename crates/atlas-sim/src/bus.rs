@@ -0,0 +1,353 @@
+//! Memory bus abstraction.
+//!
+//! The CPU talks to the outside world only through a [`Bus`]. Memory layout
+//! is up to the system designer, so besides the flat [`Memory`] array a
+//! [`SystemBus`] can be assembled from RAM and ROM regions and memory-mapped
+//! [`Device`]s, each claiming an address range.
+//!
+//! Addresses on the bus are physical addresses. Without an MMU they are the
+//! CPU's 16-bit addresses; with one they can be up to 24 bits wide.
+//!
+//! [`Memory`]: crate::memory::Memory
+
+use std::fmt::Display;
+
+use atlas_files::formats::snapshot::DeviceState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusErrorKind {
+    /// Nothing is mapped at the address.
+    Unmapped,
+    /// The address belongs to a read-only region.
+    ReadOnly,
+    /// A new region would overlap an existing one.
+    Overlap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusError {
+    pub kind: BusErrorKind,
+    pub address: u32,
+}
+
+impl Display for BusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            BusErrorKind::Unmapped => write!(f, "no memory mapped at 0x{:06x}", self.address),
+            BusErrorKind::ReadOnly => write!(f, "write to read-only memory at 0x{:06x}", self.address),
+            BusErrorKind::Overlap => write!(f, "region at 0x{:06x} overlaps an existing mapping", self.address),
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+pub trait Bus {
+    /// Read a byte. Reads may have side effects on devices.
+    fn read(&mut self, address: u32) -> Result<u8, BusError>;
+
+    /// Write a byte.
+    fn write(&mut self, address: u32, value: u8) -> Result<(), BusError>;
+
+    /// Read a byte without side effects, for debuggers and dumps.
+    /// Returns `None` if nothing is mapped at the address.
+    fn peek(&self, address: u32) -> Option<u8>;
+
+    /// Store bytes starting at `address`, bypassing write protection.
+    /// Used to load images, including into ROM.
+    fn load(&mut self, address: u32, data: &[u8]) -> Result<(), BusError>;
+
+    /// `(base, size)` of every range backed by RAM or ROM: the memory
+    /// snapshots hold.
+    fn memory_ranges(&self) -> Vec<(u32, u32)>;
+
+    /// Whether accesses to `address` may be cached. Device registers must
+    /// not be.
+    fn cacheable(&self, _address: u32) -> bool {
+        true
+    }
+
+    /// Whether the CPU may write to `address`.
+    fn writable(&self, _address: u32) -> bool {
+        true
+    }
+    /// Whether any memory-mapped device is on the bus.
+    fn has_devices(&self) -> bool {
+        false
+    }
+
+    /// Let time pass for devices on the bus. Called once per simulator step
+    /// with the cycles that step took.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// State of every device on the bus, for snapshots.
+    fn save_devices(&self) -> Vec<DeviceState> {
+        Vec::new()
+    }
+
+    /// Restore a device saved by [`save_devices`](Bus::save_devices).
+    /// Returns false if no device of that name is mapped at its base.
+    fn restore_device(&mut self, _state: &DeviceState) -> bool {
+        false
+    }
+}
+
+/// A memory-mapped peripheral. Offsets are relative to the base address the
+/// device is mapped at.
+pub trait Device {
+    /// Short human-readable name, e.g. "uart".
+    fn name(&self) -> &str;
+
+    fn read(&mut self, offset: u32) -> u8;
+
+    fn write(&mut self, offset: u32, value: u8);
+
+    /// Side-effect-free read; defaults to reading as zero.
+    fn peek(&self, _offset: u32) -> u8 {
+        0
+    }
+    /// Advance the device's notion of time by `cycles`.
+    fn tick(&mut self, _cycles: u64) {}
+    /// Register state for snapshots, in a device-defined layout.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    /// Restore state produced by [`save_state`](Device::save_state).
+    fn restore_state(&mut self, _state: &[u8]) {}
+}
+
+enum RegionKind {
+    Ram(Vec<u8>),
+    Rom(Vec<u8>),
+    Device(Box<dyn Device>),
+}
+
+struct Region {
+    base: u32,
+    size: u32,
+    kind: RegionKind,
+}
+
+impl Region {
+    fn contains(&self, address: u32) -> bool {
+        address >= self.base && address - self.base < self.size
+    }
+}
+
+/// A bus built from RAM, ROM and device regions.
+#[derive(Default)]
+pub struct SystemBus {
+    regions: Vec<Region>,
+}
+
+impl SystemBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map `size` bytes of zero-initialised RAM at `base`.
+    pub fn map_ram(&mut self, base: u32, size: u32) -> Result<(), BusError> {
+        self.map(base, size, RegionKind::Ram(vec![0; size as usize]))
+    }
+
+    /// Map a ROM holding `data` at `base`.
+    pub fn map_rom(&mut self, base: u32, data: Vec<u8>) -> Result<(), BusError> {
+        let size = data.len() as u32;
+        self.map(base, size, RegionKind::Rom(data))
+    }
+
+    /// Map a device over `size` bytes starting at `base`.
+    pub fn map_device(&mut self, base: u32, size: u32, device: Box<dyn Device>) -> Result<(), BusError> {
+        self.map(base, size, RegionKind::Device(device))
+    }
+
+    /// Names and address ranges of all mapped regions, in address order.
+    pub fn regions(&self) -> Vec<(String, u32, u32)> {
+        let mut out: Vec<_> = self
+            .regions
+            .iter()
+            .map(|r| {
+                let name = match &r.kind {
+                    RegionKind::Ram(_) => "ram".to_string(),
+                    RegionKind::Rom(_) => "rom".to_string(),
+                    RegionKind::Device(d) => d.name().to_string(),
+                };
+                (name, r.base, r.size)
+            })
+            .collect();
+        out.sort_by_key(|(_, base, _)| *base);
+        out
+    }
+
+    fn map(&mut self, base: u32, size: u32, kind: RegionKind) -> Result<(), BusError> {
+        let end = base as u64 + size as u64;
+        let overlaps = self
+            .regions
+            .iter()
+            .any(|r| (base as u64) < r.base as u64 + r.size as u64 && (r.base as u64) < end);
+        if overlaps {
+            return Err(BusError { kind: BusErrorKind::Overlap, address: base });
+        }
+        self.regions.push(Region { base, size, kind });
+        Ok(())
+    }
+
+    fn region(&self, address: u32) -> Option<&Region> {
+        self.regions.iter().find(|r| r.contains(address))
+    }
+
+    fn region_mut(&mut self, address: u32) -> Result<&mut Region, BusError> {
+        self.regions
+            .iter_mut()
+            .find(|r| r.contains(address))
+            .ok_or(BusError { kind: BusErrorKind::Unmapped, address })
+    }
+}
+
+impl Bus for SystemBus {
+    fn read(&mut self, address: u32) -> Result<u8, BusError> {
+        let region = self.region_mut(address)?;
+        let offset = address - region.base;
+        Ok(match &mut region.kind {
+            RegionKind::Ram(data) | RegionKind::Rom(data) => data[offset as usize],
+            RegionKind::Device(device) => device.read(offset),
+        })
+    }
+
+    fn write(&mut self, address: u32, value: u8) -> Result<(), BusError> {
+        let region = self.region_mut(address)?;
+        let offset = address - region.base;
+        match &mut region.kind {
+            RegionKind::Ram(data) => data[offset as usize] = value,
+            RegionKind::Rom(_) => return Err(BusError { kind: BusErrorKind::ReadOnly, address }),
+            RegionKind::Device(device) => device.write(offset, value),
+        }
+        Ok(())
+    }
+
+    fn peek(&self, address: u32) -> Option<u8> {
+        let region = self.region(address)?;
+        let offset = address - region.base;
+        Some(match &region.kind {
+            RegionKind::Ram(data) | RegionKind::Rom(data) => data[offset as usize],
+            RegionKind::Device(device) => device.peek(offset),
+        })
+    }
+
+    fn memory_ranges(&self) -> Vec<(u32, u32)> {
+        self.regions
+            .iter()
+            .filter(|r| matches!(r.kind, RegionKind::Ram(_) | RegionKind::Rom(_)))
+            .map(|r| (r.base, r.size))
+            .collect()
+    }
+
+    fn cacheable(&self, address: u32) -> bool {
+        matches!(self.region(address).map(|r| &r.kind), Some(RegionKind::Ram(_) | RegionKind::Rom(_)))
+    }
+
+    fn writable(&self, address: u32) -> bool {
+        !matches!(self.region(address).map(|r| &r.kind), Some(RegionKind::Rom(_)))
+    }
+
+    fn has_devices(&self) -> bool {
+        self.regions.iter().any(|r| matches!(r.kind, RegionKind::Device(_)))
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for region in &mut self.regions {
+            if let RegionKind::Device(device) = &mut region.kind {
+                device.tick(cycles);
+            }
+        }
+    }
+
+    fn save_devices(&self) -> Vec<DeviceState> {
+        let mut devices: Vec<DeviceState> = self
+            .regions
+            .iter()
+            .filter_map(|r| match &r.kind {
+                RegionKind::Device(device) => Some(DeviceState {
+                    name: device.name().to_string(),
+                    base: r.base,
+                    data: device.save_state(),
+                }),
+                _ => None,
+            })
+            .collect();
+        devices.sort_by_key(|d| d.base);
+        devices
+    }
+
+    fn restore_device(&mut self, state: &DeviceState) -> bool {
+        match self.regions.iter_mut().find(|r| r.base == state.base).map(|r| &mut r.kind) {
+            Some(RegionKind::Device(device)) if device.name() == state.name => {
+                device.restore_state(&state.data);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn load(&mut self, address: u32, data: &[u8]) -> Result<(), BusError> {
+        for (i, byte) in data.iter().enumerate() {
+            let address = address + i as u32;
+            let region = self.region_mut(address)?;
+            let offset = address - region.base;
+            match &mut region.kind {
+                RegionKind::Ram(bytes) | RegionKind::Rom(bytes) => bytes[offset as usize] = *byte,
+                RegionKind::Device(device) => device.write(offset, *byte),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Latch(u8);
+
+    impl Device for Latch {
+        fn name(&self) -> &str {
+            "latch"
+        }
+
+        fn read(&mut self, _offset: u32) -> u8 {
+            self.0
+        }
+
+        fn write(&mut self, _offset: u32, value: u8) {
+            self.0 = value.wrapping_add(1);
+        }
+    }
+
+    #[test]
+    fn routes_accesses_by_region() {
+        let mut bus = SystemBus::new();
+        bus.map_rom(0x0000, vec![0x12, 0x34]).unwrap();
+        bus.map_ram(0x8000, 0x100).unwrap();
+        bus.map_device(0xFF00, 0x10, Box::new(Latch(0))).unwrap();
+
+        assert_eq!(bus.read(0x0001), Ok(0x34));
+        assert_eq!(bus.write(0x0000, 0xFF).unwrap_err().kind, BusErrorKind::ReadOnly);
+
+        bus.write(0x8010, 0xAB).unwrap();
+        assert_eq!(bus.read(0x8010), Ok(0xAB));
+
+        bus.write(0xFF03, 0x41).unwrap();
+        assert_eq!(bus.read(0xFF00), Ok(0x42));
+
+        assert_eq!(bus.read(0x4000).unwrap_err().kind, BusErrorKind::Unmapped);
+        assert_eq!(bus.peek(0x4000), None);
+    }
+
+    #[test]
+    fn rejects_overlapping_regions() {
+        let mut bus = SystemBus::new();
+        bus.map_ram(0x0000, 0x8000).unwrap();
+        let err = bus.map_ram(0x7F00, 0x200).unwrap_err();
+        assert_eq!(err.kind, BusErrorKind::Overlap);
+    }
+}
@@ -0,0 +1,565 @@
+//! Two-level cache model.
+//!
+//! Mirrors the cache extension in `isa.typ`: split 4-way L1 instruction and
+//! data caches with 32-byte lines, and a unified 8-way L2 with 64-byte lines.
+//! L1 is write-through (without allocating on a write miss), L2 is
+//! write-back with write-allocate.
+//!
+//! The caches hold real data, so software that forgets cache maintenance
+//! observes stale bytes just like on hardware: stores do not update the
+//! instruction cache until `icinv`, and dirty L2 lines do not reach memory
+//! until they are evicted or cleaned with `dcclean`/`flush`.
+//!
+//! Addresses the bus reports as uncacheable (memory-mapped devices) bypass
+//! the caches entirely.
+
+use atlas_files::formats::snapshot::{CacheLineState, CacheState, CachesState};
+
+use crate::bus::{Bus, BusError, BusErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    pub line_size: u32,
+    pub ways: u32,
+    pub sets: u32,
+}
+
+impl CacheConfig {
+    /// 1 KB, 4-way, 32-byte lines.
+    pub const L1: CacheConfig = CacheConfig { line_size: 32, ways: 4, sets: 8 };
+    /// 8 KB, 8-way, 64-byte lines.
+    pub const L2: CacheConfig = CacheConfig { line_size: 64, ways: 8, sets: 16 };
+
+    pub fn size(&self) -> u32 {
+        self.line_size * self.ways * self.sets
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Dirty lines written back to memory.
+    pub writebacks: u64,
+}
+
+impl CacheStats {
+    /// Fraction of accesses that hit, or 0 if there were none.
+    pub fn hit_rate(&self) -> f64 {
+        let accesses = self.hits + self.misses;
+        if accesses == 0 { 0.0 } else { self.hits as f64 / accesses as f64 }
+    }
+}
+
+/// Base address and contents of a dirty line leaving the cache.
+type Evicted = Option<(u32, Vec<u8>)>;
+
+#[derive(Debug, Clone)]
+struct Line {
+    valid: bool,
+    dirty: bool,
+    tag: u32,
+    last_used: u64,
+    data: Vec<u8>,
+}
+
+/// A single set-associative cache with LRU replacement.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    config: CacheConfig,
+    lines: Vec<Line>,
+    clock: u64,
+    pub stats: CacheStats,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        let line = Line { valid: false, dirty: false, tag: 0, last_used: 0, data: vec![0; config.line_size as usize] };
+        Self {
+            config,
+            lines: vec![line; (config.ways * config.sets) as usize],
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    fn line_base(&self, address: u32) -> u32 {
+        address & !(self.config.line_size - 1)
+    }
+
+    fn set_range(&self, address: u32) -> std::ops::Range<usize> {
+        let set = (address / self.config.line_size) % self.config.sets;
+        let start = (set * self.config.ways) as usize;
+        start..start + self.config.ways as usize
+    }
+
+    fn tag(&self, address: u32) -> u32 {
+        address / (self.config.line_size * self.config.sets)
+    }
+
+    /// Index of the line holding `address`, updating its LRU age.
+    fn lookup(&mut self, address: u32) -> Option<usize> {
+        let tag = self.tag(address);
+        let index = self.set_range(address).find(|&i| self.lines[i].valid && self.lines[i].tag == tag)?;
+        self.clock += 1;
+        self.lines[index].last_used = self.clock;
+        Some(index)
+    }
+
+    /// Choose a victim in the set of `address`: an invalid line if there is
+    /// one, otherwise the least recently used.
+    fn victim(&self, address: u32) -> usize {
+        self.set_range(address)
+            .min_by_key(|&i| (self.lines[i].valid, self.lines[i].last_used))
+            .expect("cache sets are never empty")
+    }
+
+    /// Base address of the line at `index`.
+    fn address_of(&self, index: usize) -> u32 {
+        let set = index as u32 / self.config.ways;
+        (self.lines[index].tag * self.config.sets + set) * self.config.line_size
+    }
+
+    /// Install `data` as the line containing `address`, returning the
+    /// evicted line's base address and data if it was dirty.
+    fn fill(&mut self, address: u32, data: Vec<u8>) -> (usize, Evicted) {
+        let index = self.victim(address);
+        let evicted = (self.lines[index].valid && self.lines[index].dirty)
+            .then(|| (self.address_of(index), self.lines[index].data.clone()));
+        self.clock += 1;
+        self.lines[index] = Line { valid: true, dirty: false, tag: self.tag(address), last_used: self.clock, data };
+        (index, evicted)
+    }
+
+    /// Discard every line, including dirty ones.
+    pub fn invalidate(&mut self) {
+        for line in &mut self.lines {
+            line.valid = false;
+            line.dirty = false;
+        }
+    }
+
+    /// Mark every dirty line clean, returning the lines to write back.
+    fn take_dirty(&mut self) -> Vec<(u32, Vec<u8>)> {
+        let mut dirty = Vec::new();
+        for index in 0..self.lines.len() {
+            if self.lines[index].valid && self.lines[index].dirty {
+                dirty.push((self.address_of(index), self.lines[index].data.clone()));
+                self.lines[index].dirty = false;
+            }
+        }
+        dirty
+    }
+
+    /// Byte at `address` if it is cached, without touching LRU state or
+    /// statistics.
+    pub fn peek(&self, address: u32) -> Option<u8> {
+        let tag = self.tag(address);
+        let offset = (address - self.line_base(address)) as usize;
+        self.set_range(address)
+            .find(|&i| self.lines[i].valid && self.lines[i].tag == tag)
+            .map(|i| self.lines[i].data[offset])
+    }
+
+    /// Overwrite the byte at `address` if it is cached, leaving LRU, dirty
+    /// state and statistics alone.
+    pub fn patch(&mut self, address: u32, value: u8) {
+        let tag = self.tag(address);
+        let offset = (address - self.line_base(address)) as usize;
+        if let Some(i) = self.set_range(address).find(|&i| self.lines[i].valid && self.lines[i].tag == tag) {
+            self.lines[i].data[offset] = value;
+        }
+    }
+
+    pub fn dirty_lines(&self) -> usize {
+        self.lines.iter().filter(|l| l.valid && l.dirty).count()
+    }
+
+    /// Contents and LRU state, for snapshots. Statistics are not included.
+    pub fn save_state(&self) -> CacheState {
+        CacheState {
+            line_size: self.config.line_size,
+            ways: self.config.ways,
+            sets: self.config.sets,
+            clock: self.clock,
+            lines: self
+                .lines
+                .iter()
+                .map(|l| CacheLineState {
+                    valid: l.valid,
+                    dirty: l.dirty,
+                    tag: l.tag,
+                    last_used: l.last_used,
+                    data: l.data.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns false, leaving the cache alone, if `state` was saved from a
+    /// cache with a different geometry.
+    pub fn restore_state(&mut self, state: &CacheState) -> bool {
+        let config = CacheConfig { line_size: state.line_size, ways: state.ways, sets: state.sets };
+        let fits = |l: &CacheLineState| l.data.len() == config.line_size as usize;
+        if config != self.config || state.lines.len() != self.lines.len() || !state.lines.iter().all(fits) {
+            return false;
+        }
+        self.clock = state.clock;
+        for (line, saved) in self.lines.iter_mut().zip(&state.lines) {
+            *line = Line {
+                valid: saved.valid,
+                dirty: saved.dirty,
+                tag: saved.tag,
+                last_used: saved.last_used,
+                data: saved.data.clone(),
+            };
+        }
+        true
+    }
+}
+
+/// Extra cycles charged when an access is not satisfied by L1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLatency {
+    pub l2_hit: u32,
+    pub memory: u32,
+}
+
+impl Default for CacheLatency {
+    fn default() -> Self {
+        Self { l2_hit: 4, memory: 12 }
+    }
+}
+
+/// L1 instruction and data caches backed by a unified L2.
+#[derive(Debug, Clone)]
+pub struct CacheHierarchy {
+    pub l1i: Cache,
+    pub l1d: Cache,
+    pub l2: Cache,
+    pub latency: CacheLatency,
+}
+
+impl Default for CacheHierarchy {
+    fn default() -> Self {
+        Self::new(CacheConfig::L1, CacheConfig::L2)
+    }
+}
+
+impl CacheHierarchy {
+    pub fn new(l1: CacheConfig, l2: CacheConfig) -> Self {
+        assert!(l2.line_size >= l1.line_size, "L2 lines must be at least as large as L1 lines");
+        Self {
+            l1i: Cache::new(l1),
+            l1d: Cache::new(l1),
+            l2: Cache::new(l2),
+            latency: CacheLatency::default(),
+        }
+    }
+
+    /// Fetch an instruction byte. Returns the byte and the stall cycles.
+    pub fn fetch(&mut self, bus: &mut dyn Bus, address: u32) -> Result<(u8, u32), BusError> {
+        if !bus.cacheable(address) {
+            return Ok((bus.read(address)?, self.latency.memory));
+        }
+        let (l1i, l2) = (&mut self.l1i, &mut self.l2);
+        Self::read_through(l1i, l2, self.latency, bus, address)
+    }
+
+    /// Read a data byte. Returns the byte and the stall cycles.
+    pub fn read(&mut self, bus: &mut dyn Bus, address: u32) -> Result<(u8, u32), BusError> {
+        if !bus.cacheable(address) {
+            return Ok((bus.read(address)?, self.latency.memory));
+        }
+        let (l1d, l2) = (&mut self.l1d, &mut self.l2);
+        Self::read_through(l1d, l2, self.latency, bus, address)
+    }
+
+    /// Write a data byte. Returns the stall cycles.
+    pub fn write(&mut self, bus: &mut dyn Bus, address: u32, value: u8) -> Result<u32, BusError> {
+        if !bus.cacheable(address) {
+            bus.write(address, value)?;
+            return Ok(self.latency.memory);
+        }
+        if !bus.writable(address) {
+            return Err(BusError { kind: BusErrorKind::ReadOnly, address });
+        }
+
+        // L1: write-through, no allocation on a miss.
+        self.l1d.stats.writes += 1;
+        match self.l1d.lookup(address) {
+            Some(index) => {
+                self.l1d.stats.hits += 1;
+                let offset = (address - self.l1d.line_base(address)) as usize;
+                self.l1d.lines[index].data[offset] = value;
+            }
+            None => self.l1d.stats.misses += 1,
+        }
+
+        // L2: write-back with write-allocate.
+        self.l2.stats.writes += 1;
+        let mut stall = self.latency.l2_hit;
+        let index = match self.l2.lookup(address) {
+            Some(index) => {
+                self.l2.stats.hits += 1;
+                index
+            }
+            None => {
+                self.l2.stats.misses += 1;
+                stall += self.latency.memory;
+                let (index, evicted) = Self::fill_l2(&mut self.l2, bus, address)?;
+                stall += Self::write_back(&mut self.l2, self.latency, bus, evicted)?;
+                index
+            }
+        };
+        let offset = (address - self.l2.line_base(address)) as usize;
+        self.l2.lines[index].data[offset] = value;
+        self.l2.lines[index].dirty = true;
+        Ok(stall)
+    }
+
+    /// `icinv`: discard the instruction cache.
+    pub fn invalidate_instructions(&mut self) {
+        self.l1i.invalidate();
+    }
+
+    /// `dcinv`: discard the data caches. Dirty L2 lines are dropped, not
+    /// written back; software must `dcclean` first to keep them.
+    pub fn invalidate_data(&mut self) {
+        self.l1d.invalidate();
+        self.l2.invalidate();
+    }
+
+    /// `dcclean`: write every dirty line back to memory. Returns the stall
+    /// cycles.
+    pub fn clean(&mut self, bus: &mut dyn Bus) -> Result<u32, BusError> {
+        let mut stall = 0;
+        for line in self.l2.take_dirty() {
+            stall += Self::write_back(&mut self.l2, self.latency, bus, Some(line))?;
+        }
+        Ok(stall)
+    }
+
+    /// `flush`: clean, then invalidate every level.
+    pub fn flush(&mut self, bus: &mut dyn Bus) -> Result<u32, BusError> {
+        let stall = self.clean(bus)?;
+        self.l1i.invalidate();
+        self.l1d.invalidate();
+        self.l2.invalidate();
+        Ok(stall)
+    }
+
+    /// The value the CPU would read at `address` through the data path,
+    /// without side effects.
+    pub fn peek(&self, bus: &dyn Bus, address: u32) -> Option<u8> {
+        if !bus.cacheable(address) {
+            return bus.peek(address);
+        }
+        self.l1d.peek(address).or_else(|| self.l2.peek(address)).or_else(|| bus.peek(address))
+    }
+
+    pub fn save_state(&self) -> CachesState {
+        CachesState { l1i: self.l1i.save_state(), l1d: self.l1d.save_state(), l2: self.l2.save_state() }
+    }
+
+    /// Returns false if any level's geometry differs from the saved one.
+    pub fn restore_state(&mut self, state: &CachesState) -> bool {
+        let fits = [(&self.l1i, &state.l1i), (&self.l1d, &state.l1d), (&self.l2, &state.l2)]
+            .iter()
+            .all(|(cache, saved)| cache.config == CacheConfig { line_size: saved.line_size, ways: saved.ways, sets: saved.sets });
+        fits && self.l1i.restore_state(&state.l1i) && self.l1d.restore_state(&state.l1d) && self.l2.restore_state(&state.l2)
+    }
+
+    /// Overwrite `address` in every level and in memory, as a debugger
+    /// would, so that all views agree afterwards.
+    pub fn patch(&mut self, bus: &mut dyn Bus, address: u32, value: u8) -> Result<(), BusError> {
+        bus.load(address, &[value])?;
+        for cache in [&mut self.l1i, &mut self.l1d, &mut self.l2] {
+            cache.patch(address, value);
+        }
+        Ok(())
+    }
+
+    fn read_through(
+        l1: &mut Cache,
+        l2: &mut Cache,
+        latency: CacheLatency,
+        bus: &mut dyn Bus,
+        address: u32,
+    ) -> Result<(u8, u32), BusError> {
+        l1.stats.reads += 1;
+        if let Some(index) = l1.lookup(address) {
+            l1.stats.hits += 1;
+            let offset = (address - l1.line_base(address)) as usize;
+            return Ok((l1.lines[index].data[offset], 0));
+        }
+        l1.stats.misses += 1;
+
+        l2.stats.reads += 1;
+        let mut stall = latency.l2_hit;
+        let l2_index = match l2.lookup(address) {
+            Some(index) => {
+                l2.stats.hits += 1;
+                index
+            }
+            None => {
+                l2.stats.misses += 1;
+                stall += latency.memory;
+                let (index, evicted) = Self::fill_l2(l2, bus, address)?;
+                stall += Self::write_back(l2, latency, bus, evicted)?;
+                index
+            }
+        };
+
+        // Copy the L1-sized part of the L2 line into L1. L1 is
+        // write-through, so evicted L1 lines are never dirty.
+        let l1_base = l1.line_base(address);
+        let start = (l1_base - l2.line_base(address)) as usize;
+        let data = l2.lines[l2_index].data[start..start + l1.config.line_size as usize].to_vec();
+        let value = data[(address - l1_base) as usize];
+        l1.fill(address, data);
+        Ok((value, stall))
+    }
+
+    /// Read the line containing `address` from memory into L2.
+    fn fill_l2(
+        l2: &mut Cache,
+        bus: &mut dyn Bus,
+        address: u32,
+    ) -> Result<(usize, Evicted), BusError> {
+        let base = l2.line_base(address);
+        let mut data = Vec::with_capacity(l2.config.line_size as usize);
+        for i in 0..l2.config.line_size {
+            let byte_address = base + i;
+            // Only the requested byte has to exist; the rest of the line
+            // may run past the end of a region or into device registers,
+            // which are never cached.
+            let byte = match bus.peek(byte_address) {
+                Some(_) if !bus.cacheable(byte_address) => 0,
+                Some(byte) => byte,
+                None if byte_address == address => return Err(BusError { kind: BusErrorKind::Unmapped, address }),
+                None => 0,
+            };
+            data.push(byte);
+        }
+        Ok(l2.fill(address, data))
+    }
+
+    /// Write an evicted or cleaned L2 line to memory, skipping bytes past
+    /// the end of the region it was filled from and device registers.
+    fn write_back(
+        l2: &mut Cache,
+        latency: CacheLatency,
+        bus: &mut dyn Bus,
+        line: Evicted,
+    ) -> Result<u32, BusError> {
+        match line {
+            Some((base, data)) => {
+                l2.stats.writebacks += 1;
+                for (i, byte) in data.iter().enumerate() {
+                    let address = base + i as u32;
+                    if bus.cacheable(address) && bus.writable(address) && bus.peek(address).is_some() {
+                        bus.write(address, *byte)?;
+                    }
+                }
+                Ok(latency.memory)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn l2_is_write_back() {
+        let mut memory = Memory::new();
+        let mut caches = CacheHierarchy::default();
+
+        caches.write(&mut memory, 0x100, 0x42).unwrap();
+        assert_eq!(memory.peek(0x100), Some(0x00));
+        assert_eq!(caches.read(&mut memory, 0x100).unwrap().0, 0x42);
+        assert_eq!(caches.l2.dirty_lines(), 1);
+
+        caches.clean(&mut memory).unwrap();
+        assert_eq!(memory.peek(0x100), Some(0x42));
+        assert_eq!(caches.l2.dirty_lines(), 0);
+        assert_eq!(caches.l2.stats.writebacks, 1);
+    }
+
+    #[test]
+    fn instruction_cache_goes_stale_until_invalidated() {
+        let mut memory = Memory::new();
+        let mut caches = CacheHierarchy::default();
+
+        assert_eq!(caches.fetch(&mut memory, 0x40).unwrap().0, 0x00);
+        caches.write(&mut memory, 0x40, 0xD2).unwrap();
+        assert_eq!(caches.fetch(&mut memory, 0x40).unwrap().0, 0x00);
+
+        caches.invalidate_instructions();
+        assert_eq!(caches.fetch(&mut memory, 0x40).unwrap().0, 0xD2);
+    }
+
+    #[test]
+    fn lines_straddling_a_device_leave_its_registers_alone() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use crate::bus::{Device, SystemBus};
+
+        struct Probe(Rc<RefCell<Vec<(u32, u8)>>>);
+        impl Device for Probe {
+            fn name(&self) -> &str {
+                "probe"
+            }
+            fn read(&mut self, _offset: u32) -> u8 {
+                0xAA
+            }
+            fn write(&mut self, offset: u32, value: u8) {
+                self.0.borrow_mut().push((offset, value));
+            }
+            fn peek(&self, _offset: u32) -> u8 {
+                0xAA
+            }
+        }
+
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut bus = SystemBus::new();
+        bus.map_ram(0, 0xFF30).unwrap();
+        bus.map_device(0xFF30, 0x10, Box::new(Probe(writes.clone()))).unwrap();
+        let mut caches = CacheHierarchy::default();
+
+        // The 64-byte L2 line at 0xff00 covers RAM and the device.
+        caches.write(&mut bus, 0xFF00, 0x42).unwrap();
+        assert_eq!(caches.peek(&bus, 0xFF30), Some(0xAA));
+        caches.flush(&mut bus).unwrap();
+        assert!(writes.borrow().is_empty());
+        assert_eq!(bus.peek(0xFF00), Some(0x42));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_way() {
+        let mut memory = Memory::new();
+        let mut caches = CacheHierarchy::default();
+        let stride = CacheConfig::L1.line_size * CacheConfig::L1.sets;
+
+        // Five lines mapping to the same L1 set; the first is evicted.
+        for i in 0..5 {
+            caches.read(&mut memory, i * stride).unwrap();
+        }
+        assert_eq!(caches.l1d.stats.misses, 5);
+        caches.read(&mut memory, 4 * stride).unwrap();
+        assert_eq!(caches.l1d.stats.hits, 1);
+        caches.read(&mut memory, 0).unwrap();
+        assert_eq!(caches.l1d.stats.misses, 6);
+    }
+}
@@ -0,0 +1,40 @@
+//! Instruction and branch coverage.
+//!
+//! While recording, the simulator counts how often each instruction
+//! address completes and, for conditional branches, how often the branch
+//! was taken and not taken. Instructions that fault are not counted.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Executions of each instruction, by address.
+    pub executed: BTreeMap<u16, u64>,
+    /// Outcomes of each conditional branch, by address.
+    pub branches: BTreeMap<u16, BranchCoverage>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&mut self, pc: u16) {
+        *self.executed.entry(pc).or_default() += 1;
+    }
+
+    pub(crate) fn record_branch(&mut self, pc: u16, taken: bool) {
+        let branch = self.branches.entry(pc).or_default();
+        if taken {
+            branch.taken += 1;
+        } else {
+            branch.not_taken += 1;
+        }
+    }
+}
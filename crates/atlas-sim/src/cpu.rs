@@ -0,0 +1,157 @@
+//! Architectural CPU state: the register file and the status register.
+//!
+//! The sixteen 8-bit registers are stored as-is. The three special-purpose
+//! pairs (TR, SP, PC) live inside the register file, the lower-numbered
+//! register holding the high byte, so writing `r15` really does move the
+//! program counter.
+
+use atlas_isa::RegisterIdentifier;
+
+/// Temporary Register pair (r10 = high, r11 = low)
+pub const TR_HIGH: RegisterIdentifier = 10;
+pub const TR_LOW: RegisterIdentifier = 11;
+/// Stack Pointer pair (r12 = high, r13 = low)
+pub const SP_HIGH: RegisterIdentifier = 12;
+pub const SP_LOW: RegisterIdentifier = 13;
+/// Program Counter pair (r14 = high, r15 = low)
+pub const PC_HIGH: RegisterIdentifier = 14;
+pub const PC_LOW: RegisterIdentifier = 15;
+
+/// Status register holding the Z/C/N/V condition flags, the interrupt
+/// enable and the privilege mode. The CPU resets into supervisor mode with
+/// interrupts disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatusRegister(pub u8);
+
+impl StatusRegister {
+    pub const CARRY: u8 = 1 << 0;
+    pub const ZERO: u8 = 1 << 1;
+    pub const NEGATIVE: u8 = 1 << 2;
+    pub const OVERFLOW: u8 = 1 << 3;
+    /// Global interrupt enable. Cleared on exception entry.
+    pub const INTERRUPT_ENABLE: u8 = 1 << 6;
+    /// Set while the CPU runs in user mode.
+    pub const USER: u8 = 1 << 7;
+
+    pub fn carry(&self) -> bool {
+        self.0 & Self::CARRY != 0
+    }
+
+    pub fn zero(&self) -> bool {
+        self.0 & Self::ZERO != 0
+    }
+
+    pub fn negative(&self) -> bool {
+        self.0 & Self::NEGATIVE != 0
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.0 & Self::OVERFLOW != 0
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.0 & Self::INTERRUPT_ENABLE != 0
+    }
+
+    pub fn user(&self) -> bool {
+        self.0 & Self::USER != 0
+    }
+
+    /// Set or clear the given flag bit(s).
+    pub fn set(&mut self, flag: u8, value: bool) {
+        if value {
+            self.0 |= flag;
+        } else {
+            self.0 &= !flag;
+        }
+    }
+
+    /// Update Z and N from an 8-bit result.
+    pub fn set_zn(&mut self, result: u8) {
+        self.set(Self::ZERO, result == 0);
+        self.set(Self::NEGATIVE, result & 0x80 != 0);
+    }
+}
+
+impl std::fmt::Display for StatusRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}{} {}{}",
+            flag(self.negative(), 'N'),
+            flag(self.zero(), 'Z'),
+            flag(self.carry(), 'C'),
+            flag(self.overflow(), 'V'),
+            if self.user() { 'U' } else { 'S' },
+            flag(self.interrupts_enabled(), 'I'),
+        )
+    }
+}
+
+/// Register file plus status register.
+#[derive(Debug, Clone, Default)]
+pub struct Cpu {
+    regs: [u8; 16],
+    pub sr: StatusRegister,
+}
+
+impl Cpu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a register. r0 always reads as zero.
+    pub fn reg(&self, reg: RegisterIdentifier) -> u8 {
+        match reg & 0xF {
+            0 => 0,
+            r => self.regs[r as usize],
+        }
+    }
+
+    /// Write a register. Writes to r0 are discarded.
+    pub fn set_reg(&mut self, reg: RegisterIdentifier, value: u8) {
+        match reg & 0xF {
+            0 => {}
+            r => self.regs[r as usize] = value,
+        }
+    }
+
+    /// Read two registers as a 16-bit value (`high:low`).
+    pub fn pair(&self, high: RegisterIdentifier, low: RegisterIdentifier) -> u16 {
+        ((self.reg(high) as u16) << 8) | self.reg(low) as u16
+    }
+
+    /// Write a 16-bit value into two registers (`high:low`).
+    pub fn set_pair(&mut self, high: RegisterIdentifier, low: RegisterIdentifier, value: u16) {
+        self.set_reg(high, (value >> 8) as u8);
+        self.set_reg(low, value as u8);
+    }
+
+    pub fn tr(&self) -> u16 {
+        self.pair(TR_HIGH, TR_LOW)
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.pair(SP_HIGH, SP_LOW)
+    }
+
+    pub fn set_sp(&mut self, value: u16) {
+        self.set_pair(SP_HIGH, SP_LOW, value);
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pair(PC_HIGH, PC_LOW)
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.set_pair(PC_HIGH, PC_LOW, value);
+    }
+
+    /// All sixteen registers, r0 included (always zero).
+    pub fn registers(&self) -> [u8; 16] {
+        let mut regs = self.regs;
+        regs[0] = 0;
+        regs
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::Display;
+
+use crate::bus::BusError;
+use crate::exception::{Access, Exception};
+
+#[derive(Debug, Clone)]
+pub enum SimError {
+    /// The fetched word does not decode to a valid instruction.
+    InvalidInstruction { pc: u16, word: u16, details: String },
+    /// The instruction decoded, but cannot be executed in the current state.
+    IllegalInstruction { pc: u16, details: String },
+    /// A memory access by the instruction at `pc` to the virtual `address`
+    /// was rejected by the bus.
+    BusFault { pc: u16, address: u16, access: Access, error: BusError },
+    /// The instruction at `pc` raised an exception.
+    Exception { pc: u16, exception: Exception },
+}
+
+impl Display for SimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimError::InvalidInstruction { pc, word, details } => {
+                write!(f, "Invalid instruction 0x{:04x} at 0x{:04x}: {}", word, pc, details)
+            }
+            SimError::IllegalInstruction { pc, details } => {
+                write!(f, "Illegal instruction at 0x{:04x}: {}", pc, details)
+            }
+            SimError::BusFault { pc, error, .. } => {
+                write!(f, "Bus fault at 0x{:04x}: {}", pc, error)
+            }
+            SimError::Exception { pc, exception } => {
+                write!(f, "Unhandled exception at 0x{:04x}: {}", pc, exception)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+/// Why a snapshot could not be restored into a simulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestoreError {
+    /// The snapshot holds memory the bus does not map.
+    Memory(BusError),
+    /// The saved caches have a different geometry.
+    CacheGeometry,
+    /// No device of this name is mapped at the saved base address.
+    MissingDevice { name: String, base: u32 },
+}
+
+impl Display for RestoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestoreError::Memory(error) => write!(f, "Cannot restore memory: {}", error),
+            RestoreError::CacheGeometry => write!(f, "Saved caches do not match the simulated ones"),
+            RestoreError::MissingDevice { name, base } => {
+                write!(f, "No {} device at 0x{:06x} to restore", name, base)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
@@ -0,0 +1,92 @@
+//! Architectural exceptions raised while executing an instruction.
+
+use std::fmt::Display;
+
+use crate::interrupt::IRQ_VECTOR_BASE;
+
+/// Kind of memory access that caused a fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Fetch,
+    Read,
+    Write,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Fetch => write!(f, "fetch"),
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// The word does not decode, or a privileged instruction was executed
+    /// in user mode.
+    IllegalInstruction { word: u16 },
+    /// `sysc` with the given syscall number.
+    Syscall(u8),
+    /// The page table entry for the virtual address is not valid.
+    PageFault { address: u16, access: Access },
+    /// The page is mapped, but its permissions forbid the access.
+    ProtectionFault { address: u16, access: Access },
+    /// The bus rejected the access to the virtual address.
+    BusError { address: u16, access: Access },
+    /// An enabled IRQ line of the interrupt controller is asserted.
+    Interrupt(u8),
+}
+
+impl Exception {
+    pub const VECTOR_ILLEGAL_INSTRUCTION: u8 = 1;
+    pub const VECTOR_SYSCALL: u8 = 2;
+    pub const VECTOR_PAGE_FAULT: u8 = 3;
+    pub const VECTOR_PROTECTION_FAULT: u8 = 4;
+    pub const VECTOR_BUS_ERROR: u8 = 5;
+
+    /// Index into the exception vector table. Vector 0 is reserved and
+    /// interrupts use vectors 8–15.
+    pub fn vector(&self) -> u8 {
+        match self {
+            Exception::IllegalInstruction { .. } => Self::VECTOR_ILLEGAL_INSTRUCTION,
+            Exception::Syscall(_) => Self::VECTOR_SYSCALL,
+            Exception::PageFault { .. } => Self::VECTOR_PAGE_FAULT,
+            Exception::ProtectionFault { .. } => Self::VECTOR_PROTECTION_FAULT,
+            Exception::BusError { .. } => Self::VECTOR_BUS_ERROR,
+            Exception::Interrupt(line) => IRQ_VECTOR_BASE + line,
+        }
+    }
+
+    /// Faulting virtual address for memory exceptions.
+    pub fn address(&self) -> Option<u16> {
+        match self {
+            Exception::PageFault { address, .. }
+            | Exception::ProtectionFault { address, .. }
+            | Exception::BusError { address, .. } => Some(*address),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exception::IllegalInstruction { word } => {
+                write!(f, "illegal instruction 0x{:04x}", word)
+            }
+            Exception::Syscall(n) => write!(f, "syscall 0x{:02x}", n),
+            Exception::PageFault { address, access } => {
+                write!(f, "page fault on {} of 0x{:04x}", access, address)
+            }
+            Exception::ProtectionFault { address, access } => {
+                write!(f, "protection fault on {} of 0x{:04x}", access, address)
+            }
+            Exception::BusError { address, access } => {
+                write!(f, "bus error on {} of 0x{:04x}", access, address)
+            }
+            Exception::Interrupt(line) => write!(f, "interrupt on IRQ line {}", line),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Execution history for rewinding the simulator.
+//!
+//! While recording, every step notes where it started, and every store
+//! journals the physical address it overwrote together with the old byte.
+//! Every `interval` steps a snapshot keeps the CPU-side state: registers,
+//! system and MMU registers, caches and statistics. Memory is not copied;
+//! undoing the journal back to a snapshot restores it, so snapshots stay
+//! small even with 16 MB of physical memory behind the MMU.
+//!
+//! History is bounded: once more than `capacity` steps are recorded, the
+//! oldest snapshot is dropped together with the steps and journal entries
+//! before the next one, so only roughly the last `capacity` steps can be
+//! rewound.
+//!
+//! Rewinding to an earlier step restores the nearest snapshot at or before
+//! it and re-executes forward. Re-executing would repeat the side effects
+//! of memory-mapped devices, so history is only recorded on buses without
+//! them.
+
+use crate::cache::CacheHierarchy;
+use crate::cpu::Cpu;
+use crate::mmu::Mmu;
+use crate::system::SystemControl;
+use crate::timing::RunStats;
+
+/// Steps between snapshots unless configured otherwise.
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 1000;
+/// Steps kept for rewinding unless configured otherwise.
+pub const DEFAULT_HISTORY_CAPACITY: u64 = 100_000;
+
+/// The simulator state a snapshot restores.
+#[derive(Debug, Clone)]
+pub(crate) struct MachineState {
+    pub cpu: Cpu,
+    pub system: Option<SystemControl>,
+    pub mmu: Option<Mmu>,
+    pub interrupt_enable: u8,
+    pub caches: Option<CacheHierarchy>,
+    pub halted: bool,
+    pub stats: RunStats,
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    position: u64,
+    state: MachineState,
+}
+
+/// Where one recorded step started.
+#[derive(Debug, Clone, Copy)]
+struct StepRecord {
+    pc: u16,
+    cycles: u64,
+    /// Number of journal entries ever made before the step's stores.
+    journal: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    interval: u64,
+    capacity: u64,
+    snapshots: Vec<Snapshot>,
+    /// Position of the first step still recorded.
+    start: u64,
+    steps: Vec<StepRecord>,
+    /// Number of journal entries dropped from the front.
+    journal_start: usize,
+    /// (physical address, previous value) of every store, oldest first.
+    journal: Vec<(u32, u8)>,
+}
+
+impl History {
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: DEFAULT_HISTORY_CAPACITY,
+            snapshots: Vec::new(),
+            start: 0,
+            steps: Vec::new(),
+            journal_start: 0,
+            journal: Vec::new(),
+        }
+    }
+
+    /// Keep about `steps` steps instead of [`DEFAULT_HISTORY_CAPACITY`].
+    /// At least one snapshot interval is always kept.
+    pub fn with_capacity(mut self, steps: u64) -> Self {
+        self.capacity = steps;
+        self
+    }
+
+    /// Number of steps recorded; the current step's position.
+    pub fn position(&self) -> u64 {
+        self.start + self.steps.len() as u64
+    }
+
+    /// Oldest position that can still be rewound to.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// PC at the start of the step at `position`.
+    pub fn pc_at(&self, position: u64) -> Option<u16> {
+        let index = position.checked_sub(self.start)?;
+        self.steps.get(index as usize).map(|step| step.pc)
+    }
+
+    /// Latest recorded position that started at or before `cycle`, or the
+    /// oldest one if `cycle` is older than the history.
+    pub fn position_at_cycle(&self, cycle: u64) -> u64 {
+        self.start + (self.steps.partition_point(|step| step.cycles <= cycle) as u64).saturating_sub(1)
+    }
+
+    /// Whether the next step should capture a snapshot.
+    pub(crate) fn wants_snapshot(&self) -> bool {
+        self.position().is_multiple_of(self.interval)
+    }
+
+    pub(crate) fn begin_step(&mut self, pc: u16, cycles: u64, snapshot: Option<MachineState>) {
+        if let Some(state) = snapshot {
+            self.snapshots.push(Snapshot { position: self.position(), state });
+        }
+        self.steps.push(StepRecord { pc, cycles, journal: self.journal_start + self.journal.len() });
+        while self.steps.len() as u64 > self.capacity && self.snapshots.len() > 1 {
+            self.snapshots.remove(0);
+            let dropped = (self.snapshots[0].position - self.start) as usize;
+            self.steps.drain(..dropped);
+            self.start = self.snapshots[0].position;
+            let journal = self.steps[0].journal - self.journal_start;
+            self.journal.drain(..journal);
+            self.journal_start += journal;
+        }
+    }
+
+    pub(crate) fn log_write(&mut self, physical: u32, old: u8) {
+        self.journal.push((physical, old));
+    }
+
+    /// Drop everything recorded from the latest snapshot at or before
+    /// `position` (or the oldest snapshot) on. Returns the snapshot's state
+    /// and the stores to undo, newest first.
+    pub(crate) fn rewind(&mut self, position: u64) -> Option<(MachineState, Vec<(u32, u8)>)> {
+        let position = position.max(self.start);
+        let index = self.snapshots.iter().rposition(|s| s.position <= position)?;
+        self.snapshots.truncate(index + 1);
+        let snapshot = self.snapshots.pop()?;
+        let kept = (snapshot.position - self.start) as usize;
+        let step = self.steps[kept];
+        self.steps.truncate(kept);
+        let mut undo = self.journal.split_off(step.journal - self.journal_start);
+        undo.reverse();
+        Some((snapshot.state, undo))
+    }
+}
@@ -0,0 +1,135 @@
+//! Interrupt controller.
+//!
+//! Devices assert one of eight level-triggered IRQ lines through an
+//! [`IrqLine`] handle. The controller masks them and the CPU takes the
+//! lowest-numbered pending, enabled line as exception vector
+//! [`IRQ_VECTOR_BASE`]` + line` whenever the interrupt-enable bit in SR is
+//! set. Handlers must clear the source in the device before `eret`, or the
+//! interrupt is taken again.
+//!
+//! Registers, at [`IRQ_BASE`] in the system page:
+//!
+//! | Offset | Register  | Description                                     |
+//! |--------|-----------|-------------------------------------------------|
+//! | 0      | `PENDING` | asserted lines (read-only)                      |
+//! | 1      | `ENABLE`  | per-line mask, 1 = enabled                      |
+//! | 2      | `ACTIVE`  | lowest pending enabled line, `0xff` if none     |
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Address of the first interrupt controller register.
+pub const IRQ_BASE: u16 = 0xFF20;
+/// Number of interrupt controller register bytes.
+pub const IRQ_SIZE: u16 = 4;
+
+pub const IRQ_PENDING: u16 = 0;
+pub const IRQ_ENABLE: u16 = 1;
+pub const IRQ_ACTIVE: u16 = 2;
+
+pub const IRQ_LINES: u8 = 8;
+/// Exception vector of IRQ line 0.
+pub const IRQ_VECTOR_BASE: u8 = 8;
+
+/// A device's connection to one interrupt line.
+#[derive(Debug, Clone)]
+pub struct IrqLine {
+    lines: Rc<Cell<u8>>,
+    mask: u8,
+}
+
+impl IrqLine {
+    pub fn set(&self, asserted: bool) {
+        let lines = self.lines.get();
+        self.lines.set(if asserted { lines | self.mask } else { lines & !self.mask });
+    }
+
+    pub fn raise(&self) {
+        self.set(true);
+    }
+
+    pub fn lower(&self) {
+        self.set(false);
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.lines.get() & self.mask != 0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InterruptController {
+    lines: Rc<Cell<u8>>,
+    pub enable: u8,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle for a device to drive `line` (0–7).
+    pub fn line(&self, line: u8) -> IrqLine {
+        assert!(line < IRQ_LINES, "IRQ line {} out of range", line);
+        IrqLine { lines: Rc::clone(&self.lines), mask: 1 << line }
+    }
+
+    pub fn pending(&self) -> u8 {
+        self.lines.get()
+    }
+
+    /// Lowest-numbered line that is both asserted and enabled.
+    pub fn active(&self) -> Option<u8> {
+        let ready = self.pending() & self.enable;
+        (ready != 0).then(|| ready.trailing_zeros() as u8)
+    }
+
+    /// Whether any line could ever interrupt the CPU.
+    pub fn any_enabled(&self) -> bool {
+        self.enable != 0
+    }
+
+    pub fn is_register(address: u16) -> bool {
+        (IRQ_BASE..IRQ_BASE + IRQ_SIZE).contains(&address)
+    }
+
+    pub fn read_register(&self, offset: u16) -> u8 {
+        match offset {
+            IRQ_PENDING => self.pending(),
+            IRQ_ENABLE => self.enable,
+            IRQ_ACTIVE => self.active().unwrap_or(0xFF),
+            _ => 0,
+        }
+    }
+
+    pub fn write_register(&mut self, offset: u16, value: u8) {
+        if offset == IRQ_ENABLE {
+            self.enable = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_and_prioritises_lines() {
+        let mut controller = InterruptController::new();
+        let timer = controller.line(3);
+        let uart = controller.line(1);
+
+        timer.raise();
+        uart.raise();
+        assert_eq!(controller.read_register(IRQ_PENDING), 0b1010);
+        assert_eq!(controller.active(), None);
+
+        controller.write_register(IRQ_ENABLE, 0b1000);
+        assert_eq!(controller.active(), Some(3));
+        controller.write_register(IRQ_ENABLE, 0xFF);
+        assert_eq!(controller.active(), Some(1));
+
+        uart.lower();
+        assert_eq!(controller.read_register(IRQ_ACTIVE), 3);
+    }
+}
@@ -0,0 +1,51 @@
+//! Atlas Sim - instruction-level simulator for the Atlas-8
+//!
+//! This crate executes linked Atlas-8 images. It models the architectural
+//! register file and the status flags, and executes every instruction format
+//! by decoding fetched words with `ParsedInstruction::decode`. Memory is
+//! reached through a [`Bus`]: a flat 64 KB [`Memory`] by default, or a
+//! [`SystemBus`] of RAM, ROM and memory-mapped devices such as the [`Uart`]
+//! console and the [`Timer`]. Execution time is tracked in cycles by a
+//! configurable [`TimingModel`], optionally with an L1/L2 [`CacheHierarchy`]
+//! between the CPU and the bus. An optional [`Mmu`] translates 16-bit
+//! virtual addresses to 24-bit physical ones. The CPU runs in user or
+//! supervisor mode and vectors exceptions and interrupts to software
+//! handlers.
+
+pub mod bus;
+pub mod cache;
+pub mod coverage;
+pub mod cpu;
+pub mod error;
+pub mod exception;
+pub mod history;
+pub mod interrupt;
+pub mod memory;
+pub mod mmu;
+pub mod profile;
+pub mod simulator;
+pub mod system;
+pub mod timer;
+pub mod timing;
+pub mod trace;
+pub mod uart;
+pub mod watch;
+
+pub use bus::{Bus, BusError, BusErrorKind, Device, SystemBus};
+pub use cache::{Cache, CacheConfig, CacheHierarchy, CacheLatency, CacheStats};
+pub use coverage::{BranchCoverage, Coverage};
+pub use cpu::{Cpu, StatusRegister};
+pub use error::{RestoreError, SimError};
+pub use exception::{Access, Exception};
+pub use history::History;
+pub use interrupt::{InterruptController, IrqLine};
+pub use memory::Memory;
+pub use mmu::Mmu;
+pub use profile::StepSample;
+pub use simulator::{ExitReason, Simulator, StepResult};
+pub use system::SystemControl;
+pub use timer::Timer;
+pub use timing::{FormatStats, RunStats, TimingModel};
+pub use trace::TraceEntry;
+pub use uart::Uart;
+pub use watch::{WatchHit, WatchKind, Watchpoint};
@@ -0,0 +1,70 @@
+//! Flat byte-addressable memory, 64 KB unless sized explicitly.
+
+use crate::bus::{Bus, BusError, BusErrorKind};
+
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// The default bus: 64 KB of RAM covering the whole 16-bit address space.
+#[derive(Debug, Clone)]
+pub struct Memory {
+    data: Vec<u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    /// Memory of `size` bytes starting at physical address 0, e.g. to back
+    /// the 24-bit physical address space of the MMU.
+    pub fn with_size(size: usize) -> Self {
+        Self { data: vec![0; size] }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn index(&self, address: u32) -> Result<usize, BusError> {
+        if (address as usize) < self.data.len() {
+            Ok(address as usize)
+        } else {
+            Err(BusError { kind: BusErrorKind::Unmapped, address })
+        }
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u32) -> Result<u8, BusError> {
+        Ok(self.data[self.index(address)?])
+    }
+
+    fn write(&mut self, address: u32, value: u8) -> Result<(), BusError> {
+        let index = self.index(address)?;
+        self.data[index] = value;
+        Ok(())
+    }
+
+    fn peek(&self, address: u32) -> Option<u8> {
+        self.data.get(address as usize).copied()
+    }
+
+    fn load(&mut self, address: u32, data: &[u8]) -> Result<(), BusError> {
+        for (i, byte) in data.iter().enumerate() {
+            self.write(address + i as u32, *byte)?;
+        }
+        Ok(())
+    }
+
+    fn memory_ranges(&self) -> Vec<(u32, u32)> {
+        vec![(0, self.data.len() as u32)]
+    }
+}
@@ -0,0 +1,245 @@
+//! Memory management unit.
+//!
+//! Implements the MMU extension from `isa.typ`: 16-bit virtual addresses are
+//! split into a 6-bit virtual page number and a 10-bit offset (1 KB pages)
+//! and translated, together with an 8-bit process ID, into 24-bit physical
+//! addresses.
+//!
+//! Each process has a 64-entry page table of 4-byte little-endian entries at
+//! `PTBR + (PID << 8) + (VPN << 2)`:
+//!
+//! | Bits  | Field                                |
+//! |-------|--------------------------------------|
+//! | 13:0  | physical frame number                |
+//! | 16    | valid                                |
+//! | 17    | writable                             |
+//! | 18    | executable                           |
+//! | 19    | accessible from user mode            |
+//!
+//! The table is walked on every access; there is no TLB, so page table
+//! updates take effect immediately. The top page (`0xFC00`–`0xFFFF`) is never
+//! translated: it is identity-mapped for the supervisor and holds the
+//! memory-mapped system registers, including the MMU's own at [`MMU_BASE`].
+
+use atlas_files::formats::snapshot::MmuState;
+
+use crate::exception::{Access, Exception};
+
+/// Address of the first MMU register.
+pub const MMU_BASE: u16 = 0xFF00;
+/// Number of MMU register bytes.
+pub const MMU_SIZE: u16 = 8;
+
+/// `CTRL` (read/write): bit 0 enables translation.
+pub const MMU_CTRL: u16 = 0;
+/// `PID` (read/write): current process ID.
+pub const MMU_PID: u16 = 1;
+/// `PTBR` (read/write): 24-bit page table base, little-endian over three bytes.
+pub const MMU_PTBR: u16 = 2;
+/// `FAULT_ADDR` (read-only): virtual address of the last fault, little-endian.
+pub const MMU_FAULT_ADDR: u16 = 5;
+/// `FAULT_STATUS` (read-only): see [`Mmu::fault_status`].
+pub const MMU_FAULT_STATUS: u16 = 7;
+
+pub const PTE_VALID: u32 = 1 << 16;
+pub const PTE_WRITE: u32 = 1 << 17;
+pub const PTE_EXEC: u32 = 1 << 18;
+pub const PTE_USER: u32 = 1 << 19;
+const PTE_FRAME_MASK: u32 = 0x3FFF;
+
+pub const PAGE_SIZE: u16 = 1 << 10;
+/// Virtual page number of the untranslated system page.
+pub const SYSTEM_PAGE: u16 = 63;
+
+#[derive(Debug, Clone, Default)]
+pub struct Mmu {
+    pub enabled: bool,
+    pub pid: u8,
+    /// Page table base; only the low 24 bits are used.
+    pub ptbr: u32,
+    fault_address: u16,
+    fault_status: u8,
+}
+
+impl Mmu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fault_address(&self) -> u16 {
+        self.fault_address
+    }
+
+    /// Bits 1:0 hold the access (0 = fetch, 1 = read, 2 = write), bit 2 is
+    /// set for protection faults and clear for missing pages, bit 3 is set
+    /// if the access was made from user mode.
+    pub fn fault_status(&self) -> u8 {
+        self.fault_status
+    }
+
+    pub fn is_register(address: u16) -> bool {
+        (MMU_BASE..MMU_BASE + MMU_SIZE).contains(&address)
+    }
+
+    pub fn save_state(&self) -> MmuState {
+        MmuState {
+            enabled: self.enabled,
+            pid: self.pid,
+            ptbr: self.ptbr,
+            fault_address: self.fault_address,
+            fault_status: self.fault_status,
+        }
+    }
+
+    pub fn from_state(state: &MmuState) -> Self {
+        Self {
+            enabled: state.enabled,
+            pid: state.pid,
+            ptbr: state.ptbr,
+            fault_address: state.fault_address,
+            fault_status: state.fault_status,
+        }
+    }
+
+    pub fn read_register(&self, offset: u16) -> u8 {
+        match offset {
+            MMU_CTRL => self.enabled as u8,
+            MMU_PID => self.pid,
+            2..=4 => (self.ptbr >> (8 * (offset - MMU_PTBR))) as u8,
+            5 | 6 => (self.fault_address >> (8 * (offset - MMU_FAULT_ADDR))) as u8,
+            MMU_FAULT_STATUS => self.fault_status,
+            _ => 0,
+        }
+    }
+
+    pub fn write_register(&mut self, offset: u16, value: u8) {
+        match offset {
+            MMU_CTRL => self.enabled = value & 1 != 0,
+            MMU_PID => self.pid = value,
+            2..=4 => {
+                let shift = 8 * (offset - MMU_PTBR);
+                self.ptbr = (self.ptbr & !(0xFF << shift)) | ((value as u32) << shift);
+            }
+            // Fault registers are read-only.
+            _ => {}
+        }
+    }
+
+    /// Physical address of the page table entry for `vpn` in the current
+    /// process.
+    pub fn entry_address(&self, vpn: u16) -> u32 {
+        (self.ptbr + ((self.pid as u32) << 8) + ((vpn as u32) << 2)) & 0xFF_FFFF
+    }
+
+    /// Translate a virtual address. `read_physical` reads page table bytes.
+    pub fn translate(
+        &mut self,
+        address: u16,
+        access: Access,
+        user: bool,
+        read_physical: impl Fn(u32) -> Option<u8>,
+    ) -> Result<u32, Exception> {
+        if !self.enabled {
+            return Ok(address as u32);
+        }
+
+        let vpn = address / PAGE_SIZE;
+        let offset = (address % PAGE_SIZE) as u32;
+        if vpn == SYSTEM_PAGE {
+            if user {
+                return Err(self.fault(address, access, user, true));
+            }
+            return Ok(address as u32);
+        }
+
+        let base = self.entry_address(vpn);
+        let mut entry = 0u32;
+        for i in 0..4 {
+            match read_physical(base + i) {
+                Some(byte) => entry |= (byte as u32) << (8 * i),
+                None => return Err(self.fault(address, access, user, false)),
+            }
+        }
+
+        if entry & PTE_VALID == 0 {
+            return Err(self.fault(address, access, user, false));
+        }
+        let allowed = match access {
+            Access::Fetch => entry & PTE_EXEC != 0,
+            Access::Read => true,
+            Access::Write => entry & PTE_WRITE != 0,
+        };
+        if !allowed || (user && entry & PTE_USER == 0) {
+            return Err(self.fault(address, access, user, true));
+        }
+
+        Ok(((entry & PTE_FRAME_MASK) << 10) | offset)
+    }
+
+    fn fault(&mut self, address: u16, access: Access, user: bool, protection: bool) -> Exception {
+        self.fault_address = address;
+        self.fault_status = match access {
+            Access::Fetch => 0,
+            Access::Read => 1,
+            Access::Write => 2,
+        } | (protection as u8) << 2
+            | (user as u8) << 3;
+        if protection {
+            Exception::ProtectionFault { address, access }
+        } else {
+            Exception::PageFault { address, access }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Page tables at 0x1000 with VPN 2 of PID 3 mapped to frame 0x0123.
+    fn table(flags: u32) -> impl Fn(u32) -> Option<u8> {
+        move |address| {
+            let entry = 0x1000 + (3 << 8) + (2 << 2);
+            let pte = 0x0123 | flags;
+            if (entry..entry + 4).contains(&address) {
+                Some((pte >> (8 * (address - entry))) as u8)
+            } else {
+                Some(0)
+            }
+        }
+    }
+
+    fn mmu() -> Mmu {
+        Mmu { enabled: true, pid: 3, ptbr: 0x1000, ..Mmu::default() }
+    }
+
+    #[test]
+    fn translates_to_24_bit_physical_addresses() {
+        let mut mmu = mmu();
+        let physical = mmu.translate(0x0812, Access::Read, false, table(PTE_VALID)).unwrap();
+        assert_eq!(physical, (0x0123 << 10) | 0x012);
+    }
+
+    #[test]
+    fn reports_missing_pages_and_protection() {
+        let mut mmu = mmu();
+        let err = mmu.translate(0x0C00, Access::Read, false, table(PTE_VALID)).unwrap_err();
+        assert_eq!(err, Exception::PageFault { address: 0x0C00, access: Access::Read });
+        assert_eq!(mmu.fault_status(), 1);
+
+        let err = mmu.translate(0x0800, Access::Write, true, table(PTE_VALID | PTE_USER)).unwrap_err();
+        assert_eq!(err, Exception::ProtectionFault { address: 0x0800, access: Access::Write });
+        assert_eq!(mmu.fault_status(), 2 | 4 | 8);
+        assert_eq!(mmu.read_register(MMU_FAULT_ADDR + 1), 0x08);
+
+        let err = mmu.translate(0x0800, Access::Read, true, table(PTE_VALID)).unwrap_err();
+        assert!(matches!(err, Exception::ProtectionFault { .. }));
+    }
+
+    #[test]
+    fn system_page_is_untranslated_and_supervisor_only() {
+        let mut mmu = mmu();
+        assert_eq!(mmu.translate(0xFF01, Access::Read, false, table(0)), Ok(0xFF01));
+        assert!(mmu.translate(0xFF01, Access::Read, true, table(0)).is_err());
+    }
+}
@@ -0,0 +1,20 @@
+//! Per-step samples for profiling.
+//!
+//! [`Simulator::run_profiled`] reports, for every step, where it started,
+//! where it left PC, and the instructions and cycles it took. Attributing
+//! samples to labels or functions is up to the caller, which knows the
+//! image's symbols.
+//!
+//! [`Simulator::run_profiled`]: crate::simulator::Simulator::run_profiled
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepSample {
+    pub pc: u16,
+    /// PC after the step; anything but `pc + 2` is a transfer of control
+    /// (or, while halted, `pc` itself).
+    pub next_pc: u16,
+    /// 1 for an executed instruction, 0 for an interrupt entry or an idle
+    /// step while halted.
+    pub instructions: u64,
+    pub cycles: u64,
+}
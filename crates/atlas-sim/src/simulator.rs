@@ -0,0 +1,1718 @@
+//! Fetch / decode / execute loop.
+//!
+//! Instructions are fetched as big-endian 16-bit words at PC, decoded with
+//! [`ParsedInstruction::decode`] and executed against the [`Cpu`] and
+//! [`Bus`]. PC is advanced past the instruction *before* it executes, so
+//! relative branches and reads of r14/r15 see the address of the next
+//! instruction.
+//!
+//! Every step is charged cycles according to the simulator's
+//! [`TimingModel`] and accumulated into [`RunStats`]. When a
+//! [`CacheHierarchy`] is attached, fetches and data accesses go through it
+//! and cache misses add stall cycles. With an [`Mmu`] attached, every
+//! address the CPU issues is virtual and translated before it reaches the
+//! caches or the bus.
+//!
+//! Faults, illegal instructions and `sysc` are architectural exceptions. If
+//! software installed a handler in the vector table (see
+//! [`SystemControl`]), the CPU saves PC and SR, switches to supervisor mode
+//! and jumps to it; `eret` returns. Without a handler, faults stop the
+//! simulator with a [`SimError`] and `sysc` is reported to the host as
+//! [`StepResult::Syscall`].
+//!
+//! Interrupts from the [`InterruptController`] are taken between
+//! instructions through the same exception path. `halt` stops the CPU until
+//! an enabled IRQ line is asserted; if interrupts are globally disabled the
+//! CPU then simply resumes after the `halt`.
+
+use atlas_files::formats::snapshot::{CpuState, MemoryPage, Snapshot, SystemState};
+use atlas_isa::{AluOp, BranchCond, ImmOp, MemOp, MOffset, Operand, ParsedInstruction, PeekPokeOp, StackOp, XOperand, XTypeOp};
+
+use crate::bus::{Bus, BusError, BusErrorKind};
+use crate::cache::CacheHierarchy;
+use crate::coverage::Coverage;
+use crate::cpu::{Cpu, PC_HIGH, StatusRegister};
+use crate::history::{History, MachineState};
+use crate::error::{RestoreError, SimError};
+use crate::exception::{Access, Exception};
+use crate::interrupt::{IRQ_BASE, InterruptController};
+use crate::mmu::{MMU_BASE, Mmu};
+use crate::profile::StepSample;
+use crate::memory::Memory;
+use crate::system::{SYS_SR, SYSTEM_BASE, SystemControl};
+use crate::timing::{RunStats, TimingModel};
+use crate::trace::TraceEntry;
+use crate::watch::{WatchHit, Watchpoint};
+
+/// Granularity of the memory pages in a snapshot.
+const SNAPSHOT_PAGE_SIZE: usize = 256;
+
+/// What happened during a single step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// The instruction executed normally.
+    Continue,
+    /// A `halt` instruction was executed (or the CPU was already halted).
+    Halt,
+    /// A `sysc` instruction was executed with the given syscall number.
+    Syscall(u8),
+}
+
+/// Why [`Simulator::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    Halted,
+    Syscall(u8),
+    StepLimit,
+}
+
+impl std::fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitReason::Halted => write!(f, "halted"),
+            ExitReason::Syscall(n) => write!(f, "syscall 0x{:02x}", n),
+            ExitReason::StepLimit => write!(f, "step limit reached"),
+        }
+    }
+}
+
+pub struct Simulator {
+    pub cpu: Cpu,
+    pub bus: Box<dyn Bus>,
+    pub timing: TimingModel,
+    /// Optional L1/L2 caches between the CPU and the bus.
+    pub caches: Option<CacheHierarchy>,
+    /// Optional MMU; its registers appear at [`MMU_BASE`] when present.
+    pub mmu: Option<Mmu>,
+    /// Optional exception registers; they appear at [`SYSTEM_BASE`] when
+    /// present. Without them no exception has a handler.
+    pub system: Option<SystemControl>,
+    /// Optional interrupt controller; its registers appear at [`IRQ_BASE`]
+    /// when present.
+    pub interrupts: Option<InterruptController>,
+    halted: bool,
+    stats: RunStats,
+    /// Penalty cycles charged by the instruction currently executing.
+    pending_cycles: u64,
+    /// Stores made during a traced step.
+    write_log: Option<Vec<(u16, u8)>>,
+    /// Data watchpoints checked on every load and store.
+    pub watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,
+    /// Recorded steps and stores, if rewinding is enabled.
+    history: Option<History>,
+    /// Executed instructions and branch outcomes, if recording coverage.
+    coverage: Option<Coverage>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// A simulator backed by a flat 64 KB [`Memory`].
+    pub fn new() -> Self {
+        Self::with_bus(Box::new(Memory::new()))
+    }
+
+    /// A simulator attached to an arbitrary bus, e.g. a
+    /// [`SystemBus`](crate::bus::SystemBus) describing a particular board.
+    pub fn with_bus(bus: Box<dyn Bus>) -> Self {
+        Self {
+            cpu: Cpu::new(),
+            bus,
+            timing: TimingModel::default(),
+            caches: None,
+            mmu: None,
+            system: None,
+            interrupts: None,
+            halted: false,
+            stats: RunStats::default(),
+            pending_cycles: 0,
+            write_log: None,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            history: None,
+            coverage: None,
+        }
+    }
+
+    /// Load a flat image at `base` and point PC at it.
+    pub fn load(&mut self, image: &[u8], base: u16) -> Result<(), BusError> {
+        self.bus.load(base as u32, image)?;
+        self.cpu.set_pc(base);
+        self.halted = false;
+        Ok(())
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.stats.instructions
+    }
+
+    /// Number of cycles elapsed so far.
+    pub fn cycles(&self) -> u64 {
+        self.stats.cycles
+    }
+
+    pub fn stats(&self) -> &RunStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = RunStats::default();
+    }
+
+    /// Execute instructions until the CPU halts, traps, or `max_steps`
+    /// steps have been taken. A halted CPU keeps idling (one step per cycle)
+    /// as long as some IRQ line is enabled and could wake it.
+    pub fn run(&mut self, max_steps: u64) -> Result<ExitReason, SimError> {
+        self.run_with(max_steps, Self::step)
+    }
+
+    /// Like [`run`](Self::run), handing a [`TraceEntry`] for every executed
+    /// instruction or taken interrupt to `on_entry`.
+    pub fn run_traced(
+        &mut self,
+        max_steps: u64,
+        mut on_entry: impl FnMut(&TraceEntry),
+    ) -> Result<ExitReason, SimError> {
+        self.run_with(max_steps, |sim| {
+            let (result, entry) = sim.step_traced()?;
+            if let Some(entry) = entry {
+                on_entry(&entry);
+            }
+            Ok(result)
+        })
+    }
+
+    /// Like [`run`](Self::run), handing every watchpoint hit to `on_hit` as
+    /// it happens.
+    pub fn run_watched(
+        &mut self,
+        max_steps: u64,
+        mut on_hit: impl FnMut(&Self, &WatchHit),
+    ) -> Result<ExitReason, SimError> {
+        self.run_with(max_steps, |sim| {
+            let result = sim.step();
+            for hit in sim.take_watch_hits() {
+                on_hit(sim, &hit);
+            }
+            result
+        })
+    }
+
+    /// Like [`run`](Self::run), handing a [`StepSample`] for every step to
+    /// `on_sample`.
+    pub fn run_profiled(
+        &mut self,
+        max_steps: u64,
+        mut on_sample: impl FnMut(&StepSample),
+    ) -> Result<ExitReason, SimError> {
+        self.run_with(max_steps, |sim| {
+            let (pc, instructions, cycles) = (sim.cpu.pc(), sim.stats.instructions, sim.stats.cycles);
+            let result = sim.step()?;
+            on_sample(&StepSample {
+                pc,
+                next_pc: sim.cpu.pc(),
+                instructions: sim.stats.instructions - instructions,
+                cycles: sim.stats.cycles - cycles,
+            });
+            Ok(result)
+        })
+    }
+
+    fn run_with(
+        &mut self,
+        max_steps: u64,
+        mut step: impl FnMut(&mut Self) -> Result<StepResult, SimError>,
+    ) -> Result<ExitReason, SimError> {
+        for _ in 0..max_steps {
+            match step(self)? {
+                StepResult::Continue => {}
+                StepResult::Halt if self.may_wake() => {}
+                StepResult::Halt => return Ok(ExitReason::Halted),
+                StepResult::Syscall(n) => return Ok(ExitReason::Syscall(n)),
+            }
+        }
+        Ok(ExitReason::StepLimit)
+    }
+
+    /// Whether a halted CPU could still be woken, i.e. some IRQ line is
+    /// enabled.
+    pub fn may_wake(&self) -> bool {
+        self.interrupts.as_ref().is_some_and(InterruptController::any_enabled)
+    }
+
+    /// Lowest pending enabled IRQ line, if any.
+    fn active_irq(&self) -> Option<u8> {
+        self.interrupts.as_ref().and_then(InterruptController::active)
+    }
+
+    /// Watchpoint hits recorded since the last call, oldest first.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    /// Start recording history, with a snapshot every `interval` steps, so
+    /// that later steps can be rewound. Steps taken before cannot be, nor
+    /// can steps older than the last [`crate::history::DEFAULT_HISTORY_CAPACITY`] or so.
+    /// Returns false and records nothing if the bus maps devices, whose
+    /// side effects can be neither undone nor replayed.
+    pub fn record_history(&mut self, interval: u64) -> bool {
+        if self.bus.has_devices() {
+            return false;
+        }
+        self.history = Some(History::new(interval));
+        true
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Start counting executed instructions and branch outcomes.
+    pub fn record_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Return to the start of the recorded step at `position`, or to the
+    /// oldest one still recorded. Does nothing without history or if
+    /// `position` is not in the past. Coverage and
+    /// traces are not recorded again for the re-executed steps.
+    pub fn rewind(&mut self, position: u64) -> Result<(), SimError> {
+        let Some(history) = &mut self.history else {
+            return Ok(());
+        };
+        if position >= history.position() {
+            return Ok(());
+        }
+        let Some((state, undo)) = history.rewind(position) else {
+            return Ok(());
+        };
+        self.restore_machine_state(state);
+        for (physical, old) in undo {
+            // The journal only holds addresses that were stored to.
+            let _ = self.patch_physical(physical, old);
+        }
+        let (coverage, write_log) = (self.coverage.take(), self.write_log.take());
+        let mut replayed = Ok(());
+        while replayed.is_ok() && self.history.as_ref().is_some_and(|h| h.position() < position) {
+            replayed = self.step().map(|_| ());
+        }
+        (self.coverage, self.write_log) = (coverage, write_log);
+        self.watch_hits.clear();
+        replayed
+    }
+
+    /// Rewind to the latest recorded step that started at or before
+    /// `cycle`.
+    pub fn rewind_to_cycle(&mut self, cycle: u64) -> Result<(), SimError> {
+        match &self.history {
+            Some(history) if cycle < self.stats.cycles => self.rewind(history.position_at_cycle(cycle)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Capture the complete machine state, including memory and devices.
+    pub fn save_snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        snapshot.cpu = CpuState { registers: self.cpu.registers(), sr: self.cpu.sr.0, halted: self.halted };
+        snapshot.instructions = self.stats.instructions;
+        snapshot.cycles = self.stats.cycles;
+        let system = self.system.unwrap_or_default();
+        snapshot.system = SystemState {
+            vbr: system.vbr,
+            epc: system.epc,
+            esr: system.esr,
+            cause: system.cause,
+            earg: system.earg,
+            eaddr: system.eaddr,
+        };
+        snapshot.interrupt_enable = self.interrupts.as_ref().map_or(0, |i| i.enable);
+        snapshot.mmu = self.mmu.as_ref().map(Mmu::save_state);
+        snapshot.caches = self.caches.as_ref().map(CacheHierarchy::save_state);
+        let limit = Self::physical_limit(self.mmu.is_some());
+        for (base, size) in self.bus.memory_ranges() {
+            let end = base.saturating_add(size).min(limit);
+            for address in (base..end).step_by(SNAPSHOT_PAGE_SIZE) {
+                let data: Vec<u8> = (address..(address + SNAPSHOT_PAGE_SIZE as u32).min(end))
+                    .map(|a| self.bus.peek(a).unwrap_or(0))
+                    .collect();
+                if data.iter().any(|&b| b != 0) {
+                    snapshot.memory.push(MemoryPage { address, data });
+                }
+            }
+        }
+        snapshot.memory.sort_by_key(|page| page.address);
+        snapshot.devices = self.bus.save_devices();
+        snapshot
+    }
+
+    /// Replace the machine state with `snapshot`. Memory the snapshot does
+    /// not cover is cleared, other statistics are reset, and history
+    /// recording stops. A snapshot that does not fit the machine is
+    /// rejected before anything changes.
+    pub fn restore_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), RestoreError> {
+        let limit = Self::physical_limit(snapshot.mmu.is_some());
+        for page in &snapshot.memory {
+            let beyond = page.address as u64 + page.data.len() as u64 > limit as u64;
+            if beyond || self.bus.peek(page.address).is_none() {
+                let error = BusError { kind: BusErrorKind::Unmapped, address: page.address };
+                return Err(RestoreError::Memory(error));
+            }
+        }
+        let mapped = self.bus.save_devices();
+        for device in &snapshot.devices {
+            if !mapped.iter().any(|d| d.name == device.name && d.base == device.base) {
+                return Err(RestoreError::MissingDevice { name: device.name.clone(), base: device.base });
+            }
+        }
+        let caches = match (&self.caches, &snapshot.caches) {
+            (caches, Some(saved)) => {
+                let mut caches = caches.clone().unwrap_or_default();
+                if !caches.restore_state(saved) {
+                    return Err(RestoreError::CacheGeometry);
+                }
+                Some(caches)
+            }
+            (Some(caches), None) => Some(CacheHierarchy::new(caches.l1i.config(), caches.l2.config())),
+            (None, None) => None,
+        };
+
+        for (base, size) in self.bus.memory_ranges() {
+            let end = base.saturating_add(size).min(limit);
+            if base >= end {
+                continue;
+            }
+            let mut data = vec![0; (end - base) as usize];
+            for page in &snapshot.memory {
+                let start = page.address.max(base);
+                let stop = (page.address + page.data.len() as u32).min(end);
+                if start < stop {
+                    data[(start - base) as usize..(stop - base) as usize]
+                        .copy_from_slice(&page.data[(start - page.address) as usize..(stop - page.address) as usize]);
+                }
+            }
+            self.bus.load(base, &data).map_err(RestoreError::Memory)?;
+        }
+        for device in &snapshot.devices {
+            self.bus.restore_device(device);
+        }
+        self.caches = caches;
+
+        for (r, value) in snapshot.cpu.registers.iter().enumerate() {
+            self.cpu.set_reg(r as u8, *value);
+        }
+        self.cpu.sr = StatusRegister(snapshot.cpu.sr);
+        self.halted = snapshot.cpu.halted;
+        if let Some(system) = &mut self.system {
+            let saved = &snapshot.system;
+            *system = SystemControl {
+                vbr: saved.vbr,
+                epc: saved.epc,
+                esr: saved.esr,
+                cause: saved.cause,
+                earg: saved.earg,
+                eaddr: saved.eaddr,
+            };
+        }
+        if let Some(interrupts) = &mut self.interrupts {
+            interrupts.enable = snapshot.interrupt_enable;
+        }
+        self.mmu = snapshot.mmu.as_ref().map(Mmu::from_state);
+        self.stats = RunStats { instructions: snapshot.instructions, cycles: snapshot.cycles, ..Default::default() };
+        self.pending_cycles = 0;
+        self.watch_hits.clear();
+        self.history = None;
+        Ok(())
+    }
+
+    /// Size of the physical address space: 24 bits with an MMU, 16 without.
+    fn physical_limit(mmu: bool) -> u32 {
+        if mmu { 1 << 24 } else { 0x10000 }
+    }
+
+    fn machine_state(&self) -> MachineState {
+        MachineState {
+            cpu: self.cpu.clone(),
+            system: self.system,
+            mmu: self.mmu.clone(),
+            interrupt_enable: self.interrupts.as_ref().map_or(0, |i| i.enable),
+            caches: self.caches.clone(),
+            halted: self.halted,
+            stats: self.stats.clone(),
+        }
+    }
+
+    fn restore_machine_state(&mut self, state: MachineState) {
+        self.cpu = state.cpu;
+        self.system = state.system;
+        self.mmu = state.mmu;
+        if let Some(interrupts) = &mut self.interrupts {
+            interrupts.enable = state.interrupt_enable;
+        }
+        self.caches = state.caches;
+        self.halted = state.halted;
+        self.stats = state.stats;
+    }
+
+    /// [`step`](Self::step), also describing what the step did. Idle steps
+    /// of a halted CPU produce no entry.
+    pub fn step_traced(&mut self) -> Result<(StepResult, Option<TraceEntry>), SimError> {
+        let before = self.cpu.registers();
+        let pc = self.cpu.pc();
+        let word = match (self.peek(pc), self.peek(pc.wrapping_add(1))) {
+            (Some(high), Some(low)) => u16::from_be_bytes([high, low]),
+            _ => 0,
+        };
+        let (step, exceptions) = (self.stats.instructions, self.stats.exceptions);
+
+        self.write_log = Some(Vec::new());
+        let result = self.step();
+        let memory = self.write_log.take().unwrap_or_default();
+        let result = result?;
+
+        let exception = self.system.filter(|_| self.stats.exceptions != exceptions).map(|system| system.cause);
+        if self.stats.instructions == step && exception.is_none() {
+            return Ok((result, None));
+        }
+        let after = self.cpu.registers();
+        let registers = (1..PC_HIGH)
+            .filter(|&r| before[r as usize] != after[r as usize])
+            .map(|r| (r, after[r as usize]))
+            .collect();
+        let entry = TraceEntry { step, pc, word, registers, sr: self.cpu.sr, memory, exception };
+        Ok((result, Some(entry)))
+    }
+
+    /// Fetch, decode and execute a single instruction, or take a pending
+    /// interrupt. While halted, a step idles for one cycle. Devices on the
+    /// bus are ticked with the cycles the step took.
+    pub fn step(&mut self) -> Result<StepResult, SimError> {
+        if let Some(history) = &self.history {
+            let snapshot = history.wants_snapshot().then(|| self.machine_state());
+            let (pc, cycles) = (self.cpu.pc(), self.stats.cycles);
+            if let Some(history) = &mut self.history {
+                history.begin_step(pc, cycles, snapshot);
+            }
+        }
+        let start = self.stats.cycles;
+        let result = self.advance();
+        self.bus.tick(self.stats.cycles - start);
+        result
+    }
+
+    fn advance(&mut self) -> Result<StepResult, SimError> {
+        if self.halted {
+            if self.active_irq().is_none() {
+                self.stats.cycles += 1;
+                return Ok(StepResult::Halt);
+            }
+            self.halted = false;
+        }
+
+        let pc = self.cpu.pc();
+        self.pending_cycles = 0;
+        if self.cpu.sr.interrupts_enabled()
+            && let Some(line) = self.active_irq()
+        {
+            let exception = Exception::Interrupt(line);
+            if !self.enter_exception(exception, pc) {
+                return Err(SimError::Exception { pc, exception });
+            }
+            self.stats.cycles += self.pending_cycles;
+            return Ok(StepResult::Continue);
+        }
+
+        match self.execute_at(pc) {
+            Ok(result) => Ok(result),
+            Err(error) => self.trap(pc, error),
+        }
+    }
+
+    fn execute_at(&mut self, pc: u16) -> Result<StepResult, SimError> {
+        let word = self.fetch(pc)?;
+        let instr = ParsedInstruction::decode(word)
+            .map_err(|details| SimError::InvalidInstruction { pc, word, details })?;
+
+        // Every X-type instruction except `sysc` is privileged.
+        if let ParsedInstruction::X { op, .. } = instr
+            && op != XTypeOp::SYSC
+            && self.cpu.sr.user()
+        {
+            return Err(SimError::Exception { pc, exception: Exception::IllegalInstruction { word } });
+        }
+
+        self.cpu.set_pc(pc.wrapping_add(2));
+        let result = self.execute(&instr, pc)?;
+
+        let format = instr.format();
+        let cycles = self.timing.cost(format, instr.opcode()) as u64 + self.pending_cycles;
+        self.stats.instructions += 1;
+        self.stats.cycles += cycles;
+        let per_format = self.stats.by_format.entry(format).or_default();
+        per_format.instructions += 1;
+        per_format.cycles += cycles;
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc);
+        }
+        if result == StepResult::Halt {
+            self.halted = true;
+        }
+        Ok(result)
+    }
+
+    fn execute(&mut self, instr: &ParsedInstruction, pc: u16) -> Result<StepResult, SimError> {
+        match instr {
+            ParsedInstruction::A { op, dest, source, .. } => {
+                self.execute_alu(*op, *dest, *source);
+            }
+            ParsedInstruction::I { op, dest, immediate, .. } => {
+                let imm = self.operand_value(immediate, pc)? as u8;
+                let rd = self.cpu.reg(*dest);
+                let result = match op {
+                    ImmOp::LDI => imm,
+                    ImmOp::ADDI => self.add(rd, imm, false),
+                    ImmOp::SUBI => self.sub(rd, imm, false),
+                    ImmOp::ANDI => self.logic(rd & imm),
+                    ImmOp::ORI => self.logic(rd | imm),
+                };
+                self.cpu.set_reg(*dest, result);
+            }
+            ParsedInstruction::M { op, dest, base, offset, .. } => {
+                let address = self.effective_address(*base, *offset);
+                match op {
+                    MemOp::LD => {
+                        let value = self.read(pc, address)?;
+                        self.cpu.set_reg(*dest, value);
+                    }
+                    MemOp::ST => self.write(pc, address, self.cpu.reg(*dest))?,
+                }
+            }
+            ParsedInstruction::BI { absolute, cond, operand, .. } => {
+                let offset = self.operand_value(operand, pc)? as u8;
+                let taken = self.condition_met(*cond);
+                self.record_branch(*cond, pc, taken);
+                if taken {
+                    let target = if *absolute {
+                        offset as u16
+                    } else {
+                        self.cpu.pc().wrapping_add(offset as i8 as u16)
+                    };
+                    self.take_branch(target);
+                } else {
+                    self.stats.branches += 1;
+                }
+            }
+            ParsedInstruction::BR { absolute, cond, source, .. } => {
+                let value = self.cpu.pair(source.high, source.low);
+                let taken = self.condition_met(*cond);
+                self.record_branch(*cond, pc, taken);
+                if taken {
+                    let target = if *absolute { value } else { self.cpu.pc().wrapping_add(value) };
+                    self.take_branch(target);
+                } else {
+                    self.stats.branches += 1;
+                }
+            }
+            ParsedInstruction::S { op, operand, .. } => self.execute_stack(*op, *operand, pc)?,
+            ParsedInstruction::P { op, register, offset, .. } => {
+                let offset = self.operand_value(offset, pc)? as u8;
+                let address = self.cpu.sp().wrapping_add(offset as u16);
+                match op {
+                    PeekPokeOp::PEEK => {
+                        let value = self.read(pc, address)?;
+                        self.cpu.set_reg(*register, value);
+                    }
+                    PeekPokeOp::POKE => self.write(pc, address, self.cpu.reg(*register))?,
+                }
+            }
+            ParsedInstruction::X { op, operand, .. } => {
+                return self.execute_extended(*op, *operand, pc);
+            }
+        }
+        Ok(StepResult::Continue)
+    }
+
+    fn execute_alu(&mut self, op: AluOp, dest: u8, source: u8) {
+        let rd = self.cpu.reg(dest);
+        let rs = self.cpu.reg(source);
+        let carry = self.cpu.sr.carry();
+
+        let result = match op {
+            AluOp::ADD => self.add(rd, rs, false),
+            AluOp::ADDC => self.add(rd, rs, carry),
+            AluOp::SUB => self.sub(rd, rs, false),
+            // Carry is an inverted borrow, so a pending borrow is !C.
+            AluOp::SUBC => self.sub(rd, rs, !carry),
+            AluOp::AND => self.logic(rd & rs),
+            AluOp::OR => self.logic(rd | rs),
+            AluOp::XOR => self.logic(rd ^ rs),
+            AluOp::NOT => self.logic(!rs),
+            AluOp::SHL => self.shift(rs << 1, rs & 0x80 != 0),
+            AluOp::SHR => self.shift(rs >> 1, rs & 0x01 != 0),
+            AluOp::ROL => self.shift(rs.rotate_left(1), rs & 0x80 != 0),
+            AluOp::ROR => self.shift(rs.rotate_right(1), rs & 0x01 != 0),
+            AluOp::CMP => {
+                self.sub(rd, rs, false);
+                return;
+            }
+            AluOp::TST => {
+                self.logic(rd & rs);
+                return;
+            }
+            AluOp::MOV => rs,
+            AluOp::NEG => self.sub(0, rd, false),
+        };
+        self.cpu.set_reg(dest, result);
+    }
+
+    fn execute_stack(&mut self, op: StackOp, operand: u8, pc: u16) -> Result<(), SimError> {
+        let sp = self.cpu.sp();
+        match op {
+            StackOp::PUSH => {
+                self.charge_stack_op();
+                let sp = sp.wrapping_sub(2);
+                self.write(pc, sp, self.cpu.reg(operand))?;
+                self.cpu.set_sp(sp);
+            }
+            StackOp::POP => {
+                self.charge_stack_op();
+                let value = self.read(pc, sp)?;
+                self.cpu.set_reg(operand, value);
+                self.cpu.set_sp(sp.wrapping_add(2));
+            }
+            StackOp::SUBSP_IMM => self.cpu.set_sp(sp.wrapping_sub(operand as u16)),
+            StackOp::SUBSP_REG => self.cpu.set_sp(sp.wrapping_sub(self.cpu.reg(operand) as u16)),
+            StackOp::ADDSP_IMM => self.cpu.set_sp(sp.wrapping_add(operand as u16)),
+            StackOp::ADDSP_REG => self.cpu.set_sp(sp.wrapping_add(self.cpu.reg(operand) as u16)),
+        }
+        Ok(())
+    }
+
+    fn execute_extended(&mut self, op: XTypeOp, operand: XOperand, pc: u16) -> Result<StepResult, SimError> {
+        match op {
+            XTypeOp::SYSC => {
+                let number = match operand {
+                    XOperand::Immediate(n) => n,
+                    _ => 0,
+                };
+                // Return to the instruction after `sysc`.
+                let epc = self.cpu.pc();
+                if self.enter_exception(Exception::Syscall(number), epc) {
+                    Ok(StepResult::Continue)
+                } else {
+                    Ok(StepResult::Syscall(number))
+                }
+            }
+            XTypeOp::ERET => {
+                let Some(system) = self.system else {
+                    return Err(SimError::IllegalInstruction {
+                        pc,
+                        details: "eret without system control registers".to_string(),
+                    });
+                };
+                self.cpu.sr = StatusRegister(system.esr);
+                self.cpu.set_pc(system.epc);
+                Ok(StepResult::Continue)
+            }
+            XTypeOp::HALT => Ok(StepResult::Halt),
+            // Without caches, cache maintenance has no effect.
+            XTypeOp::ICINV | XTypeOp::DCINV | XTypeOp::DCCLEAN | XTypeOp::FLUSH => {
+                if let Some(caches) = self.caches.as_mut() {
+                    let bus = self.bus.as_mut();
+                    let stall = match op {
+                        XTypeOp::ICINV => {
+                            caches.invalidate_instructions();
+                            Ok(0)
+                        }
+                        XTypeOp::DCINV => {
+                            caches.invalidate_data();
+                            Ok(0)
+                        }
+                        XTypeOp::DCCLEAN => caches.clean(bus),
+                        _ => caches.flush(bus),
+                    }
+                    .map_err(|error| SimError::BusFault {
+                        pc,
+                        address: error.address as u16,
+                        access: Access::Write,
+                        error,
+                    })?;
+                    self.pending_cycles += stall as u64;
+                }
+                Ok(StepResult::Continue)
+            }
+        }
+    }
+
+    // ── Exceptions ─────────────────────────────────────────────────────
+
+    /// Turn an error raised by the instruction at `pc` into an exception if
+    /// it is architectural and software handles it. The faulting
+    /// instruction is abandoned and `EPC` points at it, so handlers can
+    /// retry it (after mapping a page) or skip it.
+    fn trap(&mut self, pc: u16, error: SimError) -> Result<StepResult, SimError> {
+        let exception = match &error {
+            SimError::Exception { exception, .. } => *exception,
+            SimError::InvalidInstruction { word, .. } => Exception::IllegalInstruction { word: *word },
+            SimError::BusFault { address, access, .. } => Exception::BusError { address: *address, access: *access },
+            SimError::IllegalInstruction { .. } => return Err(error),
+        };
+        self.cpu.set_pc(pc);
+        if self.enter_exception(exception, pc) {
+            self.stats.cycles += self.pending_cycles;
+            Ok(StepResult::Continue)
+        } else {
+            Err(error)
+        }
+    }
+
+    /// Enter the handler for `exception`, saving `epc`. Returns false if no
+    /// handler is installed, leaving the CPU untouched.
+    fn enter_exception(&mut self, exception: Exception, epc: u16) -> bool {
+        let vector = exception.vector();
+        let Some(handler) = self.vector_handler(vector) else {
+            return false;
+        };
+        let Some(system) = self.system.as_mut() else {
+            return false;
+        };
+
+        system.epc = epc;
+        system.esr = self.cpu.sr.0;
+        system.cause = vector;
+        if let Exception::Syscall(number) = exception {
+            system.earg = number;
+        }
+        if let Some(address) = exception.address() {
+            system.eaddr = address;
+        }
+        self.cpu.sr.set(StatusRegister::USER | StatusRegister::INTERRUPT_ENABLE, false);
+        self.cpu.set_pc(handler);
+        self.stats.exceptions += 1;
+        self.pending_cycles += self.timing.exception_penalty as u64;
+        true
+    }
+
+    /// Handler address for `vector`, read from the vector table as the
+    /// supervisor would. `None` if there is no table or no handler.
+    fn vector_handler(&mut self, vector: u8) -> Option<u16> {
+        let vbr = self.system?.vbr;
+        if vbr == 0 {
+            return None;
+        }
+        let entry = vbr.wrapping_add(2 * vector as u16);
+        let low = self.translate_as(entry, Access::Read, false).ok()?;
+        let high = self.translate_as(entry.wrapping_add(1), Access::Read, false).ok()?;
+        let handler = u16::from_le_bytes([self.peek_physical(low)?, self.peek_physical(high)?]);
+        (handler != 0).then_some(handler)
+    }
+
+    // ── Helpers ────────────────────────────────────────────────────────
+
+    /// Fetch the big-endian instruction word at `pc`. Fetch time is part of
+    /// each instruction's base cost, so it is not counted as a data access.
+    fn fetch(&mut self, pc: u16) -> Result<u16, SimError> {
+        let high = self.fetch_byte(pc, pc)?;
+        let low = self.fetch_byte(pc, pc.wrapping_add(1))?;
+        Ok(((high as u16) << 8) | low as u16)
+    }
+
+    fn fetch_byte(&mut self, pc: u16, address: u16) -> Result<u8, SimError> {
+        let physical = self.translate(pc, address, Access::Fetch)?;
+        let fault = |error| SimError::BusFault { pc, address, access: Access::Fetch, error };
+        match self.caches.as_mut() {
+            Some(caches) => {
+                let (value, stall) = caches.fetch(self.bus.as_mut(), physical).map_err(fault)?;
+                self.pending_cycles += stall as u64;
+                Ok(value)
+            }
+            None => self.bus.read(physical).map_err(fault),
+        }
+    }
+
+    /// Read a data byte on behalf of the instruction at `pc`.
+    fn read(&mut self, pc: u16, address: u16) -> Result<u8, SimError> {
+        let value = self.read_data(pc, address)?;
+        if self.watchpoints.iter().any(|w| w.matches(address, Access::Read)) {
+            self.watch_hits.push(WatchHit { pc, address, access: Access::Read, old: value, new: value });
+        }
+        Ok(value)
+    }
+
+    fn read_data(&mut self, pc: u16, address: u16) -> Result<u8, SimError> {
+        self.stats.memory_reads += 1;
+        self.pending_cycles += self.timing.memory_access_penalty as u64;
+        let physical = self.translate(pc, address, Access::Read)?;
+        if let Some(mmu) = &self.mmu
+            && Mmu::is_register(address)
+        {
+            return Ok(mmu.read_register(address - MMU_BASE));
+        }
+        if let Some(system) = &self.system
+            && SystemControl::is_register(address)
+        {
+            let offset = address - SYSTEM_BASE;
+            if offset == SYS_SR {
+                return Ok(self.cpu.sr.0);
+            }
+            return Ok(system.read_register(offset));
+        }
+        if let Some(interrupts) = &self.interrupts
+            && InterruptController::is_register(address)
+        {
+            return Ok(interrupts.read_register(address - IRQ_BASE));
+        }
+
+        let fault = |error| SimError::BusFault { pc, address, access: Access::Read, error };
+        match self.caches.as_mut() {
+            Some(caches) => {
+                let (value, stall) = caches.read(self.bus.as_mut(), physical).map_err(fault)?;
+                self.pending_cycles += stall as u64;
+                Ok(value)
+            }
+            None => self.bus.read(physical).map_err(fault),
+        }
+    }
+
+    /// Write a data byte on behalf of the instruction at `pc`.
+    fn write(&mut self, pc: u16, address: u16, value: u8) -> Result<(), SimError> {
+        if let Some(log) = &mut self.write_log {
+            log.push((address, value));
+        }
+        let watched = self.watchpoints.iter().any(|w| w.matches(address, Access::Write));
+        let old = if watched { self.peek(address).unwrap_or(0) } else { 0 };
+        self.write_data(pc, address, value)?;
+        if watched {
+            self.watch_hits.push(WatchHit { pc, address, access: Access::Write, old, new: value });
+        }
+        Ok(())
+    }
+
+    fn write_data(&mut self, pc: u16, address: u16, value: u8) -> Result<(), SimError> {
+        self.stats.memory_writes += 1;
+        self.pending_cycles += self.timing.memory_access_penalty as u64;
+        let physical = self.translate(pc, address, Access::Write)?;
+        if let Some(mmu) = &mut self.mmu
+            && Mmu::is_register(address)
+        {
+            mmu.write_register(address - MMU_BASE, value);
+            return Ok(());
+        }
+        if let Some(system) = &mut self.system
+            && SystemControl::is_register(address)
+        {
+            let offset = address - SYSTEM_BASE;
+            if offset == SYS_SR {
+                self.cpu.sr = StatusRegister(value);
+            } else {
+                system.write_register(offset, value);
+            }
+            return Ok(());
+        }
+        if let Some(interrupts) = &mut self.interrupts
+            && InterruptController::is_register(address)
+        {
+            interrupts.write_register(address - IRQ_BASE, value);
+            return Ok(());
+        }
+
+        if self.history.is_some() && self.bus.cacheable(physical) {
+            let previous = self.peek_physical(physical).unwrap_or(0);
+            if let Some(history) = &mut self.history {
+                history.log_write(physical, previous);
+            }
+        }
+
+        let fault = |error| SimError::BusFault { pc, address, access: Access::Write, error };
+        match self.caches.as_mut() {
+            Some(caches) => {
+                let stall = caches.write(self.bus.as_mut(), physical, value).map_err(fault)?;
+                self.pending_cycles += stall as u64;
+                Ok(())
+            }
+            None => self.bus.write(physical, value).map_err(fault),
+        }
+    }
+
+    /// Map a virtual address to a physical one in the current mode.
+    fn translate(&mut self, pc: u16, address: u16, access: Access) -> Result<u32, SimError> {
+        let user = self.cpu.sr.user();
+        self.translate_as(address, access, user)
+            .map_err(|exception| SimError::Exception { pc, exception })
+    }
+
+    /// Without an enabled MMU, virtual and physical addresses are the same
+    /// and user mode may only access the lower half of memory. Page tables
+    /// are read through the caches, so the walk sees what the CPU last
+    /// stored.
+    fn translate_as(&mut self, address: u16, access: Access, user: bool) -> Result<u32, Exception> {
+        match self.mmu.as_mut() {
+            Some(mmu) if mmu.enabled => {
+                let (caches, bus) = (&self.caches, &self.bus);
+                let read_physical = |physical| match caches {
+                    Some(caches) => caches.peek(bus.as_ref(), physical),
+                    None => bus.peek(physical),
+                };
+                mmu.translate(address, access, user, read_physical)
+            }
+            _ if user && address >= 0x8000 => Err(Exception::ProtectionFault { address, access }),
+            _ => Ok(address as u32),
+        }
+    }
+
+    /// Side-effect-free read of a virtual address as seen from supervisor
+    /// mode, for debuggers and dumps. Returns `None` if nothing is mapped.
+    pub fn peek(&self, address: u16) -> Option<u8> {
+        self.peek_physical(self.debug_translate(address)?)
+    }
+
+    /// Store a byte at a virtual address on behalf of a debugger, bypassing
+    /// ROM protection and keeping cached copies in sync. Returns `None` if
+    /// nothing is mapped.
+    pub fn poke(&mut self, address: u16, value: u8) -> Option<()> {
+        let physical = self.debug_translate(address)?;
+        self.bus.peek(physical)?;
+        self.patch_physical(physical, value).ok()
+    }
+
+    /// Store to physical memory and every cached copy of it.
+    fn patch_physical(&mut self, physical: u32, value: u8) -> Result<(), BusError> {
+        match self.caches.as_mut() {
+            Some(caches) => caches.patch(self.bus.as_mut(), physical, value),
+            None => self.bus.load(physical, &[value]),
+        }
+    }
+
+    /// Supervisor translation for debugger accesses, without recording
+    /// faults in the MMU.
+    fn debug_translate(&self, address: u16) -> Option<u32> {
+        match &self.mmu {
+            Some(mmu) if mmu.enabled => {
+                let mut mmu = mmu.clone();
+                mmu.translate(address, Access::Read, false, |p| self.peek_physical(p)).ok()
+            }
+            _ => Some(address as u32),
+        }
+    }
+
+    /// Side-effect-free read of a physical address through the caches.
+    fn peek_physical(&self, address: u32) -> Option<u8> {
+        match &self.caches {
+            Some(caches) => caches.peek(self.bus.as_ref(), address),
+            None => self.bus.peek(address),
+        }
+    }
+
+    fn take_branch(&mut self, target: u16) {
+        self.stats.branches += 1;
+        self.stats.branches_taken += 1;
+        self.pending_cycles += self.timing.taken_branch_penalty as u64;
+        self.cpu.set_pc(target);
+    }
+
+    fn charge_stack_op(&mut self) {
+        self.stats.stack_ops += 1;
+        self.pending_cycles += self.timing.stack_penalty as u64;
+    }
+
+    fn operand_value(&self, operand: &Operand, pc: u16) -> Result<u16, SimError> {
+        match operand {
+            Operand::Immediate(value) => Ok(*value),
+            Operand::Label(name) => Err(SimError::IllegalInstruction {
+                pc,
+                details: format!("unresolved label '{}'", name),
+            }),
+        }
+    }
+
+    /// Compute an M-type address from the base register and the 4-bit
+    /// offset field. Offsets -5..=7 are plain signed displacements; -6, -7
+    /// and -8 select TR, SP and PC as a 16-bit base to which the base
+    /// register is added.
+    fn effective_address(&self, base: u8, offset: MOffset) -> u16 {
+        let field = match offset {
+            MOffset::Offset8(v) => v & 0xF,
+            MOffset::SR(r) => r & 0xF,
+        };
+        let base_value = self.cpu.reg(base) as u16;
+        // sign-extend the 4-bit field
+        match ((field << 4) as i8) >> 4 {
+            -6 => self.cpu.tr().wrapping_add(base_value),
+            -7 => self.cpu.sp().wrapping_add(base_value),
+            -8 => self.cpu.pc().wrapping_add(base_value),
+            displacement => base_value.wrapping_add(displacement as u16),
+        }
+    }
+
+    /// Count the outcome of a conditional branch when recording coverage.
+    fn record_branch(&mut self, cond: BranchCond, pc: u16, taken: bool) {
+        if cond != BranchCond::Unconditional
+            && let Some(coverage) = &mut self.coverage
+        {
+            coverage.record_branch(pc, taken);
+        }
+    }
+
+    fn condition_met(&self, cond: BranchCond) -> bool {
+        let sr = &self.cpu.sr;
+        match cond {
+            BranchCond::Unconditional => true,
+            BranchCond::EQ => sr.zero(),
+            BranchCond::NE => !sr.zero(),
+            BranchCond::CS => sr.carry(),
+            BranchCond::CC => !sr.carry(),
+            BranchCond::MI => sr.negative(),
+            BranchCond::PL => !sr.negative(),
+            BranchCond::OV => sr.overflow(),
+        }
+    }
+
+    fn add(&mut self, a: u8, b: u8, carry_in: bool) -> u8 {
+        let sum = a as u16 + b as u16 + carry_in as u16;
+        let result = sum as u8;
+        let sr = &mut self.cpu.sr;
+        sr.set_zn(result);
+        sr.set(StatusRegister::CARRY, sum > 0xFF);
+        sr.set(StatusRegister::OVERFLOW, (a ^ result) & (b ^ result) & 0x80 != 0);
+        result
+    }
+
+    /// Subtract with ARM-style flags: C is set when no borrow occurred, so
+    /// `cmp a, b` followed by `bcc` branches when `a < b` (unsigned).
+    fn sub(&mut self, a: u8, b: u8, borrow_in: bool) -> u8 {
+        let diff = a as i16 - b as i16 - borrow_in as i16;
+        let result = diff as u8;
+        let sr = &mut self.cpu.sr;
+        sr.set_zn(result);
+        sr.set(StatusRegister::CARRY, diff >= 0);
+        sr.set(StatusRegister::OVERFLOW, (a ^ b) & (a ^ result) & 0x80 != 0);
+        result
+    }
+
+    /// Logical operations update Z/N, clear V and leave C untouched.
+    fn logic(&mut self, result: u8) -> u8 {
+        self.cpu.sr.set_zn(result);
+        self.cpu.sr.set(StatusRegister::OVERFLOW, false);
+        result
+    }
+
+    /// Shifts and rotates update Z/N and move the outgoing bit into C.
+    fn shift(&mut self, result: u8, carry_out: bool) -> u8 {
+        self.cpu.sr.set_zn(result);
+        self.cpu.sr.set(StatusRegister::CARRY, carry_out);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coverage::BranchCoverage;
+    use atlas_isa::RegisterPairIdentifier;
+
+    fn program(instructions: &[ParsedInstruction]) -> Simulator {
+        let mut image = Vec::new();
+        for instr in instructions {
+            let word = instr.encode().unwrap();
+            image.push((word >> 8) as u8);
+            image.push(word as u8);
+        }
+        let mut sim = Simulator::new();
+        sim.load(&image, 0).unwrap();
+        sim
+    }
+
+    fn ldi(dest: u8, value: u16) -> ParsedInstruction {
+        ParsedInstruction::I { op: ImmOp::LDI, dest, immediate: Operand::Immediate(value), line: 0, source_file: None }
+    }
+
+    fn alu(op: AluOp, dest: u8, source: u8) -> ParsedInstruction {
+        ParsedInstruction::A { op, dest, source, line: 0, source_file: None }
+    }
+
+    fn halt() -> ParsedInstruction {
+        ParsedInstruction::X { op: XTypeOp::HALT, operand: XOperand::None, line: 0, source_file: None }
+    }
+
+    #[test]
+    fn add_sets_carry_and_zero() {
+        let mut sim = program(&[ldi(1, 0xFF), ldi(2, 0x01), alu(AluOp::ADD, 1, 2), halt()]);
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.cpu.reg(1), 0);
+        assert!(sim.cpu.sr.carry());
+        assert!(sim.cpu.sr.zero());
+    }
+
+    #[test]
+    fn cmp_clears_carry_on_unsigned_less_than() {
+        let mut sim = program(&[ldi(1, 0x03), ldi(2, 0x07), alu(AluOp::CMP, 1, 2), halt()]);
+        sim.run(100).unwrap();
+        assert_eq!(sim.cpu.reg(1), 0x03);
+        assert!(!sim.cpu.sr.carry());
+        assert!(sim.cpu.sr.negative());
+    }
+
+    #[test]
+    fn writes_to_r0_are_discarded() {
+        let mut sim = program(&[ldi(0, 0x42), halt()]);
+        sim.run(100).unwrap();
+        assert_eq!(sim.cpu.reg(0), 0);
+    }
+
+    #[test]
+    fn push_pop_round_trip() {
+        let push = ParsedInstruction::S { op: StackOp::PUSH, operand: 5, line: 0, source_file: None };
+        let pop = ParsedInstruction::S { op: StackOp::POP, operand: 6, line: 0, source_file: None };
+        let mut sim = program(&[ldi(12, 0xF0), ldi(5, 0x5A), push, pop, halt()]);
+        sim.run(100).unwrap();
+        assert_eq!(sim.cpu.reg(6), 0x5A);
+        assert_eq!(sim.cpu.sp(), 0xF000);
+    }
+
+    #[test]
+    fn relative_branch_is_taken_from_next_instruction() {
+        let skip = ParsedInstruction::BI {
+            absolute: false,
+            cond: BranchCond::Unconditional,
+            operand: Operand::Immediate(2),
+            line: 0,
+            source_file: None,
+        };
+        let mut sim = program(&[skip, ldi(1, 0x11), ldi(2, 0x22), halt()]);
+        sim.run(100).unwrap();
+        assert_eq!(sim.cpu.reg(1), 0);
+        assert_eq!(sim.cpu.reg(2), 0x22);
+    }
+
+    #[test]
+    fn register_branch_jumps_to_pair() {
+        let jump = ParsedInstruction::BR {
+            absolute: true,
+            cond: BranchCond::Unconditional,
+            source: RegisterPairIdentifier { high: 10, low: 11 },
+            line: 0,
+            source_file: None,
+        };
+        let mut sim = program(&[ldi(11, 0x08), jump, ldi(1, 0x11), halt(), halt()]);
+        sim.run(100).unwrap();
+        assert_eq!(sim.cpu.reg(1), 0);
+        assert_eq!(sim.cpu.pc(), 0x000A);
+    }
+
+    #[test]
+    fn runs_multi_file_test_program() {
+        let sources = [
+            ("main.asm", include_str!("../../../test/main.asm")),
+            ("math.asm", include_str!("../../../test/math.asm")),
+            ("io.asm", include_str!("../../../test/io.asm")),
+        ];
+        let objects: Vec<_> = sources
+            .iter()
+            .map(|(name, src)| (name.to_string(), atlas_assembler::assemble_source(src, name).unwrap()))
+            .collect();
+        let image = atlas_linker::link_objects(&objects).unwrap();
+
+        let mut sim = Simulator::new();
+        sim.load(&image.data, 0).unwrap();
+        assert_eq!(sim.run(10_000).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.cpu.reg(9), 6);
+        assert_eq!(sim.bus.peek(0x80), Some(0xAA));
+    }
+
+    #[test]
+    fn write_to_rom_is_a_bus_fault() {
+        use crate::bus::SystemBus;
+
+        let store = ParsedInstruction::M {
+            op: MemOp::ST,
+            dest: 1,
+            base: 2,
+            offset: MOffset::Offset8(0),
+            line: 0,
+            source_file: None,
+        };
+        let mut image = Vec::new();
+        for instr in [ldi(2, 0x10), store, halt()] {
+            image.extend(instr.encode().unwrap().to_be_bytes());
+        }
+        let mut bus = SystemBus::new();
+        bus.map_rom(0x0000, image).unwrap();
+        bus.map_ram(0x8000, 0x8000).unwrap();
+
+        let mut sim = Simulator::with_bus(Box::new(bus));
+        let err = sim.run(100).unwrap_err();
+        assert!(matches!(err, SimError::BusFault { pc: 0x0002, .. }));
+    }
+
+    #[test]
+    fn charges_memory_and_stack_penalties() {
+        let push = ParsedInstruction::S { op: StackOp::PUSH, operand: 5, line: 0, source_file: None };
+        let pop = ParsedInstruction::S { op: StackOp::POP, operand: 6, line: 0, source_file: None };
+        let mut sim = program(&[ldi(12, 0xF0), push, pop, halt()]);
+        sim.run(100).unwrap();
+        // ldi 1, push 1+1+1, pop 1+1+1, halt 1
+        assert_eq!(sim.cycles(), 8);
+        assert_eq!(sim.stats().stack_ops, 2);
+        assert_eq!(sim.stats().memory_reads, 1);
+        assert_eq!(sim.stats().memory_writes, 1);
+    }
+
+    #[test]
+    fn multiply_cost_grows_with_multiplier_bits() {
+        let multiply = |multiplier: u8| {
+            let driver = format!(
+                ".import multiply\n.import divide\n.import abs_value\n\
+                 .export mul_ret\n.export div_ret\n.export abs_ret\n\
+                 ldi sp, 0xF0\nldi r1, 0x03\nldi r2, 0x{:02x}\nbr multiply\n\
+                 mul_ret:\ndiv_ret:\nabs_ret:\nhalt\n",
+                multiplier
+            );
+            let objects = vec![
+                ("driver.asm".to_string(), atlas_assembler::assemble_source(&driver, "driver.asm").unwrap()),
+                (
+                    "math.asm".to_string(),
+                    atlas_assembler::assemble_source(include_str!("../../../test/math.asm"), "math.asm").unwrap(),
+                ),
+            ];
+            let image = atlas_linker::link_objects(&objects).unwrap();
+            let mut sim = Simulator::new();
+            sim.load(&image.data, 0).unwrap();
+            assert_eq!(sim.run(10_000).unwrap(), ExitReason::Halted);
+            assert_eq!(sim.cpu.reg(1), 3u8.wrapping_mul(multiplier));
+            sim.stats().clone()
+        };
+
+        let one = multiply(0x01);
+        let fifteen = multiply(0x0F);
+        assert!(fifteen.cycles > one.cycles);
+        assert!(fifteen.branches_taken > one.branches_taken);
+        assert!(fifteen.cpi() > 1.0);
+    }
+
+    #[test]
+    fn dcclean_makes_stores_visible_in_memory() {
+        let store = ParsedInstruction::M {
+            op: MemOp::ST,
+            dest: 1,
+            base: 2,
+            offset: MOffset::Offset8(0),
+            line: 0,
+            source_file: None,
+        };
+        let clean = ParsedInstruction::X { op: XTypeOp::DCCLEAN, operand: XOperand::None, line: 0, source_file: None };
+        let mut sim = program(&[ldi(1, 0x5A), ldi(2, 0x80), store.clone(), halt()]);
+        sim.caches = Some(CacheHierarchy::default());
+        sim.run(100).unwrap();
+        assert_eq!(sim.bus.peek(0x80), Some(0x00));
+
+        let mut sim = program(&[ldi(1, 0x5A), ldi(2, 0x80), store, clean, halt()]);
+        sim.caches = Some(CacheHierarchy::default());
+        sim.run(100).unwrap();
+        assert_eq!(sim.bus.peek(0x80), Some(0x5A));
+        let caches = sim.caches.as_ref().unwrap();
+        assert!(caches.l1i.stats.hits > 0);
+        assert_eq!(caches.l2.stats.writebacks, 1);
+    }
+
+    #[test]
+    fn mmu_translates_fetches_and_faults_on_unmapped_pages() {
+        use crate::exception::Exception;
+        use crate::mmu::{PTE_EXEC, PTE_VALID, PTE_WRITE};
+
+        let store = ParsedInstruction::M {
+            op: MemOp::ST,
+            dest: 1,
+            base: 2,
+            offset: MOffset::Offset8(0),
+            line: 0,
+            source_file: None,
+        };
+        let mut image = Vec::new();
+        for instr in [ldi(1, 0x42), ldi(2, 0x10), store, ldi(10, 0x04), ldi(11, 0x00), halt()] {
+            image.extend(instr.encode().unwrap().to_be_bytes());
+        }
+
+        // Virtual page 0 lives in frame 0x40 (physical 0x10000).
+        let mut sim = Simulator::with_bus(Box::new(Memory::with_size(0x20000)));
+        let pte: u32 = 0x40 | PTE_VALID | PTE_WRITE | PTE_EXEC;
+        sim.bus.load(0x1000, &pte.to_le_bytes()).unwrap();
+        sim.bus.load(0x10000, &image).unwrap();
+        let mut mmu = Mmu::new();
+        mmu.enabled = true;
+        mmu.ptbr = 0x1000;
+        sim.mmu = Some(mmu);
+
+        sim.run(100).unwrap();
+        assert_eq!(sim.bus.peek(0x10010), Some(0x42));
+
+        // Branching into the unmapped page 1 faults on the fetch.
+        let jump = ParsedInstruction::BR {
+            absolute: true,
+            cond: BranchCond::Unconditional,
+            source: RegisterPairIdentifier { high: 10, low: 11 },
+            line: 0,
+            source_file: None,
+        };
+        sim.bus.load(0x1000A, &jump.encode().unwrap().to_be_bytes()).unwrap();
+        sim.cpu.set_pc(0);
+        sim.halted = false;
+        let err = sim.run(100).unwrap_err();
+        assert!(matches!(
+            err,
+            SimError::Exception { exception: Exception::PageFault { address: 0x0400, access: Access::Fetch }, .. }
+        ));
+        assert_eq!(sim.mmu.as_ref().unwrap().fault_address(), 0x0400);
+    }
+
+    fn assemble_and_load(source: &str) -> Simulator {
+        let objects = vec![("test.asm".to_string(), atlas_assembler::assemble_source(source, "test.asm").unwrap())];
+        let image = atlas_linker::link_objects(&objects).unwrap();
+        let mut sim = Simulator::new();
+        sim.load(&image.data, 0).unwrap();
+        sim
+    }
+
+    #[test]
+    fn user_program_calls_kernel_through_syscalls() {
+        let mut sim = assemble_and_load(
+            "
+                ldi  r10, 0x01          ; vector table at 0x0100
+                ldi  r11, 0x00
+                ldi  r2, 0x02
+                ldi  r1, on_illegal
+                st   r1, [r2, tr]
+                ldi  r2, 0x04
+                ldi  r1, on_syscall
+                st   r1, [r2, tr]
+
+                ldi  r10, 0xFF          ; system registers at 0xff10
+                ldi  r2, 0x11
+                ldi  r1, 0x01
+                st   r1, [r2, tr]       ; VBR = 0x0100
+                ldi  r2, 0x12
+                ldi  r1, user
+                st   r1, [r2, tr]       ; EPC = user
+                ldi  r2, 0x14
+                ldi  r1, 0x80
+                st   r1, [r2, tr]       ; ESR = user mode
+                eret
+
+            user:
+                ldi  r1, 0x07
+                sysc 0x05
+                mov  r3, r1
+                halt                    ; privileged: traps
+
+            on_syscall:
+                ldi  r2, 0x16
+                ld   r4, [r2, tr]       ; EARG
+                add  r1, r4
+                eret
+
+            on_illegal:
+                ldi  r9, 0xEE
+                halt
+            ",
+        );
+        sim.system = Some(SystemControl::new());
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.cpu.reg(3), 0x0C);
+        assert_eq!(sim.cpu.reg(9), 0xEE);
+        assert!(!sim.cpu.sr.user());
+        assert_eq!(sim.system.unwrap().cause, Exception::VECTOR_ILLEGAL_INSTRUCTION);
+        assert_eq!(sim.stats().exceptions, 2);
+    }
+
+    #[test]
+    fn system_and_irq_registers_are_ram_without_the_devices() {
+        let mut sim = assemble_and_load(
+            "
+                ldi  r10, 0xFF
+                ldi  r2, 0x11
+                ldi  r1, 0x5A
+                st   r1, [r2, tr]
+                ldi  r2, 0x21
+                st   r1, [r2, tr]
+                halt
+            ",
+        );
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.bus.peek(0xFF11), Some(0x5A));
+        assert_eq!(sim.bus.peek(0xFF21), Some(0x5A));
+    }
+
+    #[test]
+    fn user_mode_cannot_touch_upper_memory() {
+        let mut sim = assemble_and_load(
+            "
+                ldi  r10, 0x80
+                ld   r1, [r0, tr]
+                halt
+            ",
+        );
+        sim.cpu.sr.set(StatusRegister::USER, true);
+        let err = sim.run(100).unwrap_err();
+        assert!(matches!(
+            err,
+            SimError::Exception { pc: 0x0002, exception: Exception::ProtectionFault { address: 0x8000, .. } }
+        ));
+    }
+
+    #[test]
+    fn tr_relative_load() {
+        let load = ParsedInstruction::M {
+            op: MemOp::LD,
+            dest: 1,
+            base: 2,
+            offset: MOffset::Offset8(0b1010),
+            line: 0,
+            source_file: None,
+        };
+        let mut sim = program(&[ldi(10, 0x12), ldi(11, 0x30), ldi(2, 0x04), load, halt()]);
+        sim.bus.write(0x1234, 0x99).unwrap();
+        sim.run(100).unwrap();
+        assert_eq!(sim.cpu.reg(1), 0x99);
+    }
+
+    #[test]
+    fn halt_waits_for_interrupt_and_dispatches_to_its_vector() {
+        let mut sim = assemble_and_load(
+            "
+                ldi  r10, 0x01          ; vector table at 0x0100
+                ldi  r11, 0x00
+                ldi  r2, 0x14           ; vector 10 = IRQ line 2
+                ldi  r1, on_irq
+                st   r1, [r2, tr]
+
+                ldi  r10, 0xFF
+                ldi  r2, 0x11
+                ldi  r1, 0x01
+                st   r1, [r2, tr]       ; VBR = 0x0100
+                ldi  r2, 0x21
+                ldi  r1, 0x04
+                st   r1, [r2, tr]       ; enable IRQ line 2
+                ldi  r2, 0x17
+                ldi  r1, 0x40
+                st   r1, [r2, tr]       ; SR = interrupts enabled
+                halt
+                ldi  r9, 0x42
+                halt
+
+            on_irq:
+                ldi  r2, 0x21
+                st   r0, [r2, tr]       ; mask the line again
+                ldi  r8, 0x01
+                eret
+            ",
+        );
+        let interrupts = InterruptController::new();
+        let irq = interrupts.line(2);
+        sim.system = Some(SystemControl::new());
+        sim.interrupts = Some(interrupts);
+        assert_eq!(sim.run(50).unwrap(), ExitReason::StepLimit);
+        assert!(sim.is_halted());
+        assert_eq!(sim.cpu.reg(9), 0);
+
+        irq.raise();
+        assert_eq!(sim.run(50).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.cpu.reg(8), 0x01);
+        assert_eq!(sim.cpu.reg(9), 0x42);
+        assert_eq!(sim.system.unwrap().cause, 10);
+        assert!(sim.cpu.sr.interrupts_enabled());
+    }
+
+    #[test]
+    fn trace_records_changed_registers_and_stores() {
+        let push = ParsedInstruction::S { op: StackOp::PUSH, operand: 5, line: 0, source_file: None };
+        let mut sim = program(&[ldi(12, 0xF0), ldi(5, 0x5A), push, halt()]);
+        let mut entries = Vec::new();
+        assert_eq!(sim.run_traced(100, |entry| entries.push(entry.clone())).unwrap(), ExitReason::Halted);
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!((entries[1].step, entries[1].pc), (1, 0x02));
+        assert_eq!(entries[1].registers, [(5, 0x5A)]);
+        assert_eq!(entries[2].registers, [(12, 0xEF), (13, 0xFE)]);
+        assert_eq!(entries[2].memory, [(0xEFFE, 0x5A)]);
+        assert!(entries[3].registers.is_empty() && entries[3].memory.is_empty());
+    }
+
+    #[test]
+    fn rewind_restores_registers_memory_and_cycles() {
+        let push = ParsedInstruction::S { op: StackOp::PUSH, operand: 5, line: 0, source_file: None };
+        let instructions = [
+            ldi(12, 0xF0),
+            ldi(5, 0x01),
+            push.clone(),
+            alu(AluOp::ADD, 5, 5),
+            push.clone(),
+            alu(AluOp::ADD, 5, 5),
+            push,
+            halt(),
+        ];
+        let mut sim = program(&instructions);
+        sim.record_history(2);
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.history().unwrap().position(), 8);
+
+        for position in [5, 3, 0] {
+            sim.rewind(position).unwrap();
+            let mut expected = program(&instructions);
+            expected.run(position).unwrap();
+            assert_eq!(sim.cpu.registers(), expected.cpu.registers());
+            assert_eq!(sim.cycles(), expected.cycles());
+            assert_eq!(sim.peek(0xEFFE), expected.peek(0xEFFE));
+            assert_eq!(sim.peek(0xEFFC), expected.peek(0xEFFC));
+        }
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.peek(0xEFFA), Some(0x04));
+    }
+
+    #[test]
+    fn history_drops_the_oldest_checkpoints_beyond_its_capacity() {
+        let push = ParsedInstruction::S { op: StackOp::PUSH, operand: 5, line: 0, source_file: None };
+        let instructions = [
+            ldi(12, 0xF0),
+            ldi(5, 0x01),
+            push.clone(),
+            alu(AluOp::ADD, 5, 5),
+            push.clone(),
+            alu(AluOp::ADD, 5, 5),
+            push,
+            halt(),
+        ];
+        let mut sim = program(&instructions);
+        sim.history = Some(History::new(2).with_capacity(3));
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        let history = sim.history().unwrap();
+        assert_eq!((history.start(), history.position()), (6, 8));
+        assert_eq!(history.pc_at(5), None);
+
+        sim.rewind(3).unwrap();
+        let mut expected = program(&instructions);
+        expected.run(6).unwrap();
+        assert_eq!(sim.history().unwrap().position(), 6);
+        assert_eq!(sim.cpu.registers(), expected.cpu.registers());
+        assert_eq!(sim.cycles(), expected.cycles());
+        assert_eq!(sim.peek(0xEFFC), expected.peek(0xEFFC));
+        assert_eq!(sim.peek(0xEFFA), expected.peek(0xEFFA));
+    }
+
+    #[test]
+    fn rewind_does_not_record_replayed_steps_again() {
+        let subi = ParsedInstruction::I { op: ImmOp::SUBI, dest: 1, immediate: Operand::Immediate(1), line: 0, source_file: None };
+        let bne = ParsedInstruction::BI {
+            absolute: false,
+            cond: BranchCond::NE,
+            operand: Operand::Immediate(0xFC),
+            line: 0,
+            source_file: None,
+        };
+        let mut sim = program(&[ldi(1, 0x04), subi, bne, halt()]);
+        sim.record_coverage();
+        sim.record_history(4);
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        let coverage = sim.coverage().unwrap().clone();
+
+        sim.rewind(7).unwrap();
+        assert_eq!(sim.coverage(), Some(&coverage));
+    }
+
+    #[test]
+    fn history_is_not_recorded_with_devices() {
+        use crate::bus::SystemBus;
+        use crate::timer::{TIMER_BASE, TIMER_SIZE};
+
+        let mut bus = SystemBus::new();
+        bus.map_ram(0, TIMER_BASE).unwrap();
+        bus.map_device(TIMER_BASE, TIMER_SIZE, Box::new(crate::timer::Timer::new())).unwrap();
+        let mut sim = Simulator::with_bus(Box::new(bus));
+        assert!(!sim.record_history(2));
+        assert!(sim.history().is_none());
+        assert!(Simulator::new().record_history(2));
+    }
+
+    #[test]
+    fn snapshot_resumes_in_a_fresh_simulator() {
+        let push = ParsedInstruction::S { op: StackOp::PUSH, operand: 5, line: 0, source_file: None };
+        let instructions = [ldi(12, 0xF0), ldi(5, 0x01), push.clone(), alu(AluOp::ADD, 5, 5), push, halt()];
+        let mut sim = program(&instructions);
+        sim.caches = Some(CacheHierarchy::default());
+        sim.run(3).unwrap();
+        let snapshot = sim.save_snapshot();
+
+        let mut resumed = Simulator::new();
+        resumed.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(resumed.save_snapshot(), snapshot);
+        assert_eq!(resumed.run(100).unwrap(), ExitReason::Halted);
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        assert_eq!(resumed.cpu.registers(), sim.cpu.registers());
+        assert_eq!(resumed.cycles(), sim.cycles());
+        assert_eq!(resumed.peek(0xEFFC), Some(0x02));
+    }
+
+    #[test]
+    fn rejected_snapshot_leaves_the_machine_untouched() {
+        let mut sim = program(&[ldi(1, 0x01), halt()]);
+        sim.run(100).unwrap();
+        let mut snapshot = sim.save_snapshot();
+        snapshot.devices.push(atlas_files::formats::snapshot::DeviceState {
+            name: "uart".to_string(),
+            base: 0xFF30,
+            data: Vec::new(),
+        });
+
+        let mut target = Simulator::new();
+        target.bus.write(0x1234, 0x99).unwrap();
+        let err = target.restore_snapshot(&snapshot).unwrap_err();
+        assert!(matches!(err, RestoreError::MissingDevice { base: 0xFF30, .. }));
+        assert_eq!(target.peek(0x1234), Some(0x99));
+        assert_eq!(target.peek(0x0000), Some(0x00));
+    }
+
+    #[test]
+    fn snapshot_address_space_follows_the_snapshot_mmu() {
+        let mut sim = Simulator::with_bus(Box::new(Memory::with_size(0x20000)));
+        sim.mmu = Some(Mmu::new());
+        sim.bus.write(0x12345, 0x77).unwrap();
+        let snapshot = sim.save_snapshot();
+
+        let mut resumed = Simulator::with_bus(Box::new(Memory::with_size(0x20000)));
+        resumed.restore_snapshot(&snapshot).unwrap();
+        assert!(resumed.mmu.is_some());
+        assert_eq!(resumed.bus.peek(0x12345), Some(0x77));
+    }
+
+    #[test]
+    fn coverage_counts_instructions_and_branch_outcomes() {
+        let subi = ParsedInstruction::I { op: ImmOp::SUBI, dest: 1, immediate: Operand::Immediate(1), line: 0, source_file: None };
+        let bne = ParsedInstruction::BI {
+            absolute: false,
+            cond: BranchCond::NE,
+            operand: Operand::Immediate(0xFC),
+            line: 0,
+            source_file: None,
+        };
+        let mut sim = program(&[ldi(1, 0x02), subi, bne, halt()]);
+        sim.record_coverage();
+        assert_eq!(sim.run(100).unwrap(), ExitReason::Halted);
+        let coverage = sim.coverage().unwrap();
+        assert_eq!(coverage.executed.get(&0x02), Some(&2));
+        assert_eq!(coverage.executed.get(&0x06), Some(&1));
+        assert_eq!(coverage.branches.get(&0x04), Some(&BranchCoverage { taken: 1, not_taken: 1 }));
+        assert_eq!(coverage.branches.len(), 1);
+    }
+}
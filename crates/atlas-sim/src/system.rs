@@ -0,0 +1,83 @@
+//! System control registers used for exception handling.
+//!
+//! The registers live in the untranslated system page at [`SYSTEM_BASE`] and
+//! are accessible from supervisor mode only. 16-bit registers are
+//! little-endian.
+//!
+//! | Offset | Register | Description                                      |
+//! |--------|----------|--------------------------------------------------|
+//! | 0–1    | `VBR`    | exception vector table base (0 = no table)       |
+//! | 2–3    | `EPC`    | PC saved on exception entry                      |
+//! | 4      | `ESR`    | SR saved on exception entry                      |
+//! | 5      | `CAUSE`  | vector number of the last exception              |
+//! | 6      | `EARG`   | syscall number of the last `sysc`                |
+//! | 7      | `SR`     | the live status register                         |
+//! | 8–9    | `EADDR`  | virtual address of the last memory fault         |
+//!
+//! The vector table holds one little-endian 16-bit handler address per
+//! vector number (see [`Exception::vector`]). A handler address of 0 means
+//! the exception is not handled by software and stops the simulator.
+//!
+//! [`Exception::vector`]: crate::exception::Exception::vector
+
+/// Address of the first system control register.
+pub const SYSTEM_BASE: u16 = 0xFF10;
+/// Number of system control register bytes.
+pub const SYSTEM_SIZE: u16 = 16;
+
+pub const SYS_VBR: u16 = 0;
+pub const SYS_EPC: u16 = 2;
+pub const SYS_ESR: u16 = 4;
+pub const SYS_CAUSE: u16 = 5;
+pub const SYS_EARG: u16 = 6;
+/// Handled by the simulator, which owns the status register.
+pub const SYS_SR: u16 = 7;
+pub const SYS_EADDR: u16 = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemControl {
+    pub vbr: u16,
+    pub epc: u16,
+    pub esr: u8,
+    pub cause: u8,
+    pub earg: u8,
+    pub eaddr: u16,
+}
+
+impl SystemControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_register(address: u16) -> bool {
+        (SYSTEM_BASE..SYSTEM_BASE + SYSTEM_SIZE).contains(&address)
+    }
+
+    pub fn read_register(&self, offset: u16) -> u8 {
+        match offset {
+            0 | 1 => (self.vbr >> (8 * (offset - SYS_VBR))) as u8,
+            2 | 3 => (self.epc >> (8 * (offset - SYS_EPC))) as u8,
+            SYS_ESR => self.esr,
+            SYS_CAUSE => self.cause,
+            SYS_EARG => self.earg,
+            8 | 9 => (self.eaddr >> (8 * (offset - SYS_EADDR))) as u8,
+            _ => 0,
+        }
+    }
+
+    pub fn write_register(&mut self, offset: u16, value: u8) {
+        let set_byte = |word: u16, index: u16| {
+            let shift = 8 * index;
+            (word & !(0xFF << shift)) | ((value as u16) << shift)
+        };
+        match offset {
+            0 | 1 => self.vbr = set_byte(self.vbr, offset - SYS_VBR),
+            2 | 3 => self.epc = set_byte(self.epc, offset - SYS_EPC),
+            SYS_ESR => self.esr = value,
+            SYS_CAUSE => self.cause = value,
+            SYS_EARG => self.earg = value,
+            8 | 9 => self.eaddr = set_byte(self.eaddr, offset - SYS_EADDR),
+            _ => {}
+        }
+    }
+}
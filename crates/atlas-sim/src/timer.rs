@@ -0,0 +1,195 @@
+//! Programmable interval timer.
+//!
+//! While enabled, `COUNT` advances by one every cycle. When it reaches
+//! `COMPARE` the timer sets `STATUS.EXPIRED` and, if enabled in `CTRL`,
+//! asserts its IRQ line until software acknowledges by writing 1 to
+//! `STATUS.EXPIRED`. A periodic timer then continues counting from `RELOAD`;
+//! a one-shot timer disables itself. Writing `CTRL` with bit 3 set loads
+//! `RELOAD` into `COUNT`; the bit reads as 0. 16-bit registers are
+//! little-endian.
+//!
+//! | Offset | Register  | Description                                       |
+//! |--------|-----------|---------------------------------------------------|
+//! | 0–1    | `COUNT`   | current count                                     |
+//! | 2–3    | `RELOAD`  | count loaded on expiry (and by `CTRL.RELOAD`)     |
+//! | 4–5    | `COMPARE` | count at which the timer expires                  |
+//! | 6      | `CTRL`    | bit 0 enable, 1 IRQ enable, 2 periodic, 3 reload  |
+//! | 7      | `STATUS`  | bit 0 expired (write 1 to clear)                  |
+
+use crate::bus::Device;
+use crate::interrupt::IrqLine;
+
+/// Where `atlas emu` maps the timer.
+pub const TIMER_BASE: u32 = 0xFF38;
+/// Number of timer register bytes.
+pub const TIMER_SIZE: u32 = 8;
+/// IRQ line `atlas emu` connects the timer to.
+pub const TIMER_IRQ_LINE: u8 = 0;
+
+pub const TIMER_COUNT: u32 = 0;
+pub const TIMER_RELOAD: u32 = 2;
+pub const TIMER_COMPARE: u32 = 4;
+pub const TIMER_CTRL: u32 = 6;
+pub const TIMER_STATUS: u32 = 7;
+
+pub const CTRL_ENABLE: u8 = 1 << 0;
+pub const CTRL_IRQ: u8 = 1 << 1;
+pub const CTRL_PERIODIC: u8 = 1 << 2;
+pub const CTRL_RELOAD: u8 = 1 << 3;
+
+pub const STATUS_EXPIRED: u8 = 1 << 0;
+
+#[derive(Debug, Clone, Default)]
+pub struct Timer {
+    pub count: u16,
+    pub reload: u16,
+    pub compare: u16,
+    ctrl: u8,
+    status: u8,
+    /// Number of times the timer has expired.
+    pub expirations: u64,
+    irq: Option<IrqLine>,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drive `irq` while expired and `CTRL_IRQ` is set.
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.ctrl & CTRL_ENABLE != 0
+    }
+
+    fn expire(&mut self) {
+        self.status |= STATUS_EXPIRED;
+        self.expirations += 1;
+        if self.ctrl & CTRL_PERIODIC != 0 {
+            self.count = self.reload;
+        } else {
+            self.ctrl &= !CTRL_ENABLE;
+        }
+    }
+
+    fn update_irq(&self) {
+        if let Some(irq) = &self.irq {
+            irq.set(self.ctrl & CTRL_IRQ != 0 && self.status & STATUS_EXPIRED != 0);
+        }
+    }
+}
+
+impl Device for Timer {
+    fn name(&self) -> &str {
+        "timer"
+    }
+
+    fn read(&mut self, offset: u32) -> u8 {
+        self.peek(offset)
+    }
+
+    fn write(&mut self, offset: u32, value: u8) {
+        let set_byte = |word: u16, index: u32| {
+            let shift = 8 * index;
+            (word & !(0xFF << shift)) | ((value as u16) << shift)
+        };
+        match offset {
+            0 | 1 => self.count = set_byte(self.count, offset - TIMER_COUNT),
+            2 | 3 => self.reload = set_byte(self.reload, offset - TIMER_RELOAD),
+            4 | 5 => self.compare = set_byte(self.compare, offset - TIMER_COMPARE),
+            TIMER_CTRL => {
+                if value & CTRL_RELOAD != 0 {
+                    self.count = self.reload;
+                }
+                self.ctrl = value & !CTRL_RELOAD;
+            }
+            TIMER_STATUS => self.status &= !value,
+            _ => {}
+        }
+        self.update_irq();
+    }
+
+    fn peek(&self, offset: u32) -> u8 {
+        match offset {
+            0 | 1 => (self.count >> (8 * (offset - TIMER_COUNT))) as u8,
+            2 | 3 => (self.reload >> (8 * (offset - TIMER_RELOAD))) as u8,
+            4 | 5 => (self.compare >> (8 * (offset - TIMER_COMPARE))) as u8,
+            TIMER_CTRL => self.ctrl,
+            TIMER_STATUS => self.status,
+            _ => 0,
+        }
+    }
+
+    /// COUNT, RELOAD, COMPARE, CTRL and STATUS as laid out in the register
+    /// map, followed by the expiration count.
+    fn save_state(&self) -> Vec<u8> {
+        let mut state: Vec<u8> = (0..TIMER_SIZE).map(|offset| self.peek(offset)).collect();
+        state.extend_from_slice(&self.expirations.to_le_bytes());
+        state
+    }
+
+    fn restore_state(&mut self, state: &[u8]) {
+        let Some((registers, expirations)) = state.split_first_chunk::<8>() else {
+            return;
+        };
+        let word = |offset: u32| u16::from_le_bytes([registers[offset as usize], registers[offset as usize + 1]]);
+        self.count = word(TIMER_COUNT);
+        self.reload = word(TIMER_RELOAD);
+        self.compare = word(TIMER_COMPARE);
+        self.ctrl = registers[TIMER_CTRL as usize];
+        self.status = registers[TIMER_STATUS as usize];
+        self.expirations = expirations.try_into().map(u64::from_le_bytes).unwrap_or(0);
+        self.update_irq();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            if !self.enabled() {
+                break;
+            }
+            self.count = self.count.wrapping_add(1);
+            if self.count == self.compare {
+                self.expire();
+            }
+        }
+        self.update_irq();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupt::InterruptController;
+
+    #[test]
+    fn periodic_timer_reloads_and_interrupts_until_acknowledged() {
+        let mut controller = InterruptController::new();
+        controller.enable = 0xFF;
+        let mut timer = Timer::new().with_irq(controller.line(TIMER_IRQ_LINE));
+        timer.write(TIMER_RELOAD, 2);
+        timer.write(TIMER_COMPARE, 5);
+        timer.write(TIMER_CTRL, CTRL_ENABLE | CTRL_IRQ | CTRL_PERIODIC | CTRL_RELOAD);
+        assert_eq!(timer.read(TIMER_COUNT), 2);
+
+        timer.tick(2);
+        assert_eq!(controller.active(), None);
+        timer.tick(1);
+        assert_eq!(controller.active(), Some(TIMER_IRQ_LINE));
+        assert_eq!(timer.read(TIMER_COUNT), 2);
+
+        timer.write(TIMER_STATUS, STATUS_EXPIRED);
+        assert_eq!(controller.active(), None);
+        timer.tick(7);
+        assert_eq!(timer.expirations, 3);
+        assert_eq!(timer.read(TIMER_COUNT), 3);
+
+        timer.write(TIMER_CTRL, CTRL_ENABLE | CTRL_IRQ);
+        timer.tick(5);
+        assert_eq!(timer.expirations, 4);
+        assert!(!timer.enabled());
+    }
+}
@@ -0,0 +1,93 @@
+//! Cycle timing model and run statistics.
+//!
+//! Every instruction costs a base number of cycles looked up by
+//! [`InstructionFormat`] and opcode. On top of that the simulator charges:
+//!
+//! - a penalty for every taken branch (the fetched instruction is discarded),
+//! - a penalty for every data memory access (fetches are part of the base cost),
+//! - a penalty for `push`/`pop`, which also update SP,
+//! - a penalty for entering an exception handler.
+
+use std::collections::{BTreeMap, HashMap};
+
+use atlas_isa::{InstructionFormat, XTypeOp};
+
+#[derive(Debug, Clone)]
+pub struct TimingModel {
+    costs: HashMap<(InstructionFormat, u8), u32>,
+    /// Cost of any instruction without an entry in the table.
+    pub default_cost: u32,
+    pub taken_branch_penalty: u32,
+    pub memory_access_penalty: u32,
+    pub stack_penalty: u32,
+    pub exception_penalty: u32,
+}
+
+impl Default for TimingModel {
+    fn default() -> Self {
+        let mut model = Self {
+            costs: HashMap::new(),
+            default_cost: 1,
+            taken_branch_penalty: 2,
+            memory_access_penalty: 1,
+            stack_penalty: 1,
+            exception_penalty: 4,
+        };
+        model.set_cost(InstructionFormat::X, XTypeOp::SYSC as u8, 4);
+        model.set_cost(InstructionFormat::X, XTypeOp::ERET as u8, 4);
+        model.set_cost(InstructionFormat::X, XTypeOp::ICINV as u8, 2);
+        model.set_cost(InstructionFormat::X, XTypeOp::DCINV as u8, 2);
+        model.set_cost(InstructionFormat::X, XTypeOp::DCCLEAN as u8, 2);
+        model.set_cost(InstructionFormat::X, XTypeOp::FLUSH as u8, 4);
+        model
+    }
+}
+
+impl TimingModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the base cost of one opcode.
+    pub fn set_cost(&mut self, format: InstructionFormat, opcode: u8, cycles: u32) {
+        self.costs.insert((format, opcode), cycles);
+    }
+
+    /// Base cost of an instruction, excluding penalties.
+    pub fn cost(&self, format: InstructionFormat, opcode: u8) -> u32 {
+        self.costs.get(&(format, opcode)).copied().unwrap_or(self.default_cost)
+    }
+}
+
+/// Instructions and cycles spent in one instruction format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FormatStats {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+/// Counters accumulated while the simulator runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunStats {
+    pub instructions: u64,
+    pub cycles: u64,
+    pub branches: u64,
+    pub branches_taken: u64,
+    pub memory_reads: u64,
+    pub memory_writes: u64,
+    pub stack_ops: u64,
+    /// Exceptions taken by a software handler.
+    pub exceptions: u64,
+    pub by_format: BTreeMap<InstructionFormat, FormatStats>,
+}
+
+impl RunStats {
+    /// Average cycles per instruction, or 0 if nothing has executed.
+    pub fn cpi(&self) -> f64 {
+        if self.instructions == 0 {
+            0.0
+        } else {
+            self.cycles as f64 / self.instructions as f64
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Per-instruction execution trace.
+//!
+//! [`Simulator::step_traced`] records what each instruction did: where it
+//! was, the word executed, the registers it changed, the resulting status
+//! register and the bytes it stored. Entries contain no timing information,
+//! so a trace only changes when a program's behaviour does.
+//!
+//! [`Simulator::step_traced`]: crate::simulator::Simulator::step_traced
+
+use crate::cpu::StatusRegister;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Number of instructions executed before this one.
+    pub step: u64,
+    pub pc: u16,
+    pub word: u16,
+    /// Registers that now hold a different value, in register order. The PC
+    /// pair is left out since it changes on every instruction.
+    pub registers: Vec<(u8, u8)>,
+    /// Status register after the instruction.
+    pub sr: StatusRegister,
+    /// Bytes stored by the instruction, as (virtual address, value).
+    pub memory: Vec<(u16, u8)>,
+    /// Vector of the exception or interrupt entered instead of, or by, the
+    /// instruction.
+    pub exception: Option<u8>,
+}
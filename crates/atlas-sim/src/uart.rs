@@ -0,0 +1,224 @@
+//! UART console device.
+//!
+//! A byte-wide serial port bridging the simulated program to the host:
+//! writes to `DATA` go to an output stream, reads from `DATA` consume the
+//! next input byte. Input comes either from the host's stdin, read on a
+//! background thread so polling never blocks the simulation, or from a
+//! buffer for headless runs and tests.
+//!
+//! | Offset | Register | Description                                       |
+//! |--------|----------|---------------------------------------------------|
+//! | 0      | `DATA`   | read: next input byte (0 if none), write: output  |
+//! | 1      | `STATUS` | bit 0 input ready, bit 1 output ready, bit 2 EOF  |
+//! | 2      | `CTRL`   | bit 0 raise the IRQ line while input is ready     |
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::bus::Device;
+use crate::interrupt::IrqLine;
+
+/// Where `atlas emu` maps the UART.
+pub const UART_BASE: u32 = 0xFF30;
+/// Number of UART register bytes.
+pub const UART_SIZE: u32 = 4;
+/// IRQ line `atlas emu` connects the UART to.
+pub const UART_IRQ_LINE: u8 = 1;
+
+pub const UART_DATA: u32 = 0;
+pub const UART_STATUS: u32 = 1;
+pub const UART_CTRL: u32 = 2;
+
+pub const STATUS_RX_READY: u8 = 1 << 0;
+pub const STATUS_TX_READY: u8 = 1 << 1;
+pub const STATUS_EOF: u8 = 1 << 2;
+
+pub const CTRL_RX_IRQ: u8 = 1 << 0;
+
+enum Input {
+    Buffer(VecDeque<u8>),
+    Host(Receiver<u8>),
+}
+
+pub struct Uart {
+    input: Input,
+    output: Box<dyn Write>,
+    /// Input byte waiting to be read from `DATA`.
+    rx: Option<u8>,
+    eof: bool,
+    ctrl: u8,
+    irq: Option<IrqLine>,
+}
+
+impl Uart {
+    /// A UART reading `input` and then reporting EOF, writing to `output`.
+    pub fn new(input: Vec<u8>, output: Box<dyn Write>) -> Self {
+        Self {
+            input: Input::Buffer(input.into()),
+            output,
+            rx: None,
+            eof: false,
+            ctrl: 0,
+            irq: None,
+        }
+    }
+
+    /// A UART connected to the host's stdin and stdout.
+    pub fn stdio() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for byte in std::io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        let mut uart = Self::new(Vec::new(), Box::new(std::io::stdout()));
+        uart.input = Input::Host(receiver);
+        uart
+    }
+
+    /// Send output to `output` instead.
+    pub fn with_output(mut self, output: Box<dyn Write>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Drive `irq` while input is ready and `CTRL_RX_IRQ` is set.
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    /// Latch the next input byte, if any has arrived.
+    fn poll(&mut self) {
+        if self.rx.is_none() && !self.eof {
+            match &mut self.input {
+                Input::Buffer(bytes) => {
+                    self.rx = bytes.pop_front();
+                    self.eof = self.rx.is_none();
+                }
+                Input::Host(receiver) => match receiver.try_recv() {
+                    Ok(byte) => self.rx = Some(byte),
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => self.eof = true,
+                },
+            }
+        }
+        if let Some(irq) = &self.irq {
+            irq.set(self.ctrl & CTRL_RX_IRQ != 0 && self.rx.is_some());
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = STATUS_TX_READY;
+        if self.rx.is_some() {
+            status |= STATUS_RX_READY;
+        } else if self.eof {
+            status |= STATUS_EOF;
+        }
+        status
+    }
+}
+
+impl Device for Uart {
+    fn name(&self) -> &str {
+        "uart"
+    }
+
+    fn read(&mut self, offset: u32) -> u8 {
+        self.poll();
+        let value = match offset {
+            UART_DATA => self.rx.take().unwrap_or(0),
+            UART_STATUS => self.status(),
+            UART_CTRL => self.ctrl,
+            _ => 0,
+        };
+        self.poll();
+        value
+    }
+
+    fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            // The device has no way to report a broken host stream.
+            UART_DATA => {
+                let _ = self.output.write_all(&[value]).and_then(|_| self.output.flush());
+            }
+            UART_CTRL => self.ctrl = value,
+            _ => {}
+        }
+        self.poll();
+    }
+
+    fn peek(&self, offset: u32) -> u8 {
+        match offset {
+            UART_DATA => self.rx.unwrap_or(0),
+            UART_STATUS => self.status(),
+            UART_CTRL => self.ctrl,
+            _ => 0,
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        self.poll();
+    }
+
+    /// Only `CTRL`: received input belongs to the host side and is not
+    /// carried over.
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.ctrl]
+    }
+
+    fn restore_state(&mut self, state: &[u8]) {
+        if let Some(&ctrl) = state.first() {
+            self.ctrl = ctrl;
+            self.poll();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::interrupt::InterruptController;
+
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn echoes_input_and_raises_irq_while_data_is_ready() {
+        let output = Captured::default();
+        let mut controller = InterruptController::new();
+        controller.enable = 0xFF;
+        let mut uart = Uart::new(b"ab".to_vec(), Box::new(output.clone()))
+            .with_irq(controller.line(UART_IRQ_LINE));
+
+        uart.write(UART_CTRL, CTRL_RX_IRQ);
+        assert_eq!(controller.active(), Some(UART_IRQ_LINE));
+        assert_eq!(uart.read(UART_STATUS), STATUS_RX_READY | STATUS_TX_READY);
+
+        while uart.read(UART_STATUS) & STATUS_RX_READY != 0 {
+            let byte = uart.read(UART_DATA);
+            uart.write(UART_DATA, byte.to_ascii_uppercase());
+        }
+        assert_eq!(uart.read(UART_STATUS), STATUS_TX_READY | STATUS_EOF);
+        assert_eq!(controller.active(), None);
+        assert_eq!(*output.0.borrow(), b"AB");
+    }
+}
@@ -0,0 +1,79 @@
+//! Memory watchpoints.
+//!
+//! A watchpoint covers a range of virtual addresses and fires on data
+//! reads, writes or both. Instruction fetches never trigger watchpoints.
+//! Hits are collected by the simulator as instructions execute and handed
+//! out with [`Simulator::take_watch_hits`].
+//!
+//! [`Simulator::take_watch_hits`]: crate::simulator::Simulator::take_watch_hits
+
+use std::fmt::Display;
+
+use crate::exception::Access;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Reads and writes.
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (WatchKind::Read, Access::Read) | (WatchKind::Write, Access::Write) | (WatchKind::Access, Access::Read | Access::Write)
+        )
+    }
+}
+
+impl Display for WatchKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Access => write!(f, "access"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Watchpoint {
+    pub start: u16,
+    /// Last address covered (inclusive), so a range can reach 0xffff.
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, kind: WatchKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    pub fn matches(&self, address: u16, access: Access) -> bool {
+        (self.start..=self.end).contains(&address) && self.kind.matches(access)
+    }
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{} 0x{:04x}", self.kind, self.start)
+        } else {
+            write!(f, "{} 0x{:04x}..0x{:04x}", self.kind, self.start, self.end as u32 + 1)
+        }
+    }
+}
+
+/// One access that hit a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the instruction making the access.
+    pub pc: u16,
+    pub address: u16,
+    pub access: Access,
+    /// Value before the access; equal to `new` for reads.
+    pub old: u8,
+    pub new: u8,
+}
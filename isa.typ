@@ -347,7 +347,7 @@ That results in the following list of instructions:
     fill: (x, y) => if y == 0 { gray.lighten(40%) },
     [*xop*], [*Mnemonic*],  [*Description*],            [*Operation*],
     [0x0],    [push rs],    [Push register onto stack], [SP = SP - 2; MEM[SP] = rs],
-    [0x1],    [pop rd],     [Pop register from stack],  [SP = SP + 2; rd = MEM[SP]],
+    [0x1],    [pop rd],     [Pop register from stack],  [rd = MEM[SP]; SP = SP + 2],
     [0x2],    [subsp imm8], [allocate stack],           [SP = SP - imm8],
     [0x3],    [subsp rs],   [allocate stack],           [SP = SP - register],
     [0x4],    [addsp imm8], [deallocate stack],         [SP = SP + imm8],
@@ -479,7 +479,7 @@ This section provides a summary of all instructions defined in the Atlas-8 ISA.
   [bov], [BR-type], [Branch if Overflow], [if V=1 then PC = PC + rs],
   // S-type Instructions
   [push], [S-type], [Push register onto stack], [SP = SP - 2; MEM[SP] = rs],
-  [pop], [S-type], [Pop register from stack], [SP = SP + 2; rd = MEM[SP]],
+  [pop], [S-type], [Pop register from stack], [rd = MEM[SP]; SP = SP + 2],
   [subsp], [S-type], [Allocate stack], [SP = SP - imm8 or SP = SP - register],
   [addsp], [S-type], [Deallocate stack], [SP = SP + imm8 or SP = SP + register],
   // P-type Instructions
@@ -22,6 +22,23 @@
 ; --- Public entry point -----------------------------------------------------
 .export main
 
+; --- Checked by `atlas test` ------------------------------------------------
+; link math.asm io.asm
+; expect r9 == 6 at halt
+; expect mem[0x80] == 0xaa
+; expect steps <= 200
+
+; ============================================================================
+; Macros
+; ============================================================================
+
+; call ROUTINE, RET — branch to a library routine, which returns by branching
+; to the exported label RET placed right after the call
+.macro call routine, ret
+    br   \routine
+\ret:
+.endm
+
 ; ============================================================================
 ; Constants
 ; ============================================================================
@@ -93,8 +110,7 @@ test_shift:
 test_mul:
     ldi  r1, 0x06
     ldi  r2, 0x07
-    br   multiply             ; r1 = r1 * r2
-mul_ret:
+    call multiply, mul_ret    ; r1 = r1 * r2
     ldi  r5, 0x2A
     cmp  r1, r5
     bne  test_mem
@@ -0,0 +1,81 @@
+; ============================================================================
+; timer.asm — Periodic timer interrupts for `atlas emu`
+;
+; Programs the timer to expire every 0x40 cycles and sleeps in `halt`
+; between interrupts. The handler counts ticks in r9 and prints a '.' per
+; tick; after five ticks the main loop prints a newline and stops.
+;
+; Exercises: exception vector table, interrupt controller, timer and UART
+;            registers, halt-until-interrupt, eret.
+; ============================================================================
+
+SYS_HI:       .imm 0xFF     ; system page
+VBR_HI:       .imm 0x11     ; VBR high byte (low byte is 0)
+SR:           .imm 0x17     ; live status register
+IRQ_ENABLE:   .imm 0x21
+UART_DATA:    .imm 0x30
+TIMER_RELOAD: .imm 0x3A
+TIMER_CMP:    .imm 0x3C
+TIMER_CTRL:   .imm 0x3E
+TIMER_STATUS: .imm 0x3F
+VECTORS:      .imm 0x01     ; vector table at 0x0100
+IRQ0_SLOT:    .imm 0x10     ; vector 8 = IRQ line 0
+TICKS:        .imm 0x05
+
+main:
+    ldi  r9, 0x00
+
+    ; ------ Install the timer handler ---------------------------------------
+    ldi  r10, VECTORS
+    ldi  r11, 0x00
+    ldi  r2, IRQ0_SLOT
+    ldi  r1, on_timer
+    st   r1, [r2, tr]
+
+    ldi  r10, SYS_HI
+    ldi  r2, VBR_HI
+    ldi  r1, VECTORS
+    st   r1, [r2, tr]
+
+    ; ------ Timer: periodic, every 0x40 cycles ------------------------------
+    ldi  r2, TIMER_RELOAD
+    st   r0, [r2, tr]
+    ldi  r2, TIMER_CMP
+    ldi  r1, 0x40
+    st   r1, [r2, tr]
+    ldi  r2, TIMER_CTRL
+    ldi  r1, 0x0F             ; enable | irq | periodic | reload
+    st   r1, [r2, tr]
+
+    ldi  r2, IRQ_ENABLE
+    ldi  r1, 0x01             ; IRQ line 0
+    st   r1, [r2, tr]
+    ldi  r2, SR
+    ldi  r1, 0x40             ; interrupts on
+    st   r1, [r2, tr]
+
+wait:
+    halt
+    ldi  r5, TICKS
+    cmp  r9, r5
+    bne  wait
+
+    ldi  r2, IRQ_ENABLE
+    st   r0, [r2, tr]         ; no more wake-ups
+    ldi  r2, UART_DATA
+    ldi  r1, 0x0A
+    st   r1, [r2, tr]
+    halt
+
+; ============================================================================
+; on_timer — acknowledge the timer, count the tick, print '.'
+; ============================================================================
+on_timer:
+    ldi  r2, TIMER_STATUS
+    ldi  r1, 0x01
+    st   r1, [r2, tr]
+    inc  r9
+    ldi  r2, UART_DATA
+    ldi  r1, 0x2E
+    st   r1, [r2, tr]
+    eret
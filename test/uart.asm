@@ -0,0 +1,52 @@
+; ============================================================================
+; uart.asm — UART console demo for `atlas emu`
+;
+; Prints "ok" and then echoes its input in upper case until EOF.
+;
+; Exercises: memory-mapped device registers, polling loops.
+; ============================================================================
+
+UART_HI:      .imm 0xFF     ; UART registers at 0xff30
+UART_LO:      .imm 0x30
+DATA:         .imm 0x00
+STATUS:       .imm 0x01
+RX_READY:     .imm 0x01
+EOF:          .imm 0x04
+
+main:
+    ldi  r10, UART_HI
+    ldi  r11, UART_LO
+    ldi  r2, DATA
+    ldi  r3, STATUS
+
+    ldi  r1, 0x6F             ; 'o'
+    st   r1, [r2, tr]
+    ldi  r1, 0x6B             ; 'k'
+    st   r1, [r2, tr]
+    ldi  r1, 0x0A             ; '\n'
+    st   r1, [r2, tr]
+
+poll:
+    ld   r4, [r3, tr]
+    ldi  r5, EOF
+    and  r5, r4
+    bne  done                 ; input exhausted
+    ldi  r5, RX_READY
+    and  r5, r4
+    beq  poll                 ; nothing yet
+
+    ld   r1, [r2, tr]
+    ldi  r5, 0x61             ; 'a'
+    cmp  r1, r5
+    bcc  echo                 ; below 'a'
+    ldi  r5, 0x7B             ; 'z' + 1
+    cmp  r1, r5
+    bcs  echo                 ; above 'z'
+    ldi  r5, 0x20
+    sub  r1, r5               ; to upper case
+echo:
+    st   r1, [r2, tr]
+    br   poll
+
+done:
+    halt